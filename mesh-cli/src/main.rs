@@ -1,144 +1,989 @@
 use std::{
-    collections::HashSet,
+    io::{IsTerminal, Write},
     path::{Path, PathBuf},
+    process::ExitCode,
 };
 
-use anyhow::Context;
-use clap::Parser;
-use image::{
-    GenericImageView, ImageEncoder,
-    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+use clap::{Parser, Subcommand};
+use mesh_core::{
+    CancellationToken, DirChange, DirError, GcReport, LibraryError, LibraryRegistry, MediaType,
+    MeshCache, MeshConfig, MeshDirs, RootChange, ScanErrorRecord, ScanFilter, SourceStamp, TagEdit,
+    ThumbnailBackend, ThumbnailJob, ThumbnailProgress, collect_files,
 };
-use mesh_core::MeshConfig;
-use walkdir::WalkDir;
+
+/// 命令失败时的退出码，供 shell 脚本与 cron 任务判断结果
+///
+/// 成功时为 0；命令行参数无法解析时由 clap 以 2 退出。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Failure {
+    /// 数据库、文件读写等错误，或要查看的照片不在照片库中
+    Error = 1,
+    /// 参数在解析之后才发现无效，例如路径不存在，与 clap 的用法错误相同
+    Usage = 2,
+    /// 部分文件处理失败，其余文件已经完成
+    Partial = 3,
+}
+
+type CliResult = Result<(), Failure>;
 
 #[derive(Debug, Parser)]
+#[command(after_help = "退出码：0 成功，1 出错，2 参数无效，3 部分文件处理失败")]
 struct Cli {
-    files: Vec<PathBuf>,
-    #[arg(short)]
-    tag: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+    /// 数据目录，默认依次使用 MESH_HOME 环境变量、程序旁的 mesh-data 目录与用户目录
+    #[arg(long, global = true)]
+    home: Option<PathBuf>,
+    /// 使用的照片库，默认为通过 `libraries use` 选择的照片库
+    #[arg(long, global = true)]
+    library: Option<String>,
 }
 
-fn collect_files(
-    album_dirs: &[PathBuf],
-    excluded_dirs: &[PathBuf],
-    files: Vec<PathBuf>,
-) -> Vec<PathBuf> {
-    let ex: HashSet<_> = excluded_dirs.iter().collect();
-    let al: HashSet<_> = album_dirs.iter().collect();
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// 扫描相册目录（默认全部），收录新增或修改的文件并生成缩略图，清理已删除的照片
+    Scan { paths: Vec<PathBuf> },
+    /// 为照片库中的照片补齐缩略图（默认全部），不收录新文件
+    Thumbs {
+        paths: Vec<PathBuf>,
+        /// 同时重新处理此前失败且未修改的文件
+        #[arg(long)]
+        retry: bool,
+    },
+    /// 查看与修改照片的标签
+    Tag {
+        #[command(subcommand)]
+        command: TagCommand,
+    },
+    /// 按文件名搜索照片库，每行输出一个路径
+    Search {
+        #[arg(required = true)]
+        query: Vec<String>,
+    },
+    /// 显示照片的详细信息
+    Info {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// 显示照片库的统计信息
+    Stats,
+    /// 管理照片库
+    Libraries {
+        #[command(subcommand)]
+        command: Option<LibrariesCommand>,
+    },
+    /// 管理相册目录与排除目录
+    Albums {
+        #[command(subcommand)]
+        command: Option<AlbumsCommand>,
+    },
+    /// 管理配置文件
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// 管理缩略图缓存
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// 列出生成缩略图失败的文件
+    Errors {
+        #[command(subcommand)]
+        command: Option<ErrorsCommand>,
+    },
+    /// 照片文件夹移动后，把 old-root 下的相册目录改到 new-root 下，保留标签与缩略图
+    Relocate {
+        old_root: PathBuf,
+        new_root: PathBuf,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum TagCommand {
+    /// 列出所有标签及其照片数
+    Ls,
+    /// 列出照片的标签
+    Show {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// 为照片添加标签，标签路径以 `/` 分隔层级，如 `旅行/2024`
+    Add {
+        tag: String,
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// 从照片上移除标签
+    Remove {
+        tag: String,
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// 将照片的标签替换为这一个
+    Set {
+        tag: String,
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum LibrariesCommand {
+    /// 列出所有照片库（默认），当前照片库以 * 标记
+    List,
+    /// 创建照片库
+    Create { name: String },
+    /// 删除照片库及其数据库与缩略图，相册中的照片不受影响
+    Remove { name: String },
+    /// 设置默认使用的照片库
+    Use { name: String },
+}
 
-    let extensions = ["jpg", "jpeg", "png"];
-    // "gif", "bmp", "webp", // 图像
-    // "mp4", "mkv", "avi", "mov", "webm", // 视频
+#[derive(Debug, Subcommand)]
+enum AlbumsCommand {
+    /// 列出相册目录与排除目录（默认）
+    List,
+    /// 添加相册目录并为其中的照片生成缩略图
+    Add { path: PathBuf },
+    /// 移除相册目录，并清理其中照片的记录与缩略图
+    Remove { path: PathBuf },
+    /// 调整相册目录的顺序
+    Move { from: usize, to: usize },
+    /// 排除相册中的子目录，并清理其中照片的记录与缩略图
+    Exclude { path: PathBuf },
+    /// 取消排除子目录，并为其中的照片生成缩略图
+    Include { path: PathBuf },
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommand {
+    /// 显示配置文件的路径
+    Path,
+    /// 备份当前配置文件并恢复默认配置
+    Reset,
+}
+
+#[derive(Debug, Subcommand)]
+enum CacheCommand {
+    /// 清理已删除照片的缩略图，并按 LRU 淘汰超出上限的部分
+    Gc,
+    /// 显示缓存占用情况
+    Stats,
+    /// 把缩略图迁移到另一种存储后端（directory 或 sqlite）并更新配置
+    Migrate { backend: ThumbnailBackend },
+}
+
+#[derive(Debug, Subcommand)]
+enum ErrorsCommand {
+    /// 列出失败的文件（默认）
+    List,
+    /// 重新处理所有失败的文件
+    Retry,
+    /// 列出扩展名与内容不符的文件
+    Mismatches,
+}
+
+/// 在 stderr 上绘制单行进度条
+fn draw_progress(progress: &ThumbnailProgress) {
+    const WIDTH: usize = 30;
+    let filled = WIDTH * progress.done / progress.total.max(1);
+    let eta = progress
+        .eta
+        .map(|eta| {
+            let secs = eta.as_secs();
+            format!("{:02}:{:02}", secs / 60, secs % 60)
+        })
+        .unwrap_or_else(|| "--:--".to_owned());
+    let name = progress
+        .current
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
 
-    fn under_any<'a>(p: &Path, roots: &HashSet<&'a PathBuf>) -> bool {
-        roots.iter().any(|r| p.starts_with(r))
+    let mut stderr = std::io::stderr().lock();
+    let _ = write!(
+        stderr,
+        "\r\x1b[2K[{}{}] {}/{} ETA {} {}",
+        "#".repeat(filled),
+        " ".repeat(WIDTH - filled),
+        progress.done,
+        progress.total,
+        eta,
+        name
+    );
+    if progress.done == progress.total {
+        let _ = writeln!(stderr);
     }
+    let _ = stderr.flush();
+}
 
-    files
-        .into_iter()
-        .filter(|p| !ex.iter().any(|e| p.starts_with(e)))
-        .filter(|p| p.is_file() || p.is_dir())
-        .flat_map(|p| {
-            WalkDir::new(&p)
+fn run_libraries_command(command: LibrariesCommand, libraries: &mut LibraryRegistry) -> CliResult {
+    let result = match command {
+        LibrariesCommand::List => {
+            let current = libraries.current();
+            for name in libraries.names() {
+                let marker = if name == current { "*" } else { " " };
+                match libraries.dirs(name) {
+                    Ok(dirs) => println!("{} {}\t{}", marker, name, dirs.config_dir().display()),
+                    Err(e) => log::error!("{}", e),
+                }
+            }
+            return Ok(());
+        }
+        LibrariesCommand::Create { name } => libraries
+            .create(&name)
+            .map(|_| println!("✅ created library {}", name)),
+        LibrariesCommand::Remove { name } => libraries
+            .remove(&name)
+            .map(|_| println!("✅ removed library {}", name)),
+        LibrariesCommand::Use { name } => libraries
+            .set_current(&name)
+            .map(|_| println!("✅ using library {}", name)),
+    };
+    result.map_err(|e| {
+        eprintln!("❌ {}", e);
+        match e {
+            LibraryError::Io(..) | LibraryError::Parse(..) => Failure::Error,
+            _ => Failure::Usage,
+        }
+    })
+}
+
+fn run_albums_command(
+    command: AlbumsCommand,
+    config: &mut MeshConfig,
+    cache: &MeshCache,
+) -> CliResult {
+    let result = match command {
+        AlbumsCommand::List => {
+            let offline: Vec<PathBuf> = cache
+                .database()
+                .roots()
+                .map_err(|e| log::error!("{:?}", e))
+                .unwrap_or_default()
                 .into_iter()
-                .filter_map(|e| e.ok())
-                .filter(|e| e.file_type().is_file())
-                .map(|e| e.path().to_path_buf())
-                .filter(|f| {
-                    under_any(f, &al)
-                        && f.extension()
-                            .and_then(|s| s.to_str())
-                            .map(|e| extensions.contains(&e.to_lowercase().as_str()))
-                            .unwrap_or(false)
-                })
+                .filter(|root| !root.online)
+                .map(|root| root.path)
+                .collect();
+            for (index, dir) in config.album_dirs().iter().enumerate() {
+                if offline.contains(dir) {
+                    println!("album\t{}\t{}\toffline", index, dir.display());
+                } else {
+                    println!("album\t{}\t{}", index, dir.display());
+                }
+            }
+            for (index, dir) in config.excluded_dirs().iter().enumerate() {
+                println!("excluded\t{}\t{}", index, dir.display());
+            }
+            return Ok(());
+        }
+        AlbumsCommand::Add { path } => config.add_album_dir(path),
+        AlbumsCommand::Remove { path } => config.remove_album_dir(path),
+        AlbumsCommand::Move { from, to } => config.move_album_dir(from, to),
+        AlbumsCommand::Exclude { path } => config.add_excluded_dir(path),
+        AlbumsCommand::Include { path } => config.remove_excluded_dir(path),
+    };
+
+    match result {
+        Ok(change) => {
+            // 先登记新的相册目录并写入卷标识，卷卸载或换了挂载点之后才能找回
+            if change.index_root().is_some() {
+                reconcile_roots(config, cache);
+            }
+            apply_dir_change(&change, config, cache)
+        }
+        Err(e @ DirError::Save(_)) => {
+            log::error!("{}", e);
+            Err(Failure::Error)
+        }
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            Err(Failure::Usage)
+        }
+    }
+}
+
+/// 核对相册目录是否在线，卷挂载到新位置时同步更新配置
+fn reconcile_roots(config: &mut MeshConfig, cache: &MeshCache) {
+    let changes = match cache.reconcile_roots(config.album_dirs()) {
+        Ok(changes) => changes,
+        Err(e) => {
+            log::error!("{:?}", e);
+            return;
+        }
+    };
+
+    for change in changes {
+        match change {
+            RootChange::Offline(dir) => {
+                println!("album offline: {} (photos kept)", dir.display())
+            }
+            RootChange::Online(dir) => println!("album online: {}", dir.display()),
+            RootChange::Moved { from, to } => {
+                println!("album moved: {} -> {}", from.display(), to.display());
+                if let Err(e) = config.relocate_album_dir(&from, &to) {
+                    eprintln!("❌ {}", e);
+                }
+            }
+        }
+    }
+}
+
+fn run_relocate_command(
+    old_root: PathBuf,
+    new_root: PathBuf,
+    config: &mut MeshConfig,
+    cache: &MeshCache,
+) -> CliResult {
+    let new_root = match new_root.metadata() {
+        Ok(metadata) if metadata.is_dir() => resolve_path(new_root),
+        Ok(_) => {
+            eprintln!("❌ not a directory: {}", new_root.display());
+            return Err(Failure::Usage);
+        }
+        Err(e) => {
+            eprintln!("❌ {}: {}", new_root.display(), e);
+            return Err(Failure::Usage);
+        }
+    };
+    // 旧目录通常已不存在
+    let old_root = resolve_path(old_root);
+
+    let report = match cache.relocate(&old_root, &new_root) {
+        Ok(report) => report,
+        Err(e) => {
+            log::error!("{:?}", e);
+            return Err(Failure::Error);
+        }
+    };
+    match config.relocate_album_dir(&old_root, &new_root) {
+        Ok(_) => {}
+        Err(DirError::NotFound(_)) if report.roots > 0 => {}
+        Err(e @ DirError::Save(_)) => {
+            log::error!("{}", e);
+            return Err(Failure::Error);
+        }
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            return Err(Failure::Usage);
+        }
+    }
+
+    println!(
+        "relocated {} album directories, kept {} photos and {} thumbnails",
+        report.roots, report.photos, report.thumbnails
+    );
+    Ok(())
+}
+
+/// 索引新增的目录树，或清理被移除、被排除的目录树
+fn apply_dir_change(change: &DirChange, config: &MeshConfig, cache: &MeshCache) -> CliResult {
+    if let Some(root) = change.prune_root() {
+        match cache.prune_dir(root) {
+            Ok(report) => println!(
+                "removed {} thumbnails under {}, reclaimed {}",
+                report.removed,
+                root.display(),
+                format_bytes(report.reclaimed_bytes)
+            ),
+            Err(e) => {
+                log::error!("{:?}", e);
+                return Err(Failure::Error);
+            }
+        }
+    }
+
+    if let Some(root) = change.index_root() {
+        let files = collect_files(&ScanFilter::new(config), vec![root.to_path_buf()]);
+        return generate_thumbnails(files, config, cache, false);
+    }
+    Ok(())
+}
+
+fn run_config_command(
+    command: ConfigCommand,
+    dirs: &MeshDirs,
+    config: &mut MeshConfig,
+) -> CliResult {
+    match command {
+        ConfigCommand::Path => println!("{}", config.path().display()),
+        ConfigCommand::Reset => match MeshConfig::reset(dirs) {
+            Ok(reset) => {
+                *config = reset;
+                println!("config reset to defaults");
+            }
+            Err(e) => {
+                log::error!("{:?}", e);
+                return Err(Failure::Error);
+            }
+        },
+    }
+    Ok(())
+}
+
+fn run_cache_command(
+    command: CacheCommand,
+    config: &mut MeshConfig,
+    cache: &MeshCache,
+) -> CliResult {
+    match command {
+        CacheCommand::Gc => {
+            let mut report = GcReport::default();
+            let result = cache.gc().map(|gc_report| report.merge(gc_report));
+            report.merge(cache.enforce_limit(config.thumbnail_cache_limit()));
+
+            println!(
+                "removed {} thumbnails, reclaimed {}",
+                report.removed,
+                format_bytes(report.reclaimed_bytes)
+            );
+            if let Err(e) = result {
+                log::error!("{:?}", e);
+                return Err(Failure::Error);
+            }
+        }
+        CacheCommand::Stats => {
+            let stats = cache.thumbnail().stats();
+            println!("thumbnails: {}", stats.count);
+            println!(
+                "size: {} / {}",
+                format_bytes(stats.bytes),
+                format_bytes(config.thumbnail_cache_limit())
+            );
+            println!("backend: {:?}", config.thumbnail_store());
+            println!("path: {:?}", cache.thumbnail().path());
+        }
+        CacheCommand::Migrate { backend } => {
+            // 配置写入成功后才删除旧后端中的缩略图
+            let previous = config.thumbnail_store();
+            let migrated = cache.migrate_thumbnails(backend, || {
+                config.set_thumbnail_store(backend);
+                config
+                    .try_save()
+                    .inspect_err(|_| config.set_thumbnail_store(previous))
+            });
+            match migrated {
+                Ok(count) => println!("migrated {} thumbnails to {:?}", count, backend),
+                Err(e) => {
+                    log::error!("Failed to migrate thumbnails: {}", e);
+                    return Err(Failure::Error);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 照片库中的条目数、标签数与缩略图占用
+fn run_stats_command(config: &MeshConfig, cache: &MeshCache) -> CliResult {
+    let database = cache.database();
+    let counts = (|| {
+        let roots = database.roots()?;
+        let offline_albums = config
+            .album_dirs()
+            .iter()
+            .filter(|dir| roots.iter().any(|root| &root.path == *dir && !root.online))
+            .count();
+        Ok::<_, anyhow::Error>((
+            offline_albums,
+            database.media_count(MediaType::Photo)?,
+            database.media_count(MediaType::Video)?,
+            database.offline_count()?,
+            database.tag_count()?,
+            database.scan_errors()?.len(),
+        ))
+    })();
+    let (offline_albums, photos, videos, offline, tags, failed) = match counts {
+        Ok(counts) => counts,
+        Err(e) => {
+            log::error!("{:?}", e);
+            return Err(Failure::Error);
+        }
+    };
+
+    println!(
+        "albums: {} ({} offline)",
+        config.album_dirs().len(),
+        offline_albums
+    );
+    println!("photos: {}", photos);
+    println!("videos: {}", videos);
+    println!("offline: {}", offline);
+    println!("tags: {}", tags);
+    println!("failed: {}", failed);
+    let stats = cache.thumbnail().stats();
+    println!(
+        "thumbnails: {} ({})",
+        stats.count,
+        format_bytes(stats.bytes)
+    );
+    Ok(())
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn run_errors_command(command: ErrorsCommand, config: &MeshConfig, cache: &MeshCache) -> CliResult {
+    match command {
+        ErrorsCommand::List => {
+            let errors = scan_errors(cache)?;
+            let mut stdout = std::io::stdout().lock();
+            for error in &errors {
+                let _ = write!(stdout, "{}\t", error.kind)
+                    .and_then(|_| write_path(&mut stdout, &error.path))
+                    .and_then(|_| {
+                        writeln!(
+                            stdout,
+                            "\tretries: {}\t{}",
+                            error.retry_count, error.message
+                        )
+                    });
+            }
+            // stdout 只输出记录，统计写到 stderr
+            eprintln!("{} files failed", errors.len());
+        }
+        ErrorsCommand::Retry => {
+            let errors = scan_errors(cache)?;
+            let (files, missing): (Vec<_>, Vec<_>) = errors
+                .into_iter()
+                .map(|error| error.path)
+                .partition(|path| path.exists());
+
+            // 已删除的文件不再重试
+            for path in missing {
+                if let Err(e) = cache.database().clear_scan_error(&path) {
+                    log::warn!("{:?}", e);
+                }
+            }
+            return generate_thumbnails(files, config, cache, true);
+        }
+        ErrorsCommand::Mismatches => match cache.database().extension_mismatches() {
+            Ok(mismatches) => {
+                let mut stdout = std::io::stdout().lock();
+                for (path, mime_type) in &mismatches {
+                    let _ = write!(stdout, "{}\t", mime_type)
+                        .and_then(|_| write_path(&mut stdout, path))
+                        .and_then(|_| writeln!(stdout));
+                }
+                eprintln!("{} files have a mismatched extension", mismatches.len());
+            }
+            Err(e) => {
+                log::error!("{:?}", e);
+                return Err(Failure::Error);
+            }
+        },
+    }
+    Ok(())
+}
+
+/// 按原始字节输出路径，脚本可以原样读回非 UTF-8 的文件名
+fn write_path(out: &mut impl Write, path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        out.write_all(path.as_os_str().as_bytes())
+    }
+    #[cfg(not(unix))]
+    write!(out, "{}", path.display())
+}
+
+fn scan_errors(cache: &MeshCache) -> Result<Vec<ScanErrorRecord>, Failure> {
+    cache.database().scan_errors().map_err(|e| {
+        log::error!("{:?}", e);
+        Failure::Error
+    })
+}
+
+fn generate_thumbnails(
+    files: Vec<PathBuf>,
+    config: &MeshConfig,
+    cache: &MeshCache,
+    retry_failed: bool,
+) -> CliResult {
+    let mut job = ThumbnailJob::new(files)
+        .retry_failed(retry_failed)
+        .decode_in_worker(config.decode_worker_timeout());
+    if std::io::stderr().is_terminal() {
+        job = job.on_progress(draw_progress);
+    }
+    let result = match job.run(cache, &CancellationToken::new()) {
+        Ok(summary) => {
+            println!(
+                "✅ thumbnails: {} generated, {} skipped, {} failed",
+                summary.generated, summary.skipped, summary.failed
+            );
+            if summary.failed > 0 {
+                Err(Failure::Partial)
+            } else {
+                Ok(())
+            }
+        }
+        Err(e) => {
+            log::error!("{:?}", e);
+            Err(Failure::Error)
+        }
+    };
+
+    let report = cache.enforce_limit(config.thumbnail_cache_limit());
+    if report.removed > 0 {
+        log::info!(
+            "Evicted {} thumbnails ({}) over cache limit",
+            report.removed,
+            format_bytes(report.reclaimed_bytes)
+        );
+    }
+    result
+}
+
+/// 把命令行给出的路径转换为绝对路径，以便与照片库中的路径比较
+///
+/// 照片库中的路径都经过规范化；路径不存在时（例如位于离线的相册中）无法规范化，
+/// 只转换为绝对路径。
+fn resolve_path(path: PathBuf) -> PathBuf {
+    path.canonicalize()
+        .or_else(|_| std::path::absolute(&path))
+        .unwrap_or(path)
+}
+
+/// 检查命令行给出的路径是否存在，并用 [`resolve_path`] 转换
+fn existing_paths(paths: Vec<PathBuf>) -> Result<Vec<PathBuf>, Failure> {
+    paths
+        .into_iter()
+        .map(|path| match path.metadata() {
+            Ok(_) => Ok(resolve_path(path)),
+            Err(e) => {
+                eprintln!("❌ {}: {}", path.display(), e);
+                Err(Failure::Usage)
+            }
         })
         .collect()
 }
 
-fn generate_thumbnails(src_paths: &[PathBuf], dst_dir: &Path) -> anyhow::Result<()> {
-    // 建立输出目录
-    std::fs::create_dir_all(dst_dir).with_context(|| format!("创建输出目录 {:?} 失败", dst_dir))?;
-
-    // 并行处理每一个文件
-    src_paths
-        .iter()
-        // .par_iter()
-        .try_for_each(|src_path| -> anyhow::Result<()> {
-            // 读取原图
-            let img =
-                image::open(src_path).with_context(|| format!("无法读取图片 {:?}", src_path))?;
-
-            // ① 计算目标尺寸（最大高度 300）
-            const MAX_HEIGHT: u32 = 300;
-            let (orig_w, orig_h) = img.dimensions();
-            let (target_w, target_h) = if orig_h <= MAX_HEIGHT {
-                (orig_w, orig_h)
-            } else {
-                let scale = MAX_HEIGHT as f32 / orig_h as f32;
-                ((orig_w as f32 * scale).round() as u32, MAX_HEIGHT)
-            };
+fn run_scan_command(paths: Vec<PathBuf>, config: &MeshConfig, cache: &MeshCache) -> CliResult {
+    let full_scan = paths.is_empty();
+    let roots = if full_scan {
+        config.album_dirs().to_vec()
+    } else {
+        existing_paths(paths)?
+    };
 
-            let resized =
-                img.resize_exact(target_w, target_h, image::imageops::FilterType::Lanczos3);
+    let files = collect_files(&ScanFilter::new(config), roots);
+    println!("found {} files", files.len());
+    let result = generate_thumbnails(files, config, cache, false);
 
-            // ② 设定输出文件名 (与源文件同名，放到 dst_dir)
-            let mut dst_file = dst_dir.join(
-                src_path
-                    .file_stem()
-                    .ok_or_else(|| anyhow::anyhow!("无效文件名: {:?}", src_path))?
-                    .to_os_string(),
-            );
+    // 只有扫描全部相册时才能确定哪些照片已被删除
+    if full_scan {
+        match cache.gc() {
+            Ok(report) if report.removed > 0 => println!(
+                "removed {} thumbnails of deleted photos, reclaimed {}",
+                report.removed,
+                format_bytes(report.reclaimed_bytes)
+            ),
+            Ok(_) => {}
+            Err(e) => {
+                log::error!("{:?}", e);
+                return Err(Failure::Error);
+            }
+        }
+    }
+    result
+}
+
+fn run_thumbs_command(
+    paths: Vec<PathBuf>,
+    retry: bool,
+    config: &MeshConfig,
+    cache: &MeshCache,
+) -> CliResult {
+    let prefixes = existing_paths(paths)?;
+    let files = match cache.database().photo_paths() {
+        Ok(files) => files,
+        Err(e) => {
+            log::error!("{:?}", e);
+            return Err(Failure::Error);
+        }
+    };
+    let files: Vec<PathBuf> = files
+        .into_iter()
+        .filter(|file| prefixes.is_empty() || prefixes.iter().any(|dir| file.starts_with(dir)))
+        .collect();
+    generate_thumbnails(files, config, cache, retry)
+}
 
-            // 用与源相同的后缀；若不支持则强制 jpg
-            let ext = src_path
-                .extension()
-                .and_then(|s| s.to_str())
-                .unwrap_or("jpg")
-                .to_ascii_lowercase();
-            dst_file.set_extension(&ext);
-
-            // ③ 写入图片（JPEG 质量40，PNG 级别9）
-            let out_file = std::fs::File::create(&dst_file)
-                .with_context(|| format!("创建文件 {:?} 失败", dst_file))?;
-            let writer = std::io::BufWriter::new(out_file);
-
-            match ext.as_str() {
-                "png" => {
-                    let encoder = PngEncoder::new_with_quality(
-                        writer,
-                        image::codecs::png::CompressionType::Level(7),
-                        image::codecs::png::FilterType::NoFilter,
-                    );
-                    let rgba = resized.to_rgba8();
-                    encoder
-                        .write_image(&rgba, target_w, target_h, image::ExtendedColorType::Rgba8)
-                        .context("PNG 编码失败")?;
+fn run_tag_command(command: TagCommand, config: &MeshConfig, cache: &MeshCache) -> CliResult {
+    let (edit, tag, files) = match command {
+        TagCommand::Add { tag, files } => (TagEdit::Add, tag, files),
+        TagCommand::Remove { tag, files } => (TagEdit::Remove, tag, files),
+        TagCommand::Set { tag, files } => (TagEdit::Set, tag, files),
+        TagCommand::Ls => {
+            return match cache.database().tags_with_counts() {
+                Ok(tags) => {
+                    for (name, count) in tags {
+                        println!("{}\t{}", name, count);
+                    }
+                    Ok(())
                 }
-                _ => {
-                    // JPEG
-                    let mut encoder = JpegEncoder::new_with_quality(writer, 70);
-                    encoder.encode_image(&resized).context("JPEG 编码失败")?;
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    Err(Failure::Error)
                 }
+            };
+        }
+        TagCommand::Show { files } => {
+            let mut result = Ok(());
+            let mut stdout = std::io::stdout().lock();
+            for file in files {
+                let path = resolve_path(file);
+                match cache.database().photo_tags(&path) {
+                    Ok(tags) => {
+                        let _ = write_path(&mut stdout, &path)
+                            .and_then(|_| writeln!(stdout, "\t{}", tags.join(", ")));
+                    }
+                    Err(e) => {
+                        log::error!("{:?}", e);
+                        result = Err(Failure::Error);
+                    }
+                }
+            }
+            return result;
+        }
+    };
+
+    let tag = tag_path(&tag)?;
+    let (photos, skipped) = resolve_photos(files, edit != TagEdit::Remove, config, cache)?;
+    match cache.database().edit_tags(&tag, &photos, edit) {
+        Ok(changed) => println!("✅ {}: {} photos changed", tag, changed),
+        Err(e) => {
+            log::error!("{:?}", e);
+            return Err(Failure::Error);
+        }
+    }
+    if skipped > 0 {
+        Err(Failure::Partial)
+    } else {
+        Ok(())
+    }
+}
+
+/// 规范化以 `/` 分隔的标签路径，去掉每一级两端的空白
+fn tag_path(tag: &str) -> Result<String, Failure> {
+    let segments: Vec<&str> = tag.split('/').map(str::trim).collect();
+    if segments.iter().any(|segment| segment.is_empty()) {
+        eprintln!("❌ invalid tag path: {:?}", tag);
+        return Err(Failure::Usage);
+    }
+    Ok(segments.join("/"))
+}
+
+/// 将命令行给出的文件与目录展开为照片库中的照片，返回照片路径与跳过的文件数
+///
+/// `scan` 为 `true` 时先收录尚未入库或已修改的文件；无法收录的文件会被跳过。
+fn resolve_photos(
+    files: Vec<PathBuf>,
+    scan: bool,
+    config: &MeshConfig,
+    cache: &MeshCache,
+) -> Result<(Vec<PathBuf>, usize), Failure> {
+    let paths = existing_paths(files)?;
+    let files = collect_files(&ScanFilter::new(config), paths.clone());
+
+    // 直接给出但不在相册目录中，或被格式、大小与忽略规则过滤掉的文件
+    let mut skipped = 0;
+    for path in paths.iter().filter(|path| !path.is_dir()) {
+        if !files.contains(path) {
+            eprintln!("❌ not a photo or video in any album: {}", path.display());
+            skipped += 1;
+        }
+    }
+
+    // 已入库且未修改的文件会被直接跳过
+    if scan
+        && !files.is_empty()
+        && generate_thumbnails(files.clone(), config, cache, false) == Err(Failure::Error)
+    {
+        return Err(Failure::Error);
+    }
+
+    let mut photos = Vec::with_capacity(files.len());
+    for file in files {
+        match cache.database().contains_photo(&file) {
+            Ok(true) => photos.push(file),
+            Ok(false) => {
+                eprintln!("❌ not in library: {}", file.display());
+                skipped += 1;
+            }
+            Err(e) => {
+                log::error!("{:?}", e);
+                return Err(Failure::Error);
             }
+        }
+    }
+    Ok((photos, skipped))
+}
 
-            println!("✅ 生成缩略图: {:?}", dst_file);
+fn run_search_command(query: Vec<String>, cache: &MeshCache) -> CliResult {
+    match cache.database().search_filenames(&query.join(" ")) {
+        Ok(results) => {
+            let mut stdout = std::io::stdout().lock();
+            for (path, _) in &results {
+                let _ = write_path(&mut stdout, path).and_then(|_| writeln!(stdout));
+            }
             Ok(())
-        })
+        }
+        Err(e) => {
+            log::error!("{:?}", e);
+            Err(Failure::Error)
+        }
+    }
+}
+
+fn run_info_command(files: Vec<PathBuf>, cache: &MeshCache) -> CliResult {
+    let mut result = Ok(());
+    for (index, file) in files.into_iter().enumerate() {
+        let path = resolve_path(file);
+        let photo = match cache.database().photo(&path) {
+            Ok(Some(photo)) => photo,
+            Ok(None) => {
+                eprintln!("❌ not in library: {}", path.display());
+                result = Err(Failure::Error);
+                continue;
+            }
+            Err(e) => {
+                log::error!("{:?}", e);
+                result = Err(Failure::Error);
+                continue;
+            }
+        };
+        let tags = cache.database().photo_tags(&path).unwrap_or_else(|e| {
+            log::warn!("{:?}", e);
+            Vec::new()
+        });
+        let cached = SourceStamp::from_path(&path)
+            .is_ok_and(|stamp| cache.thumbnail().is_fresh(photo.file_hash, &stamp));
+
+        if index > 0 {
+            println!();
+        }
+        println!("path: {}", path.display());
+        println!("type: {} ({})", photo.media_type, photo.mime_type);
+        println!("size: {}", format_bytes(photo.size));
+        println!("dimensions: {}x{}", photo.width, photo.height);
+        println!("created: {}", photo.created_at);
+        println!("modified: {}", photo.modified_at);
+        println!(
+            "color space: {}",
+            photo.color_space.as_deref().unwrap_or("sRGB")
+        );
+        if let Some(duration_ms) = photo.duration_ms {
+            println!("duration: {:.1}s", duration_ms as f64 / 1000.0);
+        }
+        if let Some(codec) = &photo.codec {
+            println!("codec: {}", codec);
+        }
+        println!("tags: {}", tags.join(", "));
+        println!("thumbnail: {}", if cached { "cached" } else { "missing" });
+    }
+    result
 }
 
-fn main() {
+fn main() -> ExitCode {
+    mesh_core::run_worker_if_requested();
+
     let cli = Cli::parse();
     env_logger::init_from_env(env_logger::Env::new().filter("MESH_LOG"));
 
-    let config = MeshConfig::init();
-    let dst_dir = PathBuf::from("tmp");
-
-    let files = collect_files(config.album_dirs(), config.excluded_dirs(), cli.files);
+    let base_dirs = cli
+        .home
+        .as_deref()
+        .map(MeshDirs::new)
+        .unwrap_or_else(MeshDirs::from_env);
+    let mut libraries = match LibraryRegistry::load(base_dirs) {
+        Ok(libraries) => libraries,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            return ExitCode::from(Failure::Error as u8);
+        }
+    };
+    let dirs = match cli.library.as_deref() {
+        Some(name) => match libraries.dirs(name) {
+            Ok(dirs) => dirs,
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                return ExitCode::from(Failure::Usage as u8);
+            }
+        },
+        None => libraries.current_dirs(),
+    };
 
-    println!("file len is: {}", files.len());
+    // 配置文件无法解析时不使用默认配置继续运行，以免扫描错误的目录
+    let mut config = match MeshConfig::load(&dirs) {
+        Ok(config) => config,
+        // `config reset` 与照片库管理不需要读取原有的配置
+        Err(_)
+            if matches!(
+                cli.command,
+                Command::Config { .. } | Command::Libraries { .. }
+            ) =>
+        {
+            MeshConfig::new(&dirs)
+        }
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            eprintln!("fix the file or run `mesh-cli config reset`");
+            return ExitCode::from(Failure::Error as u8);
+        }
+    };
+    let cache = MeshCache::new(&dirs, config.thumbnail_store());
+    // 只读照片库的命令不检查相册目录，以免挂载点较多时拖慢查询
+    if matches!(
+        cli.command,
+        Command::Scan { .. }
+            | Command::Thumbs { .. }
+            | Command::Stats
+            | Command::Albums { .. }
+            | Command::Cache { .. }
+            | Command::Errors { .. }
+    ) {
+        reconcile_roots(&mut config, &cache);
+    }
 
-    if let Err(e) = generate_thumbnails(&files, &dst_dir) {
-        log::error!("{:?}", e);
+    let result = match cli.command {
+        Command::Scan { paths } => run_scan_command(paths, &config, &cache),
+        Command::Thumbs { paths, retry } => run_thumbs_command(paths, retry, &config, &cache),
+        Command::Tag { command } => run_tag_command(command, &config, &cache),
+        Command::Search { query } => run_search_command(query, &cache),
+        Command::Info { files } => run_info_command(files, &cache),
+        Command::Stats => run_stats_command(&config, &cache),
+        Command::Libraries { command } => {
+            run_libraries_command(command.unwrap_or(LibrariesCommand::List), &mut libraries)
+        }
+        Command::Albums { command } => {
+            run_albums_command(command.unwrap_or(AlbumsCommand::List), &mut config, &cache)
+        }
+        Command::Config { command } => run_config_command(command, &dirs, &mut config),
+        Command::Cache { command } => run_cache_command(command, &mut config, &cache),
+        Command::Errors { command } => {
+            run_errors_command(command.unwrap_or(ErrorsCommand::List), &config, &cache)
+        }
+        Command::Relocate { old_root, new_root } => {
+            run_relocate_command(old_root, new_root, &mut config, &cache)
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => ExitCode::from(failure as u8),
     }
 }
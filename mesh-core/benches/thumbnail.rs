@@ -0,0 +1,93 @@
+use std::{hint::black_box, path::PathBuf};
+
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+use image::{RgbImage, codecs::jpeg::JpegEncoder, imageops::FilterType};
+use mesh_core::{
+    DecodedImage, MediaFormat, THUMBNAIL_MAX_HEIGHT, decode_for_thumbnail, resize_for_thumbnail,
+    thumbnail_size,
+};
+
+/// 生成一张 24 MP 的测试 JPEG
+fn sample_jpeg() -> PathBuf {
+    let path = std::env::temp_dir().join("mesh-bench-6000x4000.jpg");
+    if path.exists() {
+        return path;
+    }
+
+    // 平滑渐变叠加少量噪点，压缩后的体积接近真实照片
+    let image = RgbImage::from_fn(6000, 4000, |x, y| {
+        let noise = ((x.wrapping_mul(7919) ^ y.wrapping_mul(104729)) & 0x0F) as u8;
+        image::Rgb([
+            ((x * 255 / 6000) as u8).saturating_add(noise),
+            ((y * 255 / 4000) as u8).saturating_add(noise),
+            ((x + y) / 40 % 240) as u8 + noise,
+        ])
+    });
+    let file = std::fs::File::create(&path).unwrap();
+    JpegEncoder::new_with_quality(file, 90)
+        .encode_image(&image)
+        .unwrap();
+    path
+}
+
+fn bench_thumbnail(c: &mut Criterion) {
+    let path = sample_jpeg();
+    let mut group = c.benchmark_group("thumbnail_6000x4000_jpeg");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(1));
+
+    // 旧实现：完整解码后用 Lanczos3 缩放
+    group.bench_function("full_decode_lanczos3", |b| {
+        b.iter(|| {
+            let image = image::open(&path).unwrap();
+            let (w, h) = thumbnail_size(image.width(), image.height(), THUMBNAIL_MAX_HEIGHT);
+            black_box(image.resize_exact(w, h, FilterType::Lanczos3))
+        })
+    });
+
+    group.bench_function("scaled_decode", |b| {
+        b.iter(|| {
+            let decoded =
+                decode_for_thumbnail(&path, MediaFormat::Jpeg, THUMBNAIL_MAX_HEIGHT).unwrap();
+            black_box(resize_for_thumbnail(decoded, THUMBNAIL_MAX_HEIGHT))
+        })
+    });
+
+    group.finish();
+}
+
+/// 只比较缩放：不能缩放解码的格式需要把原尺寸的图片缩小到缩略图
+fn bench_resize(c: &mut Criterion) {
+    let image = image::open(sample_jpeg()).unwrap();
+    let (w, h) = thumbnail_size(image.width(), image.height(), THUMBNAIL_MAX_HEIGHT);
+    let mut group = c.benchmark_group("resize_6000x4000_rgb8");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(1));
+
+    group.bench_function("thumbnail_exact", |b| {
+        b.iter_batched(
+            || image.clone(),
+            |image| black_box(image.thumbnail_exact(w, h)),
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("simd_area_average", |b| {
+        b.iter_batched(
+            || DecodedImage {
+                image: image.clone(),
+                width: image.width(),
+                height: image.height(),
+                icc: None,
+                captured_at: None,
+            },
+            |decoded| black_box(resize_for_thumbnail(decoded, THUMBNAIL_MAX_HEIGHT)),
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_thumbnail, bench_resize);
+criterion_main!(benches);
@@ -1,38 +1,65 @@
 mod database;
 mod thumbnail;
 
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use crate::MESH_DIR;
-use crate::cache::database::MeshDatabase;
-use crate::cache::thumbnail::MeshThumbnail;
+pub use crate::cache::database::{
+    MeshDatabase, PhotoRecord, RootRecord, ScanErrorKind, ScanErrorRecord, TagEdit,
+};
+pub use crate::cache::thumbnail::{
+    DirectoryStore, GcReport, MeshThumbnail, SourceStamp, SqliteStore, ThumbnailBackend,
+    ThumbnailEntry, ThumbnailStats, ThumbnailStore,
+};
+use crate::{MeshDirs, volume};
+
+/// 扫描前核对相册目录时发现的变化
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RootChange {
+    /// 相册目录无法访问，其中的照片已标记为离线
+    Offline(PathBuf),
+    /// 离线的相册目录重新可以访问
+    Online(PathBuf),
+    /// 相册目录所在的卷挂载到了新的位置，调用方应据此更新配置
+    Moved { from: PathBuf, to: PathBuf },
+}
+
+/// 相册目录移动后迁移的数量
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RelocateReport {
+    pub roots: usize,
+    pub photos: usize,
+    pub thumbnails: usize,
+}
 
 pub struct MeshCache {
     database: MeshDatabase,
     thumbnail: MeshThumbnail,
+    dir_path: PathBuf,
 }
 
 impl MeshCache {
-    pub(crate) fn dir_path() -> &'static Path {
-        let path = MESH_DIR.cache_dir();
-        if let Err(e) = std::fs::create_dir_all(&path) {
-            log::error!("{:?}", e);
-        }
-        path
-    }
-
-    pub fn new() -> Self {
-        let cache_dir_path = Self::dir_path();
-        if let Err(e) = std::fs::create_dir_all(&cache_dir_path) {
+    /// 打开 `dirs` 中的照片库数据库与缩略图缓存
+    pub fn new(dirs: &MeshDirs, thumbnail_backend: ThumbnailBackend) -> Self {
+        let cache_dir_path = dirs.cache_dir();
+        if let Err(e) = std::fs::create_dir_all(cache_dir_path) {
             log::error!("{:?}", e);
         }
 
         let database = MeshDatabase::init(cache_dir_path.join("mesh.db"));
-        let thumbnail = MeshThumbnail::new(cache_dir_path.join("thumbnail"));
+        let store = thumbnail_backend
+            .open(cache_dir_path)
+            .map_err(|e| log::warn!("Failed to open thumbnail store: {}", e))
+            .unwrap();
+        let thumbnail = MeshThumbnail::new(store);
 
         Self {
             database,
             thumbnail,
+            dir_path: cache_dir_path.to_path_buf(),
         }
     }
 
@@ -43,4 +70,355 @@ impl MeshCache {
     pub fn thumbnail(&self) -> &MeshThumbnail {
         &self.thumbnail
     }
+
+    /// 删除原图已不存在的照片记录，再清理不属于任何照片的缩略图
+    pub fn gc(&self) -> anyhow::Result<GcReport> {
+        let removed_photos = self.database.remove_missing_photos()?;
+        if removed_photos > 0 {
+            log::info!("Removed {} missing photos from database", removed_photos);
+        }
+
+        let live = self.database.photo_file_hashes()?;
+        Ok(self.thumbnail.gc(&live))
+    }
+
+    /// 删除 `dir` 目录树下的照片记录及其缩略图，用于移除相册目录或新增排除目录之后
+    pub fn prune_dir(&self, dir: &Path) -> anyhow::Result<GcReport> {
+        let removed = self.database.remove_photos_under(dir)?;
+        if !removed.is_empty() {
+            log::info!(
+                "Removed {} photos under {:?} from database",
+                removed.len(),
+                dir
+            );
+        }
+        Ok(self.thumbnail.remove_many(&removed))
+    }
+
+    /// 扫描前核对相册目录：登记新的目录，把无法访问的目录中的照片标记为离线，
+    /// 并在常见的挂载位置查找换了挂载点的卷
+    ///
+    /// 离线照片的记录、标签与缩略图都会保留，卷重新挂载后恢复在线；
+    /// 挂载到新位置时照片记录与缩略图迁移到新路径。
+    pub fn reconcile_roots(&self, album_dirs: &[PathBuf]) -> anyhow::Result<Vec<RootChange>> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        let roots = self.database.roots()?;
+        let mut changes = Vec::new();
+        // 卷标识在数据库中唯一，记录每个标识属于哪个相册目录
+        let mut volume_owners: HashMap<String, PathBuf> = roots
+            .iter()
+            .filter_map(|root| Some((root.volume_id.clone()?, root.path.clone())))
+            .collect();
+
+        for dir in album_dirs {
+            let root = roots.iter().find(|root| &root.path == dir);
+            if dir.is_dir() {
+                let volume_id = volume::ensure_volume_id(dir)
+                    .map_err(|e| log::warn!("Failed to write volume marker in {:?}: {}", dir, e))
+                    .ok();
+                match root {
+                    None => {
+                        let volume_id = volume_id
+                            .and_then(|volume_id| unique_volume_id(&volume_owners, dir, volume_id));
+                        self.database.insert_root(dir, volume_id.as_deref(), now)?;
+                        if let Some(volume_id) = volume_id {
+                            volume_owners.insert(volume_id, dir.clone());
+                        }
+                    }
+                    Some(root) => {
+                        if root.volume_id.is_none()
+                            && let Some(volume_id) = volume_id.and_then(|volume_id| {
+                                unique_volume_id(&volume_owners, dir, volume_id)
+                            })
+                        {
+                            self.database.set_root_volume_id(root.id, &volume_id)?;
+                            volume_owners.insert(volume_id, dir.clone());
+                        }
+                        if root.online {
+                            self.database.touch_root(root.id, now)?;
+                        } else {
+                            self.database.set_root_online(root, true, now)?;
+                            log::info!("Album {:?} is online again", dir);
+                            changes.push(RootChange::Online(dir.clone()));
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let Some(root) = root else {
+                continue;
+            };
+            let found = root
+                .volume_id
+                .as_deref()
+                .and_then(|volume_id| volume::find_volume(volume_id, &root.path))
+                .filter(|to| !album_dirs.contains(to));
+            if let Some(to) = found {
+                let (photos, thumbnails) = self.move_root(root, &to)?;
+                log::info!(
+                    "Album {:?} moved to {:?}, {} photos and {} thumbnails kept",
+                    root.path,
+                    to,
+                    photos,
+                    thumbnails
+                );
+                let moved_root = RootRecord {
+                    path: to.clone(),
+                    ..root.clone()
+                };
+                self.database.set_root_online(&moved_root, true, now)?;
+                changes.push(RootChange::Moved {
+                    from: root.path.clone(),
+                    to,
+                });
+            } else if root.online {
+                let count = self.database.set_root_online(root, false, now)?;
+                log::info!("Album {:?} is offline, kept {} photos", dir, count);
+                changes.push(RootChange::Offline(dir.clone()));
+            }
+        }
+        Ok(changes)
+    }
+
+    /// 照片文件夹整体移动后，把位于 `from` 下的相册目录改到 `to` 下
+    ///
+    /// 只改写相册目录记录，照片的 id、标签与缩略图都会保留。
+    pub fn relocate(&self, from: &Path, to: &Path) -> anyhow::Result<RelocateReport> {
+        let mut report = RelocateReport::default();
+        for root in self.database.roots()? {
+            let Ok(relative) = root.path.strip_prefix(from) else {
+                continue;
+            };
+            let target = if relative.as_os_str().is_empty() {
+                to.to_path_buf()
+            } else {
+                to.join(relative)
+            };
+            let (photos, thumbnails) = self.move_root(&root, &target)?;
+            report.roots += 1;
+            report.photos += photos;
+            report.thumbnails += thumbnails;
+        }
+        Ok(report)
+    }
+
+    /// 返回迁移的照片数与缩略图数
+    fn move_root(&self, root: &RootRecord, to: &Path) -> anyhow::Result<(usize, usize)> {
+        let hashes = self.database.move_root(root, to)?;
+        Ok((hashes.len(), self.thumbnail.rekey(&hashes)))
+    }
+
+    /// 把缩略图迁移到另一种存储后端，返回迁移的数量
+    ///
+    /// 复制完成后调用 `commit` 保存配置，成功后才删除旧后端中的缩略图。
+    /// 迁移完成后当前实例仍指向旧后端，调用方应重新创建 `MeshCache`。
+    pub fn migrate_thumbnails(
+        &self,
+        target: ThumbnailBackend,
+        commit: impl FnOnce() -> std::io::Result<()>,
+    ) -> std::io::Result<usize> {
+        let target_store = target.open(&self.dir_path)?;
+        if target_store.path() == self.thumbnail.path() {
+            return Ok(0);
+        }
+        self.thumbnail.migrate_to(target_store.as_ref(), commit)
+    }
+
+    /// 按 LRU 淘汰缩略图，使缓存不超过 `limit_bytes`
+    pub fn enforce_limit(&self, limit_bytes: u64) -> GcReport {
+        self.thumbnail.evict_to(limit_bytes)
+    }
+}
+
+/// `volume_id` 已经属于另一个相册目录时（复制的相册或克隆的磁盘），为 `dir` 重新生成标识
+///
+/// 无法写入新标识时返回 `None`，该相册目录不记录卷标识。
+fn unique_volume_id(
+    owners: &HashMap<String, PathBuf>,
+    dir: &Path,
+    volume_id: String,
+) -> Option<String> {
+    match owners.get(&volume_id) {
+        Some(owner) if owner != dir => {
+            log::warn!(
+                "Album {:?} has the same volume marker as {:?}, generating a new one",
+                dir,
+                owner
+            );
+            volume::new_volume_id(dir)
+                .map_err(|e| log::warn!("Failed to write volume marker in {:?}: {}", dir, e))
+                .ok()
+        }
+        _ => Some(volume_id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reconcile_regenerates_duplicate_volume_markers() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MeshCache::new(
+            &MeshDirs::new(dir.path().join("mesh")),
+            ThumbnailBackend::Directory,
+        );
+        let original = dir.path().join("original");
+        let copy = dir.path().join("copy");
+        std::fs::create_dir_all(&original).unwrap();
+        std::fs::create_dir_all(&copy).unwrap();
+
+        let volume_id = volume::ensure_volume_id(&original).unwrap();
+        cache
+            .reconcile_roots(std::slice::from_ref(&original))
+            .unwrap();
+
+        // 复制的相册带着相同的标识
+        std::fs::copy(
+            original.join(volume::VOLUME_MARKER_FILE),
+            copy.join(volume::VOLUME_MARKER_FILE),
+        )
+        .unwrap();
+        let changes = cache
+            .reconcile_roots(&[original.clone(), copy.clone()])
+            .unwrap();
+        assert!(changes.is_empty());
+
+        let roots = cache.database().roots().unwrap();
+        assert_eq!(roots.len(), 2);
+        let copy_id = volume::read_volume_id(&copy).unwrap();
+        assert_ne!(copy_id, volume_id);
+        assert_eq!(volume::read_volume_id(&original).unwrap(), volume_id);
+        for root in &roots {
+            let expected = if root.path == original {
+                &volume_id
+            } else {
+                &copy_id
+            };
+            assert_eq!(root.volume_id.as_ref(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn reconcile_handles_duplicates_among_new_albums() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MeshCache::new(
+            &MeshDirs::new(dir.path().join("mesh")),
+            ThumbnailBackend::Directory,
+        );
+        let albums: Vec<PathBuf> = ["a", "b", "c"]
+            .iter()
+            .map(|name| dir.path().join(name))
+            .collect();
+        for album in &albums {
+            std::fs::create_dir_all(album).unwrap();
+            std::fs::write(album.join(volume::VOLUME_MARKER_FILE), "cloned\n").unwrap();
+        }
+
+        cache.reconcile_roots(&albums).unwrap();
+
+        let mut ids: Vec<String> = cache
+            .database()
+            .roots()
+            .unwrap()
+            .into_iter()
+            .map(|root| root.volume_id.unwrap())
+            .collect();
+        assert!(ids.contains(&"cloned".to_owned()));
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), albums.len());
+    }
+
+    #[test]
+    fn relocate_moves_roots_and_thumbnails() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MeshCache::new(
+            &MeshDirs::new(dir.path().join("mesh")),
+            ThumbnailBackend::Directory,
+        );
+        let stamp = SourceStamp {
+            size: 1024,
+            modified: 2_000_000_000,
+        };
+        let hash = |path: &str| MeshThumbnail::generate_file_hash(Path::new(path));
+        for path in ["/old/a/1.jpg", "/old/b/2.jpg", "/old-ish/3.jpg"] {
+            let path = PathBuf::from(path);
+            cache
+                .database()
+                .upsert_photo(&PhotoRecord {
+                    file_hash: MeshThumbnail::generate_file_hash(&path),
+                    filename: path.file_name().unwrap().to_string_lossy().into_owned(),
+                    path: path.clone(),
+                    width: 640,
+                    height: 480,
+                    size: stamp.size,
+                    created_at: 1,
+                    modified_at: 2,
+                    media_type: crate::MediaType::Photo,
+                    mime_type: "image/jpeg".to_owned(),
+                    color_space: None,
+                    duration_ms: None,
+                    codec: None,
+                })
+                .unwrap();
+        }
+        cache
+            .thumbnail()
+            .write_thumbnail(hash("/old/a/1.jpg"), &stamp, b"thumbnail")
+            .unwrap();
+
+        let report = cache
+            .relocate(Path::new("/old"), Path::new("/new"))
+            .unwrap();
+        assert_eq!(
+            report,
+            RelocateReport {
+                roots: 2,
+                photos: 2,
+                thumbnails: 1,
+            }
+        );
+
+        let mut roots: Vec<PathBuf> = cache
+            .database()
+            .roots()
+            .unwrap()
+            .into_iter()
+            .map(|root| root.path)
+            .collect();
+        roots.sort();
+        assert_eq!(roots, ["/new/a", "/new/b", "/old-ish"].map(PathBuf::from));
+        assert_eq!(
+            cache
+                .thumbnail()
+                .read_thumbnail(hash("/new/a/1.jpg"), &stamp)
+                .unwrap(),
+            b"thumbnail"
+        );
+        assert!(!cache.thumbnail().is_fresh(hash("/old/a/1.jpg"), &stamp));
+        assert!(
+            cache
+                .database()
+                .contains_photo(Path::new("/new/b/2.jpg"))
+                .unwrap()
+        );
+        assert!(
+            cache
+                .database()
+                .contains_photo(Path::new("/old-ish/3.jpg"))
+                .unwrap()
+        );
+
+        // 没有相册目录位于 `from` 下时不做任何修改
+        let report = cache
+            .relocate(Path::new("/missing"), Path::new("/new"))
+            .unwrap();
+        assert_eq!(report, RelocateReport::default());
+    }
 }
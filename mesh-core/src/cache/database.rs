@@ -1,9 +1,116 @@
-use std::path::Path;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
 
-use rusqlite::Connection;
+use rusqlite::{
+    Connection, OptionalExtension, ToSql, params,
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef},
+};
+
+use crate::{MediaFormat, MediaType, MeshThumbnail};
+
+/// `photos` 表中的一行
+#[derive(Debug, Clone)]
+pub struct PhotoRecord {
+    pub path: PathBuf,
+    pub file_hash: u128,
+    pub filename: String,
+    pub width: u32,
+    pub height: u32,
+    pub size: u64,
+    /// UNIX 时间戳（秒）
+    pub created_at: i64,
+    /// UNIX 时间戳（秒）
+    pub modified_at: i64,
+    pub media_type: MediaType,
+    /// 按文件内容识别出的 MIME 类型
+    pub mime_type: String,
+    /// 内嵌 ICC 配置文件的名称，没有配置文件（按 sRGB 处理）时为 `None`
+    pub color_space: Option<String>,
+    /// 视频时长（毫秒），照片为 `None`
+    pub duration_ms: Option<u64>,
+    /// 视频编码，照片为 `None`
+    pub codec: Option<String>,
+}
+
+/// 单个文件处理失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanErrorKind {
+    /// 读取文件或写入缓存失败
+    Io,
+    /// 文件内容损坏或无法解码
+    Decode,
+    /// 不支持的格式或特性
+    Unsupported,
+}
+
+impl ScanErrorKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Io => "io",
+            Self::Decode => "decode",
+            Self::Unsupported => "unsupported",
+        }
+    }
+
+    pub(crate) fn parse(kind: &str) -> Self {
+        match kind {
+            "io" => Self::Io,
+            "unsupported" => Self::Unsupported,
+            _ => Self::Decode,
+        }
+    }
+}
+
+impl std::fmt::Display for ScanErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// `scan_errors` 表中的一行
+#[derive(Debug, Clone)]
+pub struct ScanErrorRecord {
+    pub path: PathBuf,
+    pub kind: ScanErrorKind,
+    pub message: String,
+    /// 首次失败之后又重试失败的次数
+    pub retry_count: u32,
+    /// 失败时原图的大小与修改时间（纳秒），原图变化后会自动重试
+    pub source_size: u64,
+    pub source_modified: u64,
+    /// UNIX 时间戳（秒）
+    pub last_attempt: i64,
+}
+
+/// 对一组照片的标签所做的修改
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagEdit {
+    /// 为照片添加标签
+    Add,
+    /// 从照片上移除标签
+    Remove,
+    /// 将照片的标签替换为这一个
+    Set,
+}
+
+/// `roots` 表中的一行：已扫描过的相册目录
+#[derive(Debug, Clone)]
+pub struct RootRecord {
+    pub id: i64,
+    pub path: PathBuf,
+    /// 相册目录中卷标识文件的内容，目录只读时为 `None`
+    pub volume_id: Option<String>,
+    /// 目录不可访问时为 `false`，其中的照片保留并标记为离线
+    pub online: bool,
+    /// 最近一次可以访问的时间，UNIX 时间戳（秒）
+    pub last_seen: Option<i64>,
+}
 
 pub struct MeshDatabase {
-    conn: Connection,
+    conn: Mutex<Connection>,
 }
 
 impl MeshDatabase {
@@ -12,37 +119,1030 @@ impl MeshDatabase {
             .map_err(|e| log::warn!("Failed to open database: {}", e))
             .unwrap();
 
-        init_execute(&conn)
+        migrate(&conn)
+            .and_then(|_| init_execute(&conn))
+            .and_then(|_| conn.pragma_update(None, "user_version", SCHEMA_VERSION))
             .map_err(|e| log::warn!("Failed to init database: {}", e))
             .unwrap();
 
-        MeshDatabase { conn }
+        MeshDatabase {
+            conn: Mutex::new(conn),
+        }
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
     }
 
     pub fn sqlite_version(&self) -> rusqlite::Result<String> {
-        self.conn.query_row(
+        self.conn().query_row(
             "SELECT sqlite_version()",
             [], // 参数为空
             |row| row.get(0),
         )
     }
+
+    /// 插入照片记录，路径已存在时更新
+    ///
+    /// 路径按所在的相册目录保存为相对路径；不在任何已登记的相册目录中时，
+    /// 以文件所在目录登记一个新的相册目录。
+    pub fn upsert_photo(&self, photo: &PhotoRecord) -> rusqlite::Result<()> {
+        let conn = self.conn();
+        let (root_id, relative) = match find_root(&conn, &photo.path)? {
+            Some(found) => found,
+            None => {
+                let dir = photo.path.parent().unwrap_or(Path::new(""));
+                (
+                    insert_root(&conn, dir, None, None)?,
+                    relative_path(&photo.path, dir),
+                )
+            }
+        };
+        conn.execute(
+            "INSERT INTO photos (root_id, path, file_hash, filename, width, height, size, created_at,
+                                 modified_at, media_type, mime_type, color_space, duration_ms, codec)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+             ON CONFLICT(root_id, path) DO UPDATE SET
+                offline = 0,
+                filename = excluded.filename,
+                width = excluded.width,
+                height = excluded.height,
+                size = excluded.size,
+                created_at = excluded.created_at,
+                modified_at = excluded.modified_at,
+                media_type = excluded.media_type,
+                mime_type = excluded.mime_type,
+                color_space = excluded.color_space,
+                duration_ms = excluded.duration_ms,
+                codec = excluded.codec",
+            params![
+                root_id,
+                PathBytes(&relative),
+                format_hash(photo.file_hash),
+                photo.filename,
+                photo.width,
+                photo.height,
+                photo.size as i64,
+                photo.created_at,
+                photo.modified_at,
+                photo.media_type.as_str(),
+                photo.mime_type,
+                photo.color_space,
+                photo.duration_ms.map(|ms| ms as i64),
+                photo.codec,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn contains_photo(&self, path: &Path) -> rusqlite::Result<bool> {
+        let conn = self.conn();
+        let Some((root_id, relative)) = find_root(&conn, path)? else {
+            return Ok(false);
+        };
+        conn.query_row(
+            "SELECT 1 FROM photos WHERE root_id = ?1 AND path = ?2",
+            params![root_id, PathBytes(&relative)],
+            |_| Ok(()),
+        )
+        .optional()
+        .map(|row| row.is_some())
+    }
+
+    /// 路径已入库且记录的大小与修改时间（秒）与原文件一致
+    ///
+    /// 旧版本写入的记录缺少 MIME 类型，视为过期以便重新识别。
+    pub fn contains_current_photo(
+        &self,
+        path: &Path,
+        size: u64,
+        modified_at: i64,
+    ) -> rusqlite::Result<bool> {
+        let conn = self.conn();
+        let Some((root_id, relative)) = find_root(&conn, path)? else {
+            return Ok(false);
+        };
+        conn.query_row(
+            "SELECT 1 FROM photos
+             WHERE root_id = ?1 AND path = ?2 AND size = ?3 AND modified_at = ?4
+               AND mime_type IS NOT NULL",
+            params![
+                root_id,
+                PathBytes(&relative),
+                size as i64,
+                modified_at
+            ],
+            |_| Ok(()),
+        )
+        .optional()
+        .map(|row| row.is_some())
+    }
+
+    /// 读取照片记录，不在照片库中时返回 `None`
+    pub fn photo(&self, path: &Path) -> rusqlite::Result<Option<PhotoRecord>> {
+        let conn = self.conn();
+        let Some((root_id, relative)) = find_root(&conn, path)? else {
+            return Ok(None);
+        };
+        conn.query_row(
+            "SELECT file_hash, filename, width, height, size, created_at, modified_at, media_type,
+                    mime_type, color_space, duration_ms, codec
+             FROM photos WHERE root_id = ?1 AND path = ?2",
+            params![root_id, PathBytes(&relative)],
+            |row| {
+                Ok(PhotoRecord {
+                    path: path.to_path_buf(),
+                    file_hash: u128::from_str_radix(&row.get::<_, String>(0)?, 16)
+                        .unwrap_or_default(),
+                    filename: row.get(1)?,
+                    width: row.get(2)?,
+                    height: row.get(3)?,
+                    size: row.get::<_, i64>(4)? as u64,
+                    created_at: row.get(5)?,
+                    modified_at: row.get(6)?,
+                    media_type: match row.get::<_, String>(7)?.as_str() {
+                        "video" => MediaType::Video,
+                        _ => MediaType::Photo,
+                    },
+                    mime_type: row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+                    color_space: row.get(9)?,
+                    duration_ms: row.get::<_, Option<i64>>(10)?.map(|ms| ms as u64),
+                    codec: row.get(11)?,
+                })
+            },
+        )
+        .optional()
+    }
+
+    /// 照片的标签，按名称排序
+    pub fn photo_tags(&self, path: &Path) -> rusqlite::Result<Vec<String>> {
+        let conn = self.conn();
+        let Some((root_id, relative)) = find_root(&conn, path)? else {
+            return Ok(Vec::new());
+        };
+        let mut stmt = conn.prepare(
+            "SELECT tags.name FROM photo_tags
+             JOIN tags ON tags.id = photo_tags.tag_id
+             JOIN photos ON photos.id = photo_tags.photo_id
+             WHERE photos.root_id = ?1 AND photos.path = ?2
+             ORDER BY tags.name",
+        )?;
+        stmt.query_map(params![root_id, PathBytes(&relative)], |row| row.get(0))?
+            .collect()
+    }
+
+    /// 在线相册目录中所有照片的完整路径
+    pub fn photo_paths(&self) -> rusqlite::Result<Vec<PathBuf>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT roots.path, photos.path
+             FROM photos JOIN roots ON roots.id = photos.root_id
+             WHERE photos.offline = 0
+             ORDER BY roots.path, photos.path",
+        )?;
+        stmt.query_map([], |row| {
+            Ok(absolute_path(
+                &row.get::<_, DbPath>(0)?.0,
+                &row.get::<_, DbPath>(1)?.0,
+            ))
+        })?
+        .collect()
+    }
+
+    pub fn tag_count(&self) -> rusqlite::Result<u64> {
+        self.conn()
+            .query_row("SELECT COUNT(*) FROM tags", [], |row| row.get::<_, i64>(0))
+            .map(|count| count as u64)
+    }
+
+    /// 所有标签及直接打上该标签的照片数，按名称排序
+    pub fn tags_with_counts(&self) -> rusqlite::Result<Vec<(String, u64)>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT tags.name, COALESCE(tag_counts.photo_count, 0)
+             FROM tags LEFT JOIN tag_counts ON tag_counts.tag_id = tags.id
+             ORDER BY tags.name",
+        )?;
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as u64)))?
+            .collect()
+    }
+
+    /// 修改 `paths` 中照片的标签 `tag`，返回标签有变化的照片数
+    ///
+    /// `tag` 为以 `/` 分隔的层级路径，如 `旅行/2024/京都`，不存在的上级标签会一并创建，
+    /// 每一级的 `parent_id` 指向上一级。不在照片库中的路径会被忽略。
+    /// `photo_tags` 与 `tag_counts` 在同一个事务中更新。
+    pub fn edit_tags(
+        &self,
+        tag: &str,
+        paths: &[PathBuf],
+        edit: TagEdit,
+    ) -> rusqlite::Result<usize> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+
+        let mut photo_ids = Vec::with_capacity(paths.len());
+        for path in paths {
+            let Some((root_id, relative)) = find_root(&tx, path)? else {
+                continue;
+            };
+            let id = tx
+                .query_row(
+                    "SELECT id FROM photos WHERE root_id = ?1 AND path = ?2",
+                    params![root_id, PathBytes(&relative)],
+                    |row| row.get::<_, i64>(0),
+                )
+                .optional()?;
+            photo_ids.extend(id);
+        }
+
+        // 移除标签时不创建不存在的标签
+        let tag_id = match edit {
+            TagEdit::Remove => tx
+                .query_row("SELECT id FROM tags WHERE name = ?1", [tag], |row| {
+                    row.get(0)
+                })
+                .optional()?,
+            TagEdit::Add | TagEdit::Set => Some(ensure_tag(&tx, tag)?),
+        };
+        let Some(tag_id) = tag_id else {
+            return Ok(0);
+        };
+
+        let mut affected_tags = HashSet::from([tag_id]);
+        let mut changed = 0;
+        for photo_id in photo_ids {
+            let mut photo_changed = false;
+            if edit == TagEdit::Set {
+                let mut stmt = tx.prepare(
+                    "DELETE FROM photo_tags WHERE photo_id = ?1 AND tag_id != ?2 RETURNING tag_id",
+                )?;
+                let removed = stmt
+                    .query_map([photo_id, tag_id], |row| row.get::<_, i64>(0))?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                photo_changed |= !removed.is_empty();
+                affected_tags.extend(removed);
+            }
+            let rows = match edit {
+                TagEdit::Add | TagEdit::Set => tx.execute(
+                    "INSERT OR IGNORE INTO photo_tags (photo_id, tag_id) VALUES (?1, ?2)",
+                    [photo_id, tag_id],
+                )?,
+                TagEdit::Remove => tx.execute(
+                    "DELETE FROM photo_tags WHERE photo_id = ?1 AND tag_id = ?2",
+                    [photo_id, tag_id],
+                )?,
+            };
+            if photo_changed || rows > 0 {
+                changed += 1;
+            }
+        }
+
+        for tag_id in affected_tags {
+            tx.execute(
+                "INSERT INTO tag_counts (tag_id, photo_count)
+                 SELECT ?1, COUNT(*) FROM photo_tags WHERE tag_id = ?1
+                 ON CONFLICT(tag_id) DO UPDATE SET photo_count = excluded.photo_count",
+                [tag_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(changed)
+    }
+
+    pub fn photo_count(&self) -> rusqlite::Result<u64> {
+        self.conn()
+            .query_row("SELECT COUNT(*) FROM photos", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map(|count| count as u64)
+    }
+
+    /// 按类型统计照片库中的条目数
+    pub fn media_count(&self, media_type: MediaType) -> rusqlite::Result<u64> {
+        self.conn()
+            .query_row(
+                "SELECT COUNT(*) FROM photos WHERE media_type = ?1",
+                [media_type.as_str()],
+                |row| row.get::<_, i64>(0),
+            )
+            .map(|count| count as u64)
+    }
+
+    /// 位于离线相册目录中的条目数
+    pub fn offline_count(&self) -> rusqlite::Result<u64> {
+        self.conn()
+            .query_row("SELECT COUNT(*) FROM photos WHERE offline = 1", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map(|count| count as u64)
+    }
+
+    /// 所有照片的路径哈希，用于清理无主的缩略图
+    pub fn photo_file_hashes(&self) -> rusqlite::Result<HashSet<u128>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT file_hash FROM photos")?;
+        let hashes = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .filter_map(|hash| hash.ok())
+            .filter_map(|hash| u128::from_str_radix(&hash, 16).ok())
+            .collect();
+        Ok(hashes)
+    }
+
+    /// 按文件名全文搜索，每个词按前缀匹配，返回照片的完整路径与用于显示的文件名
+    ///
+    /// 全文索引中是文件名的显示形式，无法解码为 UTF-8 的字节显示为替换字符；
+    /// 返回的路径从 `photos` 表中读取，与磁盘上的文件名完全一致。
+    pub fn search_filenames(&self, query: &str) -> rusqlite::Result<Vec<(PathBuf, String)>> {
+        let query = query
+            .split_whitespace()
+            .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT roots.path, photos.path, photos.filename
+             FROM photos_fts
+             JOIN photos ON photos.id = photos_fts.rowid
+             JOIN roots ON roots.id = photos.root_id
+             WHERE photos_fts MATCH ?1
+             ORDER BY rank",
+        )?;
+        stmt.query_map([query], |row| {
+            Ok((
+                absolute_path(&row.get::<_, DbPath>(0)?.0, &row.get::<_, DbPath>(1)?.0),
+                row.get(2)?,
+            ))
+        })?
+        .collect()
+    }
+
+    /// 扩展名与内容不符的文件及其实际 MIME 类型
+    pub fn extension_mismatches(&self) -> rusqlite::Result<Vec<(PathBuf, String)>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT roots.path, photos.path, photos.mime_type
+             FROM photos JOIN roots ON roots.id = photos.root_id
+             WHERE photos.mime_type IS NOT NULL",
+        )?;
+        let mismatches = stmt
+            .query_map([], |row| {
+                Ok((
+                    absolute_path(&row.get::<_, DbPath>(0)?.0, &row.get::<_, DbPath>(1)?.0),
+                    row.get::<_, String>(2)?,
+                ))
+            })?
+            .filter_map(|row| row.ok())
+            .filter(|(path, mime_type)| {
+                MediaFormat::from_path(path).map(|format| format.info().mime_type)
+                    != Some(mime_type.as_str())
+            })
+            .collect();
+        Ok(mismatches)
+    }
+
+    /// 删除原图已不存在的照片记录，返回删除的条数
+    ///
+    /// 离线的照片，以及所在相册目录本身不可访问（例如移动硬盘尚未挂载）的照片不会被删除。
+    pub fn remove_missing_photos(&self) -> rusqlite::Result<usize> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT photos.id, roots.path, photos.path
+             FROM photos JOIN roots ON roots.id = photos.root_id
+             WHERE photos.offline = 0",
+        )?;
+        let missing: Vec<i64> = stmt
+            .query_map([], |row| {
+                let root = row.get::<_, DbPath>(1)?.0;
+                let path = absolute_path(&root, &row.get::<_, DbPath>(2)?.0);
+                Ok((row.get::<_, i64>(0)?, root, path))
+            })?
+            .filter_map(|row| row.ok())
+            .filter(|(_, root, path)| root.exists() && !path.exists())
+            .map(|(id, _, _)| id)
+            .collect();
+
+        for id in &missing {
+            conn.execute("DELETE FROM photos WHERE id = ?1", [id])?;
+        }
+        if !missing.is_empty() {
+            refresh_tag_counts(&conn)?;
+        }
+        Ok(missing.len())
+    }
+
+    /// 删除 `dir` 目录树下的照片记录、失败记录与相册目录记录，返回被删除照片的路径哈希
+    pub fn remove_photos_under(&self, dir: &Path) -> rusqlite::Result<HashSet<u128>> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let under = |path: &PathBuf| path.starts_with(dir);
+
+        let photos: Vec<(i64, String)> = tx
+            .prepare(
+                "SELECT photos.id, roots.path, photos.path, photos.file_hash
+                 FROM photos JOIN roots ON roots.id = photos.root_id",
+            )?
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    absolute_path(&row.get::<_, DbPath>(1)?.0, &row.get::<_, DbPath>(2)?.0),
+                    row.get(3)?,
+                ))
+            })?
+            .filter_map(|row| row.ok())
+            .filter(|(_, path, _)| path.starts_with(dir))
+            .map(|(id, _, hash)| (id, hash))
+            .collect();
+        let errors: Vec<PathBuf> = tx
+            .prepare("SELECT path FROM scan_errors")?
+            .query_map([], |row| row.get::<_, DbPath>(0).map(|path| path.0))?
+            .filter_map(|row| row.ok())
+            .filter(under)
+            .collect();
+
+        for (id, _) in &photos {
+            tx.execute("DELETE FROM photos WHERE id = ?1", [id])?;
+        }
+        for path in &errors {
+            tx.execute("DELETE FROM scan_errors WHERE path = ?1", [PathBytes(path)])?;
+        }
+        let roots: Vec<i64> = tx
+            .prepare("SELECT id, path FROM roots")?
+            .query_map([], |row| Ok((row.get(0)?, row.get::<_, DbPath>(1)?.0)))?
+            .filter_map(|row| row.ok())
+            .filter(|(_, path)| under(path))
+            .map(|(id, _)| id)
+            .collect();
+        for id in &roots {
+            tx.execute("DELETE FROM roots WHERE id = ?1", [id])?;
+        }
+        if !photos.is_empty() {
+            refresh_tag_counts(&tx)?;
+        }
+        tx.commit()?;
+
+        Ok(photos
+            .iter()
+            .filter_map(|(_, hash)| u128::from_str_radix(hash, 16).ok())
+            .collect())
+    }
+
+    pub fn roots(&self) -> rusqlite::Result<Vec<RootRecord>> {
+        let conn = self.conn();
+        let mut stmt =
+            conn.prepare("SELECT id, path, volume_id, online, last_seen FROM roots ORDER BY id")?;
+        stmt.query_map([], |row| {
+            Ok(RootRecord {
+                id: row.get(0)?,
+                path: row.get::<_, DbPath>(1)?.0,
+                volume_id: row.get(2)?,
+                online: row.get(3)?,
+                last_seen: row.get(4)?,
+            })
+        })?
+        .collect()
+    }
+
+    /// 登记新的相册目录，返回其 id
+    ///
+    /// 之前以子目录登记的相册目录会合并进来，其中照片的相对路径随之改写。
+    pub fn insert_root(
+        &self,
+        path: &Path,
+        volume_id: Option<&str>,
+        now: i64,
+    ) -> rusqlite::Result<i64> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let id = insert_root(&tx, path, volume_id, Some(now))?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    pub fn set_root_volume_id(&self, id: i64, volume_id: &str) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "UPDATE roots SET volume_id = ?2 WHERE id = ?1",
+            params![id, volume_id],
+        )?;
+        Ok(())
+    }
+
+    /// 更新在线相册目录的 `last_seen`
+    pub fn touch_root(&self, id: i64, now: i64) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "UPDATE roots SET last_seen = ?2 WHERE id = ?1",
+            params![id, now],
+        )?;
+        Ok(())
+    }
+
+    /// 标记相册目录及其中的照片在线或离线，返回受影响的照片数
+    ///
+    /// 在线时同时更新 `last_seen`。
+    pub fn set_root_online(
+        &self,
+        root: &RootRecord,
+        online: bool,
+        now: i64,
+    ) -> rusqlite::Result<usize> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+
+        if online {
+            tx.execute(
+                "UPDATE roots SET online = 1, last_seen = ?2 WHERE id = ?1",
+                params![root.id, now],
+            )?;
+        } else {
+            tx.execute("UPDATE roots SET online = 0 WHERE id = ?1", [root.id])?;
+        }
+
+        let count = tx.execute(
+            "UPDATE photos SET offline = ?2 WHERE root_id = ?1",
+            params![root.id, !online],
+        )?;
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// 把相册目录及其中的失败记录从 `root.path` 移动到 `to`，照片保存的是相对路径，
+    /// 标签等关联保持不变
+    ///
+    /// 路径哈希随路径改变，返回每张照片的旧哈希与新哈希，供调用方迁移缩略图。
+    pub fn move_root(&self, root: &RootRecord, to: &Path) -> rusqlite::Result<Vec<(u128, u128)>> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "UPDATE roots SET path = ?2 WHERE id = ?1",
+            params![root.id, PathBytes(to)],
+        )?;
+
+        let photos: Vec<(i64, DbPath, String)> = tx
+            .prepare("SELECT id, path, file_hash FROM photos WHERE root_id = ?1")?
+            .query_map([root.id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .filter_map(|row| row.ok())
+            .collect();
+        let mut hashes = Vec::with_capacity(photos.len());
+        for (id, relative, old_hash) in &photos {
+            let new_hash = MeshThumbnail::generate_file_hash(&absolute_path(to, &relative.0));
+            tx.execute(
+                "UPDATE photos SET file_hash = ?2 WHERE id = ?1",
+                params![id, format_hash(new_hash)],
+            )?;
+            if let Ok(old_hash) = u128::from_str_radix(old_hash, 16) {
+                hashes.push((old_hash, new_hash));
+            }
+        }
+
+        let errors: Vec<(PathBuf, PathBuf)> = tx
+            .prepare("SELECT path FROM scan_errors")?
+            .query_map([], |row| row.get::<_, DbPath>(0).map(|path| path.0))?
+            .filter_map(|row| row.ok())
+            .filter_map(|path| {
+                let relative = path.strip_prefix(&root.path).ok()?;
+                let moved = to.join(relative);
+                Some((path, moved))
+            })
+            .collect();
+        for (path, moved) in &errors {
+            tx.execute(
+                "UPDATE scan_errors SET path = ?2 WHERE path = ?1",
+                params![PathBytes(path), PathBytes(moved)],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(hashes)
+    }
+
+    /// 记录处理失败的文件，已存在时累加重试次数
+    pub fn record_scan_error(&self, error: &ScanErrorRecord) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "INSERT INTO scan_errors (path, kind, message, retry_count, source_size, source_modified, last_attempt)
+             VALUES (?1, ?2, ?3, 0, ?4, ?5, ?6)
+             ON CONFLICT(path) DO UPDATE SET
+                kind = excluded.kind,
+                message = excluded.message,
+                retry_count = retry_count + 1,
+                source_size = excluded.source_size,
+                source_modified = excluded.source_modified,
+                last_attempt = excluded.last_attempt",
+            params![
+                PathBytes(&error.path),
+                error.kind.as_str(),
+                error.message,
+                error.source_size as i64,
+                error.source_modified as i64,
+                error.last_attempt,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn clear_scan_error(&self, path: &Path) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "DELETE FROM scan_errors WHERE path = ?1",
+            [PathBytes(path)],
+        )?;
+        Ok(())
+    }
+
+    /// 文件是否在未修改的情况下失败过
+    pub fn has_scan_error(
+        &self,
+        path: &Path,
+        source_size: u64,
+        source_modified: u64,
+    ) -> rusqlite::Result<bool> {
+        self.conn()
+            .query_row(
+                "SELECT 1 FROM scan_errors
+                 WHERE path = ?1 AND source_size = ?2 AND source_modified = ?3",
+                params![
+                    PathBytes(path),
+                    source_size as i64,
+                    source_modified as i64
+                ],
+                |_| Ok(()),
+            )
+            .optional()
+            .map(|row| row.is_some())
+    }
+
+    pub fn scan_errors(&self) -> rusqlite::Result<Vec<ScanErrorRecord>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT path, kind, message, retry_count, source_size, source_modified, last_attempt
+             FROM scan_errors ORDER BY path",
+        )?;
+        stmt.query_map([], |row| {
+            Ok(ScanErrorRecord {
+                path: row.get::<_, DbPath>(0)?.0,
+                kind: ScanErrorKind::parse(&row.get::<_, String>(1)?),
+                message: row.get(2)?,
+                retry_count: row.get(3)?,
+                source_size: row.get::<_, i64>(4)? as u64,
+                source_modified: row.get::<_, i64>(5)? as u64,
+                last_attempt: row.get(6)?,
+            })
+        })?
+        .collect()
+    }
 }
 
-fn init_execute(conn: &Connection) -> rusqlite::Result<()> {
+/// 从数据库读出的路径
+///
+/// 路径以原始字节保存在 BLOB 中，Linux 上任意字节的文件名都能原样读回；
+/// 旧版本以 TEXT 保存的路径同样可以读取。
+struct DbPath(PathBuf);
+
+impl FromSql for DbPath {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Blob(bytes) | ValueRef::Text(bytes) => Ok(Self(path_from_bytes(bytes))),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// 写入数据库的路径，见 [`DbPath`]
+struct PathBytes<'a>(&'a Path);
+
+impl ToSql for PathBytes<'_> {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(path_to_bytes(self.0)))
+    }
+}
+
+#[cfg(unix)]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+// Windows 的文件名是 UTF-16，实际使用中几乎不会出现无法转换为 UTF-8 的名称
+#[cfg(not(unix))]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn format_hash(file_hash: u128) -> String {
+    format!("{:032x}", file_hash)
+}
+
+/// 由相册目录与相对路径拼出照片的绝对路径
+fn absolute_path(root: &Path, relative: &Path) -> PathBuf {
+    if relative.as_os_str().is_empty() {
+        root.to_path_buf()
+    } else {
+        root.join(relative)
+    }
+}
+
+/// `path` 相对 `root` 的路径，不在 `root` 下时原样返回
+fn relative_path(path: &Path, root: &Path) -> PathBuf {
+    path.strip_prefix(root).unwrap_or(path).to_path_buf()
+}
+
+/// 查找名为 `name` 的标签，不存在时连同上级标签一起创建，返回标签 id
+fn ensure_tag(conn: &Connection, name: &str) -> rusqlite::Result<i64> {
+    let existing = conn
+        .query_row("SELECT id FROM tags WHERE name = ?1", [name], |row| {
+            row.get(0)
+        })
+        .optional()?;
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+
+    let parent_id = match name.rsplit_once('/') {
+        Some((parent, _)) => Some(ensure_tag(conn, parent)?),
+        None => None,
+    };
+    conn.execute(
+        "INSERT INTO tags (name, parent_id) VALUES (?1, ?2)",
+        params![name, parent_id],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// 照片被删除后（`photo_tags` 随之级联删除）重新统计每个标签的照片数
+fn refresh_tag_counts(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "DELETE FROM tag_counts;
+         INSERT INTO tag_counts (tag_id, photo_count)
+         SELECT tag_id, COUNT(*) FROM photo_tags GROUP BY tag_id;",
+    )
+}
+
+/// 查找包含 `path` 的最深的相册目录，返回其 id 与 `path` 的相对路径
+fn find_root(conn: &Connection, path: &Path) -> rusqlite::Result<Option<(i64, PathBuf)>> {
+    let mut stmt = conn.prepare_cached("SELECT id, path FROM roots")?;
+    let root = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, DbPath>(1)?.0))
+        })?
+        .filter_map(|row| row.ok())
+        .filter(|(_, root)| path.starts_with(root))
+        .max_by_key(|(_, root)| root.components().count());
+    Ok(root.map(|(id, root)| (id, relative_path(path, &root))))
+}
+
+/// 登记相册目录，并把之前登记在其下的相册目录中的照片合并进来
+///
+/// 上级相册目录中位于新目录之下的照片同样移到新目录，否则 [`find_root`] 按最深的目录
+/// 查找时找不到这些照片。
+fn insert_root(
+    conn: &Connection,
+    path: &Path,
+    volume_id: Option<&str>,
+    last_seen: Option<i64>,
+) -> rusqlite::Result<i64> {
+    conn.execute(
+        "INSERT INTO roots (path, volume_id, online, last_seen) VALUES (?1, ?2, 1, ?3)",
+        params![PathBytes(path), volume_id, last_seen],
+    )?;
+    let id = conn.last_insert_rowid();
+
+    let nested: Vec<(i64, PathBuf)> = conn
+        .prepare("SELECT id, path FROM roots WHERE id != ?1")?
+        .query_map([id], |row| {
+            Ok((row.get(0)?, row.get::<_, DbPath>(1)?.0))
+        })?
+        .filter_map(|row| row.ok())
+        .filter(|(_, nested)| nested.starts_with(path))
+        .collect();
+    for (nested_id, nested_path) in &nested {
+        let prefix = relative_path(nested_path, path);
+        let photos: Vec<(i64, DbPath)> = conn
+            .prepare("SELECT id, path FROM photos WHERE root_id = ?1")?
+            .query_map([nested_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .filter_map(|row| row.ok())
+            .collect();
+        for (photo_id, relative) in &photos {
+            conn.execute(
+                "UPDATE photos SET root_id = ?2, path = ?3 WHERE id = ?1",
+                params![photo_id, id, PathBytes(&prefix.join(&relative.0))],
+            )?;
+        }
+        conn.execute("DELETE FROM roots WHERE id = ?1", [nested_id])?;
+    }
+
+    let ancestors: Vec<(i64, PathBuf)> = conn
+        .prepare("SELECT id, path FROM roots WHERE id != ?1")?
+        .query_map([id], |row| {
+            Ok((row.get(0)?, row.get::<_, DbPath>(1)?.0))
+        })?
+        .filter_map(|row| row.ok())
+        .filter(|(_, ancestor)| path.starts_with(ancestor))
+        .collect();
+    for (ancestor_id, ancestor_path) in &ancestors {
+        let prefix = relative_path(path, ancestor_path);
+        let photos: Vec<(i64, PathBuf)> = conn
+            .prepare("SELECT id, path FROM photos WHERE root_id = ?1")?
+            .query_map([ancestor_id], |row| {
+                Ok((row.get(0)?, row.get::<_, DbPath>(1)?.0))
+            })?
+            .filter_map(|row| row.ok())
+            .filter(|(_, relative)| relative.starts_with(&prefix))
+            .collect();
+        for (photo_id, relative) in &photos {
+            conn.execute(
+                "UPDATE photos SET root_id = ?2, path = ?3 WHERE id = ?1",
+                params![photo_id, id, PathBytes(&relative_path(relative, &prefix))],
+            )?;
+        }
+    }
+    Ok(id)
+}
+
+/// 数据库结构版本，记录在 `PRAGMA user_version` 中
+const SCHEMA_VERSION: i32 = 7;
+
+/// 将旧版本的数据库升级到 `SCHEMA_VERSION`，新建的数据库由 `init_execute` 直接建表
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'photos')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Ok(());
+    }
+
+    let version: i32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version < 1 {
+        // 普通 fts5 表不支持 'delete' 命令，删除旧触发器后由 init_execute 重建
+        conn.execute_batch(
+            "DROP TRIGGER IF EXISTS photos_fts_au;
+             DROP TRIGGER IF EXISTS photos_fts_ad;",
+        )?;
+    }
+    if version < 2 {
+        // 照片库开始收录视频
+        conn.execute_batch(
+            "ALTER TABLE photos ADD COLUMN media_type TEXT NOT NULL DEFAULT 'photo';
+             ALTER TABLE photos ADD COLUMN duration_ms INTEGER;
+             ALTER TABLE photos ADD COLUMN codec TEXT;",
+        )?;
+    }
+    if version < 3 {
+        // 旧记录的 MIME 类型为空，下次扫描时重新识别
+        conn.execute("ALTER TABLE photos ADD COLUMN mime_type TEXT", [])?;
+    }
+    if version < 4 {
+        // 清空 MIME 类型使照片在下次扫描时按 ICC 配置文件重新生成缩略图
+        conn.execute_batch(
+            "ALTER TABLE photos ADD COLUMN color_space TEXT;
+             UPDATE photos SET mime_type = NULL WHERE media_type = 'photo';",
+        )?;
+    }
+    if version < 5 {
+        // 相册目录所在的卷不可访问时，其中的照片标记为离线而不是删除
+        conn.execute(
+            "ALTER TABLE photos ADD COLUMN offline INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+    }
+    if version < 6 {
+        // 照片改为保存相对相册目录的路径，重建 photos 表以更换唯一约束，id 保持不变；
+        // 外键检查只能在事务外关闭
+        let foreign_keys: bool = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+        conn.pragma_update(None, "foreign_keys", false)?;
+        let tx = conn.unchecked_transaction()?;
+        create_roots_table(&tx)?;
+        // 保持 photo_tags 等表的外键仍然指向 photos
+        tx.execute_batch(
+            "PRAGMA legacy_alter_table = ON;
+             ALTER TABLE photos RENAME TO photos_v5;
+             PRAGMA legacy_alter_table = OFF;",
+        )?;
+        create_photos_table(&tx)?;
+        tx.execute(
+            "INSERT INTO photos (id, root_id, path, file_hash, filename, width, height, size,
+                                 created_at, modified_at, media_type, mime_type, color_space,
+                                 duration_ms, codec, offline)
+             SELECT id, 0, path, file_hash, filename, width, height, size, created_at,
+                    modified_at, media_type, mime_type, color_space, duration_ms, codec, offline
+             FROM photos_v5",
+            [],
+        )?;
+        tx.execute("DROP TABLE photos_v5", [])?;
+
+        let photos: Vec<(i64, PathBuf)> = tx
+            .prepare("SELECT id, path FROM photos")?
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get::<_, DbPath>(1)?.0))
+            })?
+            .filter_map(|row| row.ok())
+            .collect();
+        for (id, path) in &photos {
+            let (root_id, relative) = match find_root(&tx, path)? {
+                Some(found) => found,
+                None => {
+                    let dir = path.parent().unwrap_or(Path::new(""));
+                    (insert_root(&tx, dir, None, None)?, relative_path(path, dir))
+                }
+            };
+            tx.execute(
+                "UPDATE photos SET root_id = ?2, path = ?3 WHERE id = ?1",
+                params![id, root_id, PathBytes(&relative)],
+            )?;
+        }
+        tx.commit()?;
+        conn.pragma_update(None, "foreign_keys", foreign_keys)?;
+    }
+    if version < 7 {
+        // 路径改为以原始字节保存，旧的 TEXT 值转换为 BLOB 以便与新写入的路径比较；
+        // 早于失败记录的数据库中还没有 scan_errors 表
+        create_scan_errors_table(conn)?;
+        conn.execute_batch(
+            "UPDATE photos SET path = CAST(path AS BLOB);
+             UPDATE roots SET path = CAST(path AS BLOB);
+             UPDATE scan_errors SET path = CAST(path AS BLOB);",
+        )?;
+    }
+    Ok(())
+}
+
+fn create_photos_table(conn: &Connection) -> rusqlite::Result<()> {
+    // path 为相对 root_id 所指相册目录的路径
     conn.execute(
         "CREATE TABLE IF NOT EXISTS photos (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
-            path TEXT NOT NULL UNIQUE,
+            root_id INTEGER NOT NULL,
+            path BLOB NOT NULL,
             file_hash TEXT NOT NULL UNIQUE,
             filename TEXT NOT NULL,
             width INTEGER NOT NULL,
             height INTEGER NOT NULL,
             size INTEGER NOT NULL,
             created_at DATETIME NOT NULL,
-            modified_at DATETIME NOT NULL
+            modified_at DATETIME NOT NULL,
+            media_type TEXT NOT NULL DEFAULT 'photo',
+            mime_type TEXT,
+            color_space TEXT,
+            duration_ms INTEGER,
+            codec TEXT,
+            offline INTEGER NOT NULL DEFAULT 0,
+            UNIQUE (root_id, path),
+            FOREIGN KEY (root_id) REFERENCES roots(id)
         )",
         [],
     )?;
+    Ok(())
+}
+
+fn create_roots_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS roots (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            path BLOB NOT NULL UNIQUE,
+            volume_id TEXT UNIQUE,
+            online INTEGER NOT NULL DEFAULT 1,
+            last_seen DATETIME
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn create_scan_errors_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS scan_errors (
+            path BLOB PRIMARY KEY,
+            kind TEXT NOT NULL,
+            message TEXT NOT NULL,
+            retry_count INTEGER NOT NULL DEFAULT 0,
+            source_size INTEGER NOT NULL,
+            source_modified INTEGER NOT NULL,
+            last_attempt DATETIME NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn init_execute(conn: &Connection) -> rusqlite::Result<()> {
+    create_roots_table(conn)?;
+    create_photos_table(conn)?;
     conn.execute(
         "CREATE TABLE IF NOT EXISTS tags (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -86,17 +1186,18 @@ fn init_execute(conn: &Connection) -> rusqlite::Result<()> {
     )?;
     conn.execute(
         "CREATE TRIGGER IF NOT EXISTS photos_fts_au AFTER UPDATE OF filename ON photos BEGIN
-          INSERT INTO photos_fts(photos_fts, rowid, filename) VALUES ('delete', old.id, old.filename);
+          DELETE FROM photos_fts WHERE rowid = old.id;
           INSERT INTO photos_fts(rowid, filename) VALUES (new.id, new.filename);
         END",
         [],
     )?;
     conn.execute(
         "CREATE TRIGGER IF NOT EXISTS photos_fts_ad AFTER DELETE ON photos BEGIN
-          INSERT INTO photos_fts(photos_fts, rowid, filename) VALUES ('delete', old.id, old.filename);
+          DELETE FROM photos_fts WHERE rowid = old.id;
         END",
         [],
     )?;
+    create_scan_errors_table(conn)?;
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_photo_tags_tag_id ON photo_tags (tag_id)",
         [],
@@ -109,5 +1210,509 @@ fn init_execute(conn: &Connection) -> rusqlite::Result<()> {
         "CREATE UNIQUE INDEX IF NOT EXISTS idx_photos_file_hash ON photos (file_hash)",
         [],
     )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_photos_media_type_created_at ON photos (media_type, created_at)",
+        [],
+    )?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 最初版本的表结构，`user_version` 为 0
+    const BASELINE_SCHEMA: &str = "
+        CREATE TABLE photos (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            path TEXT NOT NULL UNIQUE,
+            file_hash TEXT NOT NULL UNIQUE,
+            filename TEXT NOT NULL,
+            width INTEGER NOT NULL,
+            height INTEGER NOT NULL,
+            size INTEGER NOT NULL,
+            created_at DATETIME NOT NULL,
+            modified_at DATETIME NOT NULL
+        );
+        CREATE TABLE tags (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            parent_id INTEGER,
+            tag_color_hex  DEFAULT '#FFFFFF',
+            FOREIGN KEY (parent_id) REFERENCES tags(id) ON DELETE SET NULL
+        );
+        CREATE TABLE photo_tags (
+            photo_id INTEGER NOT NULL,
+            tag_id INTEGER NOT NULL,
+            PRIMARY KEY (photo_id, tag_id),
+            FOREIGN KEY (photo_id) REFERENCES photos(id) ON DELETE CASCADE,
+            FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+        );
+        CREATE TABLE tag_counts (
+            tag_id INTEGER PRIMARY KEY,
+            photo_count INTEGER NOT NULL,
+            FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+        );
+        CREATE VIRTUAL TABLE photos_fts USING fts5(photo_id UNINDEXED, filename);
+        CREATE TRIGGER photos_fts_ai AFTER INSERT ON photos BEGIN
+          INSERT INTO photos_fts(rowid, filename) VALUES (new.id, new.filename);
+        END;
+        CREATE UNIQUE INDEX idx_photos_file_hash ON photos (file_hash);
+    ";
+
+    fn insert_baseline_photo(conn: &Connection, path: &str, file_hash: &str) {
+        let filename = Path::new(path).file_name().unwrap().to_str().unwrap();
+        conn.execute(
+            "INSERT INTO photos (path, file_hash, filename, width, height, size, created_at,
+                                 modified_at)
+             VALUES (?1, ?2, ?3, 640, 480, 1024, 1, 2)",
+            params![path, file_hash, filename],
+        )
+        .unwrap();
+    }
+
+    fn open_database() -> (tempfile::TempDir, MeshDatabase) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MeshDatabase::init(dir.path().join("mesh.db"));
+        (dir, db)
+    }
+
+    fn photo(path: &str) -> PhotoRecord {
+        let path = PathBuf::from(path);
+        PhotoRecord {
+            file_hash: MeshThumbnail::generate_file_hash(&path),
+            filename: path.file_name().unwrap().to_string_lossy().into_owned(),
+            path,
+            width: 640,
+            height: 480,
+            size: 1024,
+            created_at: 1,
+            modified_at: 2,
+            media_type: MediaType::Photo,
+            mime_type: "image/jpeg".to_owned(),
+            color_space: None,
+            duration_ms: None,
+            codec: None,
+        }
+    }
+
+    fn root_paths(db: &MeshDatabase) -> Vec<PathBuf> {
+        db.roots()
+            .unwrap()
+            .into_iter()
+            .map(|root| root.path)
+            .collect()
+    }
+
+    #[test]
+    fn migrates_baseline_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("mesh.db");
+        {
+            let conn = Connection::open(&db_path).unwrap();
+            conn.execute_batch(BASELINE_SCHEMA).unwrap();
+            insert_baseline_photo(&conn, "/photos/2024/beach.jpg", "1a");
+            insert_baseline_photo(&conn, "/photos/2024/city.jpg", "2b");
+            insert_baseline_photo(&conn, "/archive/old.png", "3c");
+            conn.execute_batch(
+                "INSERT INTO tags (name) VALUES ('trip');
+                 INSERT INTO photo_tags (photo_id, tag_id) VALUES (1, 1);
+                 INSERT INTO tag_counts (tag_id, photo_count) VALUES (1, 1);",
+            )
+            .unwrap();
+        }
+
+        let db = MeshDatabase::init(&db_path);
+        let version: i32 = db
+            .conn()
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+
+        assert_eq!(db.photo_count().unwrap(), 3);
+        for path in [
+            "/photos/2024/beach.jpg",
+            "/photos/2024/city.jpg",
+            "/archive/old.png",
+        ] {
+            assert!(db.contains_photo(Path::new(path)).unwrap(), "{}", path);
+        }
+
+        // 每张照片所在的目录成为相册目录
+        let mut roots: Vec<PathBuf> = db.roots().unwrap().into_iter().map(|r| r.path).collect();
+        roots.sort();
+        assert_eq!(
+            roots,
+            vec![PathBuf::from("/archive"), PathBuf::from("/photos/2024")]
+        );
+
+        assert_eq!(
+            db.photo_tags(Path::new("/photos/2024/beach.jpg")).unwrap(),
+            vec!["trip".to_owned()]
+        );
+        let results = db.search_filenames("city").unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, PathBuf::from("/photos/2024/city.jpg"));
+
+        // 迁移时补建的失败记录表可以正常使用
+        let record = ScanErrorRecord {
+            path: PathBuf::from("/photos/2024/broken.jpg"),
+            kind: ScanErrorKind::Decode,
+            message: "truncated".to_owned(),
+            retry_count: 0,
+            source_size: 10,
+            source_modified: 20,
+            last_attempt: 30,
+        };
+        db.record_scan_error(&record).unwrap();
+        assert_eq!(db.scan_errors().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn reopening_migrated_database_is_a_no_op() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("mesh.db");
+        {
+            let conn = Connection::open(&db_path).unwrap();
+            conn.execute_batch(BASELINE_SCHEMA).unwrap();
+            insert_baseline_photo(&conn, "/photos/a.jpg", "1a");
+        }
+        drop(MeshDatabase::init(&db_path));
+
+        let db = MeshDatabase::init(&db_path);
+        assert_eq!(db.photo_count().unwrap(), 1);
+        assert_eq!(db.roots().unwrap().len(), 1);
+        assert!(db.contains_photo(Path::new("/photos/a.jpg")).unwrap());
+    }
+
+    #[test]
+    fn insert_root_takes_photos_from_ancestor_roots() {
+        let (_dir, db) = open_database();
+        let data = db.insert_root(Path::new("/data"), None, 100).unwrap();
+        db.upsert_photo(&photo("/data/a.jpg")).unwrap();
+        db.upsert_photo(&photo("/data/trip/x.jpg")).unwrap();
+        db.upsert_photo(&photo("/data/trip/day1/y.jpg")).unwrap();
+        db.upsert_photo(&photo("/data/trips/z.jpg")).unwrap();
+        db.edit_tags("kyoto", &[PathBuf::from("/data/trip/x.jpg")], TagEdit::Add)
+            .unwrap();
+
+        let trip = db.insert_root(Path::new("/data/trip"), None, 100).unwrap();
+        let relative = |root_id: i64| -> Vec<PathBuf> {
+            db.conn()
+                .prepare("SELECT path FROM photos WHERE root_id = ?1 ORDER BY path")
+                .unwrap()
+                .query_map([root_id], |row| row.get::<_, DbPath>(0).map(|path| path.0))
+                .unwrap()
+                .collect::<rusqlite::Result<_>>()
+                .unwrap()
+        };
+        assert_eq!(
+            relative(data),
+            [PathBuf::from("a.jpg"), PathBuf::from("trips/z.jpg")]
+        );
+        assert_eq!(
+            relative(trip),
+            [PathBuf::from("day1/y.jpg"), PathBuf::from("x.jpg")]
+        );
+
+        // 之后的扫描按最深的目录找到同一条记录，而不是插入重复的照片
+        db.upsert_photo(&photo("/data/trip/x.jpg")).unwrap();
+        assert_eq!(db.photo_count().unwrap(), 4);
+        assert_eq!(
+            db.photo_tags(Path::new("/data/trip/x.jpg")).unwrap(),
+            ["kyoto"]
+        );
+    }
+
+    #[test]
+    fn insert_root_merges_nested_roots() {
+        let (_dir, db) = open_database();
+        // 不在任何相册目录中的照片以所在目录登记相册目录
+        db.upsert_photo(&photo("/photos/2024/a.jpg")).unwrap();
+        db.upsert_photo(&photo("/photos/2024/kyoto/b.jpg")).unwrap();
+        db.upsert_photo(&photo("/photos/2023/c.jpg")).unwrap();
+        db.upsert_photo(&photo("/other/d.jpg")).unwrap();
+        db.edit_tags("trip", &[PathBuf::from("/photos/2024/a.jpg")], TagEdit::Add)
+            .unwrap();
+        assert_eq!(
+            root_paths(&db),
+            [
+                PathBuf::from("/photos/2024"),
+                PathBuf::from("/photos/2023"),
+                PathBuf::from("/other")
+            ]
+        );
+
+        let id = db
+            .insert_root(Path::new("/photos"), Some("volume"), 100)
+            .unwrap();
+        assert_eq!(
+            root_paths(&db),
+            [PathBuf::from("/other"), PathBuf::from("/photos")]
+        );
+        let relative: Vec<PathBuf> = db
+            .conn()
+            .prepare("SELECT path FROM photos WHERE root_id = ?1 ORDER BY path")
+            .unwrap()
+            .query_map([id], |row| row.get::<_, DbPath>(0).map(|path| path.0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            relative,
+            [
+                PathBuf::from("2023/c.jpg"),
+                PathBuf::from("2024/a.jpg"),
+                PathBuf::from("2024/kyoto/b.jpg")
+            ]
+        );
+
+        // 照片 id 不变，标签保留
+        assert_eq!(db.photo_count().unwrap(), 4);
+        assert_eq!(
+            db.photo_tags(Path::new("/photos/2024/a.jpg")).unwrap(),
+            ["trip"]
+        );
+        assert_eq!(db.tags_with_counts().unwrap(), [("trip".to_owned(), 1)]);
+        // 之后的照片登记到最深的相册目录中
+        db.upsert_photo(&photo("/photos/2024/e.jpg")).unwrap();
+        assert_eq!(root_paths(&db).len(), 2);
+        assert!(db.contains_photo(Path::new("/photos/2024/e.jpg")).unwrap());
+
+        // 卷标识唯一
+        assert!(
+            db.insert_root(Path::new("/clone"), Some("volume"), 100)
+                .is_err()
+        );
+        assert_eq!(root_paths(&db).len(), 2);
+    }
+
+    #[test]
+    fn offline_roots_keep_photos() {
+        let (_dir, db) = open_database();
+        db.upsert_photo(&photo("/media/disk/a.jpg")).unwrap();
+        db.upsert_photo(&photo("/media/disk/b.jpg")).unwrap();
+        db.upsert_photo(&photo("/home/c.jpg")).unwrap();
+        let root = db.roots().unwrap().remove(0);
+        assert_eq!(root.path, PathBuf::from("/media/disk"));
+
+        assert_eq!(db.set_root_online(&root, false, 10).unwrap(), 2);
+        let offline = db.roots().unwrap().remove(0);
+        assert!(!offline.online);
+        assert_eq!(db.offline_count().unwrap(), 2);
+        assert_eq!(db.photo_count().unwrap(), 3);
+
+        assert_eq!(db.set_root_online(&root, true, 20).unwrap(), 2);
+        let online = db.roots().unwrap().remove(0);
+        assert!(online.online);
+        assert_eq!(online.last_seen, Some(20));
+        assert_eq!(db.offline_count().unwrap(), 0);
+    }
+
+    #[test]
+    fn move_root_rewrites_hashes_and_errors() {
+        let (_dir, db) = open_database();
+        db.upsert_photo(&photo("/old/photos/a.jpg")).unwrap();
+        db.upsert_photo(&photo("/old/photos/trip/b.jpg")).unwrap();
+        db.upsert_photo(&photo("/elsewhere/c.jpg")).unwrap();
+        db.edit_tags(
+            "trip",
+            &[PathBuf::from("/old/photos/trip/b.jpg")],
+            TagEdit::Add,
+        )
+        .unwrap();
+        db.record_scan_error(&ScanErrorRecord {
+            path: PathBuf::from("/old/photos/broken.jpg"),
+            kind: ScanErrorKind::Decode,
+            message: "truncated".to_owned(),
+            retry_count: 0,
+            source_size: 10,
+            source_modified: 20,
+            last_attempt: 30,
+        })
+        .unwrap();
+        let original = db
+            .photo(Path::new("/old/photos/trip/b.jpg"))
+            .unwrap()
+            .unwrap();
+        let root = db.roots().unwrap().remove(0);
+
+        let mut hashes = db.move_root(&root, Path::new("/new/photos")).unwrap();
+        hashes.sort();
+        let hash = |path: &str| MeshThumbnail::generate_file_hash(Path::new(path));
+        let mut expected = vec![
+            (hash("/old/photos/a.jpg"), hash("/new/photos/a.jpg")),
+            (
+                hash("/old/photos/trip/b.jpg"),
+                hash("/new/photos/trip/b.jpg"),
+            ),
+        ];
+        expected.sort();
+        assert_eq!(hashes, expected);
+
+        assert!(!db.contains_photo(Path::new("/old/photos/a.jpg")).unwrap());
+        let moved = db
+            .photo(Path::new("/new/photos/trip/b.jpg"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(moved.file_hash, hash("/new/photos/trip/b.jpg"));
+        assert_eq!(moved.filename, original.filename);
+        assert_eq!(
+            db.photo_tags(Path::new("/new/photos/trip/b.jpg")).unwrap(),
+            ["trip"]
+        );
+        assert!(
+            db.photo_file_hashes()
+                .unwrap()
+                .contains(&hash("/new/photos/a.jpg"))
+        );
+        // 其他相册目录不受影响
+        assert!(db.contains_photo(Path::new("/elsewhere/c.jpg")).unwrap());
+        assert_eq!(
+            root_paths(&db),
+            [PathBuf::from("/new/photos"), PathBuf::from("/elsewhere")]
+        );
+        assert_eq!(
+            db.scan_errors().unwrap()[0].path,
+            PathBuf::from("/new/photos/broken.jpg")
+        );
+    }
+
+    fn tags(db: &MeshDatabase, path: &str) -> Vec<String> {
+        db.photo_tags(Path::new(path)).unwrap()
+    }
+
+    fn counts(db: &MeshDatabase) -> Vec<(String, u64)> {
+        db.tags_with_counts().unwrap()
+    }
+
+    #[test]
+    fn edit_tags_add_remove_and_set() {
+        let (_dir, db) = open_database();
+        for path in ["/photos/a.jpg", "/photos/b.jpg", "/photos/c.jpg"] {
+            db.upsert_photo(&photo(path)).unwrap();
+        }
+        let paths = |names: &[&str]| -> Vec<PathBuf> {
+            names
+                .iter()
+                .map(|name| Path::new("/photos").join(name))
+                .collect()
+        };
+
+        assert_eq!(
+            db.edit_tags("people/alice", &paths(&["a.jpg", "b.jpg"]), TagEdit::Add)
+                .unwrap(),
+            2
+        );
+        // 已有的标签不重复添加，不在照片库中的路径被忽略
+        assert_eq!(
+            db.edit_tags(
+                "people/alice",
+                &paths(&["a.jpg", "missing.jpg"]),
+                TagEdit::Add
+            )
+            .unwrap(),
+            0
+        );
+        db.edit_tags("trip/2024", &paths(&["a.jpg", "c.jpg"]), TagEdit::Add)
+            .unwrap();
+        // 上级标签被创建，但没有直接打在照片上
+        assert_eq!(
+            counts(&db),
+            [
+                ("people".to_owned(), 0),
+                ("people/alice".to_owned(), 2),
+                ("trip".to_owned(), 0),
+                ("trip/2024".to_owned(), 2),
+            ]
+        );
+        let parent: Option<i64> = db
+            .conn()
+            .query_row(
+                "SELECT child.parent_id = parent.id FROM tags child, tags parent
+                 WHERE child.name = 'trip/2024' AND parent.name = 'trip'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(parent, Some(1));
+
+        // 不存在的标签不会因为移除而被创建
+        assert_eq!(
+            db.edit_tags("unknown", &paths(&["a.jpg"]), TagEdit::Remove)
+                .unwrap(),
+            0
+        );
+        assert_eq!(db.tag_count().unwrap(), 4);
+        assert_eq!(
+            db.edit_tags("people/alice", &paths(&["b.jpg", "c.jpg"]), TagEdit::Remove)
+                .unwrap(),
+            1
+        );
+        assert_eq!(tags(&db, "/photos/b.jpg"), Vec::<String>::new());
+        assert_eq!(counts(&db)[1], ("people/alice".to_owned(), 1));
+    }
+
+    #[test]
+    fn edit_tags_set_replaces_other_tags() {
+        let (_dir, db) = open_database();
+        for path in ["/photos/a.jpg", "/photos/b.jpg", "/photos/c.jpg"] {
+            db.upsert_photo(&photo(path)).unwrap();
+        }
+        let a = PathBuf::from("/photos/a.jpg");
+        let b = PathBuf::from("/photos/b.jpg");
+        let c = PathBuf::from("/photos/c.jpg");
+        db.edit_tags("red", &[a.clone(), b.clone()], TagEdit::Add)
+            .unwrap();
+        db.edit_tags("blue", &[a.clone(), c.clone()], TagEdit::Add)
+            .unwrap();
+        db.edit_tags("green", std::slice::from_ref(&b), TagEdit::Add)
+            .unwrap();
+
+        // a 的 red 与 blue 被替换；b 已有 green，只移除 red；c 只有 blue，加上 green
+        assert_eq!(
+            db.edit_tags("green", &[a.clone(), b.clone(), c.clone()], TagEdit::Set)
+                .unwrap(),
+            3
+        );
+        for path in ["/photos/a.jpg", "/photos/b.jpg", "/photos/c.jpg"] {
+            assert_eq!(tags(&db, path), ["green"], "{path}");
+        }
+        // 被替换的标签的计数同时更新
+        assert_eq!(
+            counts(&db),
+            [
+                ("blue".to_owned(), 0),
+                ("green".to_owned(), 3),
+                ("red".to_owned(), 0),
+            ]
+        );
+
+        // 已经只有这一个标签时没有变化
+        assert_eq!(
+            db.edit_tags("green", std::slice::from_ref(&a), TagEdit::Set)
+                .unwrap(),
+            0
+        );
+        // 设置新的层级标签
+        assert_eq!(db.edit_tags("places/kyoto", &[a], TagEdit::Set).unwrap(), 1);
+        assert_eq!(tags(&db, "/photos/a.jpg"), ["places/kyoto"]);
+        assert_eq!(tags(&db, "/photos/b.jpg"), ["green"]);
+        assert_eq!(counts(&db)[1], ("green".to_owned(), 2));
+    }
+
+    #[test]
+    fn removing_photos_refreshes_tag_counts() {
+        let (_dir, db) = open_database();
+        db.upsert_photo(&photo("/photos/a.jpg")).unwrap();
+        db.upsert_photo(&photo("/photos/trip/b.jpg")).unwrap();
+        let all = [
+            PathBuf::from("/photos/a.jpg"),
+            PathBuf::from("/photos/trip/b.jpg"),
+        ];
+        db.edit_tags("trip", &all, TagEdit::Add).unwrap();
+
+        db.remove_photos_under(Path::new("/photos/trip")).unwrap();
+        assert_eq!(counts(&db), [("trip".to_owned(), 1)]);
+    }
+}
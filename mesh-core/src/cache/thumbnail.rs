@@ -1,78 +1,533 @@
+mod directory;
+mod sqlite;
+
 use std::{
-    fs,
-    io::{self, Write},
-    path::{Path, PathBuf},
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
+use serde::{Deserialize, Serialize};
+
+pub use directory::DirectoryStore;
+pub use sqlite::SqliteStore;
+
+/// 缩略图文件头的魔数
+const HEADER_MAGIC: &[u8; 4] = b"MTH1";
+/// 缩略图文件头长度：魔数 + 原图大小 + 原图修改时间
+const HEADER_LEN: usize = 4 + 8 + 8;
+
+/// 原图的大小与修改时间，写入缩略图文件头，用于判断缓存是否过期
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceStamp {
+    pub size: u64,
+    /// 自 UNIX 纪元以来的纳秒数
+    pub modified: u64,
+}
+
+impl SourceStamp {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+
+        Ok(Self {
+            size: metadata.len(),
+            modified,
+        })
+    }
+
+    /// 修改时间，UNIX 时间戳（秒）
+    pub fn modified_secs(&self) -> i64 {
+        (self.modified / 1_000_000_000) as i64
+    }
+
+    fn to_header(self) -> [u8; HEADER_LEN] {
+        let mut header = [0u8; HEADER_LEN];
+        header[0..4].copy_from_slice(HEADER_MAGIC);
+        header[4..12].copy_from_slice(&self.size.to_le_bytes());
+        header[12..20].copy_from_slice(&self.modified.to_le_bytes());
+        header
+    }
+
+    fn from_header(data: &[u8]) -> Option<Self> {
+        if data.len() < HEADER_LEN || &data[0..4] != HEADER_MAGIC {
+            return None;
+        }
+
+        Some(Self {
+            size: u64::from_le_bytes(data[4..12].try_into().unwrap()),
+            modified: u64::from_le_bytes(data[12..20].try_into().unwrap()),
+        })
+    }
+}
+
+/// 缩略图的存储后端
+///
+/// 存取的数据包含文件头，新鲜度判断由 [`MeshThumbnail`] 负责。
+pub trait ThumbnailStore: Send + Sync {
+    /// 存储所在的目录或文件
+    fn path(&self) -> &Path;
+
+    fn read(&self, file_hash: u128) -> io::Result<Vec<u8>>;
+
+    /// 只读取开头 `len` 个字节，用于检查文件头
+    fn read_prefix(&self, file_hash: u128, len: usize) -> io::Result<Vec<u8>> {
+        let mut data = self.read(file_hash)?;
+        data.truncate(len);
+        Ok(data)
+    }
+
+    fn write(&self, file_hash: u128, data: &[u8]) -> io::Result<()>;
+
+    fn remove(&self, file_hash: u128) -> io::Result<()>;
+
+    fn set_accessed(&self, file_hash: u128, accessed: SystemTime) -> io::Result<()>;
+
+    fn entries(&self) -> Vec<ThumbnailEntry>;
+
+    /// 删除条目后回收空间
+    fn compact(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 可在配置中选择的缩略图存储方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailBackend {
+    /// `thumbnail/xx/yy/` 分桶目录，每个缩略图一个文件
+    #[default]
+    Directory,
+    /// 单个 `thumbnails.db` 文件
+    Sqlite,
+}
+
+impl ThumbnailBackend {
+    pub fn open(self, cache_dir: &Path) -> io::Result<Box<dyn ThumbnailStore>> {
+        Ok(match self {
+            Self::Directory => Box::new(DirectoryStore::new(cache_dir.join("thumbnail"))?),
+            Self::Sqlite => Box::new(SqliteStore::open(cache_dir.join("thumbnails.db"))?),
+        })
+    }
+}
+
+impl std::str::FromStr for ThumbnailBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "directory" => Ok(Self::Directory),
+            "sqlite" => Ok(Self::Sqlite),
+            _ => Err(format!("unknown thumbnail backend: {}", s)),
+        }
+    }
+}
+
+/// 缓存中的一个缩略图
+#[derive(Debug, Clone)]
+pub struct ThumbnailEntry {
+    pub file_hash: u128,
+    pub size: u64,
+    /// 最近一次读取或写入的时间
+    pub accessed: SystemTime,
+}
+
+/// 缩略图缓存的占用情况
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThumbnailStats {
+    pub count: usize,
+    pub bytes: u64,
+}
+
+/// 一次清理删除的缩略图数量与回收的字节数
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GcReport {
+    pub removed: usize,
+    pub reclaimed_bytes: u64,
+}
+
+impl GcReport {
+    pub fn merge(&mut self, other: GcReport) {
+        self.removed += other.removed;
+        self.reclaimed_bytes += other.reclaimed_bytes;
+    }
+}
+
 pub struct MeshThumbnail {
-    thumbnail_dir_path: PathBuf,
+    store: Box<dyn ThumbnailStore>,
 }
 
 impl MeshThumbnail {
-    pub fn new(thumbnail_dir_path: PathBuf) -> Self {
-        fs::create_dir_all(&thumbnail_dir_path)
-            .map_err(|e| log::warn!("Failed to create path: {}", e))
-            .unwrap();
-
-        Self { thumbnail_dir_path }
+    pub fn new(store: Box<dyn ThumbnailStore>) -> Self {
+        Self { store }
     }
 
-    pub fn thumbnail_dir(&self) -> &PathBuf {
-        &self.thumbnail_dir_path
+    /// 缩略图存储所在的目录或文件
+    pub fn path(&self) -> &Path {
+        self.store.path()
     }
 
     /// 尝试从缓存中读取缩略图数据
-    pub fn read_thumbnail(&self, file_hash: u128) -> io::Result<Vec<u8>> {
-        let full_path = get_file_path(&self.thumbnail_dir_path, &file_hash);
-
-        // 如果文件存在，读取并返回
-        if full_path.exists() {
-            fs::read(&full_path)
-        } else {
+    ///
+    /// 缓存中记录的原图大小或修改时间与 `stamp` 不一致时视为未命中。
+    pub fn read_thumbnail(&self, file_hash: u128, stamp: &SourceStamp) -> io::Result<Vec<u8>> {
+        let mut data = self.store.read(file_hash).map_err(|e| match e.kind() {
             // 如果不存在，返回特定的错误（例如 NotFound）
-            Err(io::Error::new(
+            io::ErrorKind::NotFound => {
+                io::Error::new(io::ErrorKind::NotFound, "Thumbnail not found in cache")
+            }
+            _ => e,
+        })?;
+
+        match SourceStamp::from_header(&data) {
+            Some(cached) if cached == *stamp => {
+                // 记录访问时间，供 LRU 淘汰使用
+                if let Err(e) = self.store.set_accessed(file_hash, SystemTime::now()) {
+                    log::warn!("Failed to update thumbnail access time: {}", e);
+                }
+                data.drain(..HEADER_LEN);
+                Ok(data)
+            }
+            _ => Err(io::Error::new(
                 io::ErrorKind::NotFound,
-                "Thumbnail not found in cache",
-            ))
+                "Thumbnail in cache is stale",
+            )),
         }
     }
 
+    /// 缓存中是否存在与 `stamp` 一致的缩略图（只读取文件头）
+    pub fn is_fresh(&self, file_hash: u128, stamp: &SourceStamp) -> bool {
+        self.store
+            .read_prefix(file_hash, HEADER_LEN)
+            .map(|header| SourceStamp::from_header(&header) == Some(*stamp))
+            .unwrap_or(false)
+    }
+
     /// 将新生成的缩略图数据写入缓存
-    pub fn write_thumbnail(&self, file_hash: u128, data: &[u8]) -> io::Result<()> {
-        let full_path = get_file_path(&self.thumbnail_dir_path, &file_hash);
+    pub fn write_thumbnail(
+        &self,
+        file_hash: u128,
+        stamp: &SourceStamp,
+        data: &[u8],
+    ) -> io::Result<()> {
+        // 写入文件头与缩略图数据
+        let mut content = Vec::with_capacity(HEADER_LEN + data.len());
+        content.extend_from_slice(&stamp.to_header());
+        content.extend_from_slice(data);
+        self.store.write(file_hash, &content)
+    }
 
-        // 确保分桶目录存在
-        let parent_dir = full_path.parent().unwrap();
-        fs::create_dir_all(parent_dir)?;
+    pub fn remove_thumbnail(&self, file_hash: u128) -> io::Result<()> {
+        self.store.remove(file_hash)
+    }
+
+    /// 列出缓存中的所有缩略图
+    pub fn entries(&self) -> Vec<ThumbnailEntry> {
+        self.store.entries()
+    }
 
-        // 写入文件
-        let mut file = fs::File::create(full_path)?;
-        file.write_all(data)
+    pub fn stats(&self) -> ThumbnailStats {
+        self.entries()
+            .iter()
+            .fold(ThumbnailStats::default(), |mut stats, entry| {
+                stats.count += 1;
+                stats.bytes += entry.size;
+                stats
+            })
     }
 
-    pub fn generate_file_hash(file_path: &PathBuf) -> u128 {
+    /// 删除不属于 `live` 中任何照片的缩略图
+    pub fn gc(&self, live: &HashSet<u128>) -> GcReport {
+        let orphans = self
+            .entries()
+            .into_iter()
+            .filter(|entry| !live.contains(&entry.file_hash));
+        self.remove_entries(orphans)
+    }
+
+    /// 删除属于 `hashes` 中照片的缩略图
+    pub fn remove_many(&self, hashes: &HashSet<u128>) -> GcReport {
+        let entries = self
+            .entries()
+            .into_iter()
+            .filter(|entry| hashes.contains(&entry.file_hash));
+        self.remove_entries(entries)
+    }
+
+    /// 按最近访问时间淘汰缩略图，直到缓存总大小不超过 `limit_bytes`
+    pub fn evict_to(&self, limit_bytes: u64) -> GcReport {
+        let mut entries = self.entries();
+        let mut total: u64 = entries.iter().map(|entry| entry.size).sum();
+        if total <= limit_bytes {
+            return GcReport::default();
+        }
+
+        entries.sort_by_key(|entry| entry.accessed);
+        let victims = entries.into_iter().take_while(|entry| {
+            let over = total > limit_bytes;
+            total = total.saturating_sub(entry.size);
+            over
+        });
+        self.remove_entries(victims)
+    }
+
+    /// 把所有缩略图复制到 `target`，保留访问时间，复制完成后调用 `commit`
+    ///
+    /// `commit` 成功后才从当前存储中删除，复制或 `commit` 失败时当前存储保持不变。
+    /// 返回迁移的缩略图数量。
+    pub fn migrate_to(
+        &self,
+        target: &dyn ThumbnailStore,
+        commit: impl FnOnce() -> io::Result<()>,
+    ) -> io::Result<usize> {
+        let entries = self.entries();
+        for entry in &entries {
+            let data = self.store.read(entry.file_hash)?;
+            target.write(entry.file_hash, &data)?;
+            target.set_accessed(entry.file_hash, entry.accessed)?;
+        }
+        commit()?;
+
+        self.remove_entries(entries.iter().cloned());
+        Ok(entries.len())
+    }
+
+    /// 照片路径改变后把缩略图移到新的路径哈希下，保留访问时间，返回迁移的数量
+    pub fn rekey(&self, hashes: &[(u128, u128)]) -> usize {
+        let entries: HashMap<u128, ThumbnailEntry> = self
+            .entries()
+            .into_iter()
+            .map(|entry| (entry.file_hash, entry))
+            .collect();
+
+        let mut moved = 0;
+        for (old_hash, new_hash) in hashes {
+            let Some(entry) = entries.get(old_hash) else {
+                continue;
+            };
+            let result = self.store.read(*old_hash).and_then(|data| {
+                self.store.write(*new_hash, &data)?;
+                self.store.set_accessed(*new_hash, entry.accessed)?;
+                self.store.remove(*old_hash)
+            });
+            match result {
+                Ok(()) => moved += 1,
+                Err(e) => log::warn!("Failed to move thumbnail {}: {}", old_hash, e),
+            }
+        }
+        moved
+    }
+
+    fn remove_entries(&self, entries: impl Iterator<Item = ThumbnailEntry>) -> GcReport {
+        let mut report = GcReport::default();
+        for entry in entries {
+            match self.remove_thumbnail(entry.file_hash) {
+                Ok(()) => {
+                    report.removed += 1;
+                    report.reclaimed_bytes += entry.size;
+                }
+                Err(e) => log::warn!("Failed to remove thumbnail {}: {}", entry.file_hash, e),
+            }
+        }
+
+        if report.removed > 0
+            && let Err(e) = self.store.compact()
+        {
+            log::warn!("Failed to compact thumbnail store: {}", e);
+        }
+        report
+    }
+
+    pub fn generate_file_hash(file_path: &Path) -> u128 {
         let hasher = blake3::hash(file_path.as_os_str().as_encoded_bytes());
         let bytes = hasher.as_bytes();
         u128::from_le_bytes(bytes[0..16].try_into().unwrap())
     }
 }
 
-// 假设我们使用原图路径的哈希值来定位缩略图
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const BACKENDS: [ThumbnailBackend; 2] = [ThumbnailBackend::Directory, ThumbnailBackend::Sqlite];
+
+    const STAMP: SourceStamp = SourceStamp {
+        size: 1234,
+        modified: 1_700_000_000_000_000_000,
+    };
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn accessed(thumbnail: &MeshThumbnail) -> HashMap<u128, SystemTime> {
+        thumbnail
+            .entries()
+            .into_iter()
+            .map(|entry| (entry.file_hash, entry.accessed))
+            .collect()
+    }
+
+    /// 写入 `1..=count` 号缩略图，访问时间依次递增
+    fn filled(backend: ThumbnailBackend, dir: &Path, count: u128) -> MeshThumbnail {
+        let thumbnail = MeshThumbnail::new(backend.open(dir).unwrap());
+        for hash in 1..=count {
+            thumbnail
+                .write_thumbnail(hash, &STAMP, &[hash as u8; 100])
+                .unwrap();
+            thumbnail
+                .store
+                .set_accessed(hash, at(1_000_000 + hash as u64 * 60))
+                .unwrap();
+        }
+        thumbnail
+    }
+
+    #[test]
+    fn read_checks_source_stamp() {
+        for backend in BACKENDS {
+            let dir = tempfile::tempdir().unwrap();
+            let thumbnail = filled(backend, dir.path(), 1);
+
+            assert_eq!(thumbnail.read_thumbnail(1, &STAMP).unwrap(), [1; 100]);
+            assert!(thumbnail.is_fresh(1, &STAMP));
+
+            // 原图大小或修改时间改变后缓存过期
+            let resized = SourceStamp {
+                size: STAMP.size + 1,
+                ..STAMP
+            };
+            let touched = SourceStamp {
+                modified: STAMP.modified + 1,
+                ..STAMP
+            };
+            for stamp in [resized, touched] {
+                let e = thumbnail.read_thumbnail(1, &stamp).unwrap_err();
+                assert_eq!(e.kind(), io::ErrorKind::NotFound, "{backend:?}");
+                assert!(!thumbnail.is_fresh(1, &stamp));
+            }
+            assert!(!thumbnail.is_fresh(2, &STAMP));
+            assert_eq!(
+                thumbnail.read_thumbnail(2, &STAMP).unwrap_err().kind(),
+                io::ErrorKind::NotFound
+            );
+        }
+    }
+
+    #[test]
+    fn evict_removes_least_recently_used() {
+        for backend in BACKENDS {
+            let dir = tempfile::tempdir().unwrap();
+            let thumbnail = filled(backend, dir.path(), 4);
+            let entry_size = thumbnail.entries()[0].size;
+            let total = thumbnail.stats().bytes;
+            assert_eq!(total, entry_size * 4);
+
+            assert_eq!(thumbnail.evict_to(total).removed, 0);
+
+            // 读取会刷新访问时间，1 号不再是最久未使用的
+            thumbnail.read_thumbnail(1, &STAMP).unwrap();
+            let report = thumbnail.evict_to(total - 1);
+            assert_eq!(report.removed, 1, "{backend:?}");
+            assert_eq!(report.reclaimed_bytes, entry_size);
+            let mut left: Vec<u128> = accessed(&thumbnail).into_keys().collect();
+            left.sort();
+            assert_eq!(left, [1, 3, 4]);
+
+            let report = thumbnail.evict_to(entry_size);
+            assert_eq!(report.removed, 2);
+            assert_eq!(accessed(&thumbnail).into_keys().collect::<Vec<_>>(), [1]);
+
+            assert_eq!(thumbnail.evict_to(0).removed, 1);
+            assert_eq!(thumbnail.stats().count, 0);
+        }
+    }
+
+    #[test]
+    fn gc_keeps_live_thumbnails() {
+        for backend in BACKENDS {
+            let dir = tempfile::tempdir().unwrap();
+            let thumbnail = filled(backend, dir.path(), 3);
+
+            let report = thumbnail.gc(&HashSet::from([1, 3, 99]));
+            assert_eq!(report.removed, 1, "{backend:?}");
+            let mut left: Vec<u128> = accessed(&thumbnail).into_keys().collect();
+            left.sort();
+            assert_eq!(left, [1, 3]);
+
+            let report = thumbnail.remove_many(&HashSet::from([3, 99]));
+            assert_eq!(report.removed, 1);
+            assert_eq!(accessed(&thumbnail).into_keys().collect::<Vec<_>>(), [1]);
+        }
+    }
+
+    #[test]
+    fn migrate_keeps_data_and_access_times() {
+        for (from, to) in [
+            (ThumbnailBackend::Directory, ThumbnailBackend::Sqlite),
+            (ThumbnailBackend::Sqlite, ThumbnailBackend::Directory),
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            let source = filled(from, dir.path(), 3);
+            let before = accessed(&source);
 
-/// 计算缩略图的完整缓存路径
-fn get_file_path(root: &Path, file_hash: &u128) -> PathBuf {
-    // 2. 使用 u128 值计算目录分桶。
-    // 提取 u128 的最高两位十六进制数字作为第一层目录（即最高字节）。
-    let dir_segment_1_val = (file_hash >> 120) as u8;
-    let dir_segment_1 = format!("{:02x}", dir_segment_1_val);
+            let target = to.open(dir.path()).unwrap();
+            // 提交失败时保留原存储中的缩略图
+            assert!(
+                source
+                    .migrate_to(target.as_ref(), || Err(io::Error::other("save failed")))
+                    .is_err()
+            );
+            assert_eq!(accessed(&source), before, "{from:?} -> {to:?}");
 
-    // 提取 u128 的接下来两位十六进制数字作为第二层目录（即次高字节）。
-    let dir_segment_2_val = ((file_hash >> 112) & 0xFF) as u8;
-    let dir_segment_2 = format!("{:02x}", dir_segment_2_val);
+            assert_eq!(source.migrate_to(target.as_ref(), || Ok(())).unwrap(), 3);
+            assert_eq!(source.stats().count, 0, "{from:?} -> {to:?}");
 
-    // 3. 构建目录路径: {thumbnail_dir}/{dir_segment_1}/{dir_segment_2}
-    let bucket_dir = root.join(dir_segment_1).join(dir_segment_2);
+            let target = MeshThumbnail::new(target);
+            assert_eq!(accessed(&target), before);
+            for hash in 1..=3 {
+                assert!(target.is_fresh(hash, &STAMP));
+                assert_eq!(
+                    target.store.read(hash).unwrap()[HEADER_LEN..],
+                    [hash as u8; 100]
+                );
+            }
+        }
+    }
 
-    // 4. 构建最终文件路径。文件名依然是完整的十六进制字符串。
-    bucket_dir.join(format!("{}", file_hash))
+    #[test]
+    fn rekey_moves_thumbnails() {
+        for backend in BACKENDS {
+            let dir = tempfile::tempdir().unwrap();
+            let thumbnail = filled(backend, dir.path(), 2);
+            let before = accessed(&thumbnail);
+
+            // 不存在的缩略图被跳过
+            assert_eq!(thumbnail.rekey(&[(1, 10), (5, 50)]), 1, "{backend:?}");
+            let after = accessed(&thumbnail);
+            assert_eq!(after.len(), 2);
+            assert_eq!(after[&10], before[&1]);
+            assert!(thumbnail.read_thumbnail(10, &STAMP).is_ok());
+            assert!(!thumbnail.is_fresh(1, &STAMP));
+        }
+    }
+
+    #[test]
+    fn header_round_trips() {
+        assert_eq!(SourceStamp::from_header(&STAMP.to_header()), Some(STAMP));
+        assert_eq!(
+            SourceStamp::from_header(&STAMP.to_header()[..HEADER_LEN - 1]),
+            None
+        );
+        let mut header = STAMP.to_header();
+        header[0] = b'X';
+        assert_eq!(SourceStamp::from_header(&header), None);
+    }
 }
@@ -0,0 +1,99 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use walkdir::WalkDir;
+
+use super::{ThumbnailEntry, ThumbnailStore};
+
+/// 每个缩略图一个文件，按路径哈希分桶存放在 `thumbnail/xx/yy/` 目录下
+pub struct DirectoryStore {
+    thumbnail_dir_path: PathBuf,
+}
+
+impl DirectoryStore {
+    pub fn new(thumbnail_dir_path: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&thumbnail_dir_path)?;
+        Ok(Self { thumbnail_dir_path })
+    }
+}
+
+impl ThumbnailStore for DirectoryStore {
+    fn path(&self) -> &Path {
+        &self.thumbnail_dir_path
+    }
+
+    fn read(&self, file_hash: u128) -> io::Result<Vec<u8>> {
+        fs::read(get_file_path(&self.thumbnail_dir_path, &file_hash))
+    }
+
+    fn read_prefix(&self, file_hash: u128, len: usize) -> io::Result<Vec<u8>> {
+        let file = fs::File::open(get_file_path(&self.thumbnail_dir_path, &file_hash))?;
+        let mut prefix = Vec::with_capacity(len);
+        io::Read::read_to_end(&mut io::Read::take(file, len as u64), &mut prefix)?;
+        Ok(prefix)
+    }
+
+    fn write(&self, file_hash: u128, data: &[u8]) -> io::Result<()> {
+        let full_path = get_file_path(&self.thumbnail_dir_path, &file_hash);
+
+        // 确保分桶目录存在
+        let parent_dir = full_path.parent().unwrap();
+        fs::create_dir_all(parent_dir)?;
+
+        fs::write(full_path, data)
+    }
+
+    fn remove(&self, file_hash: u128) -> io::Result<()> {
+        fs::remove_file(get_file_path(&self.thumbnail_dir_path, &file_hash))
+    }
+
+    /// 用文件修改时间记录访问时间
+    fn set_accessed(&self, file_hash: u128, accessed: SystemTime) -> io::Result<()> {
+        fs::File::options()
+            .write(true)
+            .open(get_file_path(&self.thumbnail_dir_path, &file_hash))?
+            .set_modified(accessed)
+    }
+
+    fn entries(&self) -> Vec<ThumbnailEntry> {
+        WalkDir::new(&self.thumbnail_dir_path)
+            .min_depth(3)
+            .max_depth(3)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| {
+                let file_hash = e.file_name().to_str()?.parse::<u128>().ok()?;
+                let metadata = e.metadata().ok()?;
+                Some(ThumbnailEntry {
+                    file_hash,
+                    size: metadata.len(),
+                    accessed: metadata.modified().unwrap_or(UNIX_EPOCH),
+                })
+            })
+            .collect()
+    }
+}
+
+// 假设我们使用原图路径的哈希值来定位缩略图
+
+/// 计算缩略图的完整缓存路径
+fn get_file_path(root: &Path, file_hash: &u128) -> PathBuf {
+    // 2. 使用 u128 值计算目录分桶。
+    // 提取 u128 的最高两位十六进制数字作为第一层目录（即最高字节）。
+    let dir_segment_1_val = (file_hash >> 120) as u8;
+    let dir_segment_1 = format!("{:02x}", dir_segment_1_val);
+
+    // 提取 u128 的接下来两位十六进制数字作为第二层目录（即次高字节）。
+    let dir_segment_2_val = ((file_hash >> 112) & 0xFF) as u8;
+    let dir_segment_2 = format!("{:02x}", dir_segment_2_val);
+
+    // 3. 构建目录路径: {thumbnail_dir}/{dir_segment_1}/{dir_segment_2}
+    let bucket_dir = root.join(dir_segment_1).join(dir_segment_2);
+
+    // 4. 构建最终文件路径。文件名依然是完整的十六进制字符串。
+    bucket_dir.join(format!("{}", file_hash))
+}
@@ -0,0 +1,155 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{Connection, OptionalExtension, params};
+
+use super::{ThumbnailEntry, ThumbnailStore};
+
+/// 所有缩略图以 BLOB 形式存放在同一个 SQLite 文件中，便于备份且不占用大量 inode
+pub struct SqliteStore {
+    file_path: PathBuf,
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(file_path: PathBuf) -> io::Result<Self> {
+        let conn = Connection::open(&file_path).map_err(io::Error::other)?;
+        // auto_vacuum 只能在建表前设置，用于删除后回收空间
+        conn.execute_batch(
+            "PRAGMA auto_vacuum = INCREMENTAL;
+             PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS thumbnails (
+                file_hash BLOB PRIMARY KEY,
+                data BLOB NOT NULL,
+                accessed_at INTEGER NOT NULL
+             ) WITHOUT ROWID;",
+        )
+        .map_err(io::Error::other)?;
+
+        Ok(Self {
+            file_path,
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl ThumbnailStore for SqliteStore {
+    fn path(&self) -> &Path {
+        &self.file_path
+    }
+
+    fn read(&self, file_hash: u128) -> io::Result<Vec<u8>> {
+        self.conn()
+            .query_row(
+                "SELECT data FROM thumbnails WHERE file_hash = ?1",
+                [hash_key(file_hash)],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(io::Error::other)?
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn read_prefix(&self, file_hash: u128, len: usize) -> io::Result<Vec<u8>> {
+        self.conn()
+            .query_row(
+                "SELECT substr(data, 1, ?2) FROM thumbnails WHERE file_hash = ?1",
+                params![hash_key(file_hash), len as i64],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(io::Error::other)?
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn write(&self, file_hash: u128, data: &[u8]) -> io::Result<()> {
+        self.conn()
+            .execute(
+                "INSERT OR REPLACE INTO thumbnails (file_hash, data, accessed_at)
+                 VALUES (?1, ?2, ?3)",
+                params![hash_key(file_hash), data, to_unix_millis(SystemTime::now())],
+            )
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn remove(&self, file_hash: u128) -> io::Result<()> {
+        let removed = self
+            .conn()
+            .execute(
+                "DELETE FROM thumbnails WHERE file_hash = ?1",
+                [hash_key(file_hash)],
+            )
+            .map_err(io::Error::other)?;
+        if removed == 0 {
+            return Err(io::Error::from(io::ErrorKind::NotFound));
+        }
+        Ok(())
+    }
+
+    fn set_accessed(&self, file_hash: u128, accessed: SystemTime) -> io::Result<()> {
+        self.conn()
+            .execute(
+                "UPDATE thumbnails SET accessed_at = ?2 WHERE file_hash = ?1",
+                params![hash_key(file_hash), to_unix_millis(accessed)],
+            )
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn entries(&self) -> Vec<ThumbnailEntry> {
+        let conn = self.conn();
+        let entries = conn
+            .prepare("SELECT file_hash, length(data), accessed_at FROM thumbnails")
+            .and_then(|mut stmt| {
+                stmt.query_map([], |row| {
+                    let key: Vec<u8> = row.get(0)?;
+                    Ok(ThumbnailEntry {
+                        file_hash: from_hash_key(&key),
+                        size: row.get::<_, i64>(1)? as u64,
+                        accessed: from_unix_millis(row.get(2)?),
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()
+            });
+
+        entries
+            .map_err(|e| log::warn!("Failed to list thumbnails: {}", e))
+            .unwrap_or_default()
+    }
+
+    fn compact(&self) -> io::Result<()> {
+        self.conn()
+            .execute_batch("PRAGMA incremental_vacuum;")
+            .map_err(io::Error::other)
+    }
+}
+
+fn hash_key(file_hash: u128) -> [u8; 16] {
+    file_hash.to_be_bytes()
+}
+
+fn from_hash_key(key: &[u8]) -> u128 {
+    let mut bytes = [0u8; 16];
+    let len = key.len().min(16);
+    bytes[16 - len..].copy_from_slice(&key[..len]);
+    u128::from_be_bytes(bytes)
+}
+
+fn to_unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn from_unix_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
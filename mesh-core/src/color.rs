@@ -0,0 +1,81 @@
+//! 按内嵌的 ICC 配置文件把解码结果转换到 sRGB
+
+use image::{DynamicImage, RgbImage, RgbaImage};
+use moxcms::{ColorProfile, DataColorSpace, Layout, ProfileText, TransformOptions};
+
+/// 把 `image` 从 `icc` 描述的颜色空间转换到 sRGB，并返回配置文件的名称
+///
+/// 没有内嵌配置文件的图片按 sRGB 处理，名称为 `None`。只转换 RGB 配置文件，
+/// 灰度与 CMYK 配置文件只记录名称；转换失败时保留原图。
+pub(crate) fn to_srgb(image: DynamicImage, icc: Option<&[u8]>) -> (DynamicImage, Option<String>) {
+    let Some(icc) = icc else {
+        return (image, None);
+    };
+    let profile = match ColorProfile::new_from_slice(icc) {
+        Ok(profile) => profile,
+        Err(e) => {
+            log::warn!("Failed to parse ICC profile: {}", e);
+            return (image, Some("unknown".to_owned()));
+        }
+    };
+
+    let name = profile_name(&profile);
+    if profile.color_space != DataColorSpace::Rgb {
+        return (image, Some(name));
+    }
+
+    match convert(&image, &profile) {
+        Ok(converted) => (converted, Some(name)),
+        Err(e) => {
+            log::warn!("Failed to convert {} to sRGB: {}", name, e);
+            (image, Some(name))
+        }
+    }
+}
+
+fn convert(image: &DynamicImage, profile: &ColorProfile) -> Result<DynamicImage, moxcms::CmsError> {
+    let srgb = ColorProfile::new_srgb();
+    let layout = if image.color().has_alpha() {
+        Layout::Rgba
+    } else {
+        Layout::Rgb
+    };
+    let transform =
+        profile.create_transform_8bit(layout, &srgb, layout, TransformOptions::default())?;
+
+    // 缩略图与预览只需要 8 位精度
+    let (width, height) = (image.width(), image.height());
+    let src = match layout {
+        Layout::Rgba => image.to_rgba8().into_raw(),
+        _ => image.to_rgb8().into_raw(),
+    };
+    let mut dst = vec![0u8; src.len()];
+    transform.transform(&src, &mut dst)?;
+
+    Ok(match layout {
+        Layout::Rgba => DynamicImage::ImageRgba8(RgbaImage::from_raw(width, height, dst).unwrap()),
+        _ => DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, dst).unwrap()),
+    })
+}
+
+/// 配置文件的描述，例如 `Display P3`、`Adobe RGB (1998)`
+fn profile_name(profile: &ColorProfile) -> String {
+    let name = match &profile.description {
+        Some(ProfileText::PlainString(text)) => text.clone(),
+        Some(ProfileText::Localizable(texts)) => texts
+            .iter()
+            .find(|text| text.language == "en")
+            .or_else(|| texts.first())
+            .map(|text| text.value.clone())
+            .unwrap_or_default(),
+        Some(ProfileText::Description(text)) => text.ascii_string.clone(),
+        None => String::new(),
+    };
+
+    let name = name.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    if name.is_empty() {
+        "unknown".to_owned()
+    } else {
+        name.to_owned()
+    }
+}
@@ -1,85 +1,311 @@
+mod dirs;
+mod store;
+
 use std::{
-    cell::RefCell,
+    fs, io,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
 use directories::UserDirs;
 use serde::{Deserialize, Serialize};
 
-use crate::MESH_DIR;
+use crate::{MediaFormat, MeshDirs, ThumbnailBackend, paths::write_atomic};
+
+pub use dirs::{DirChange, DirError};
+pub use store::{ConfigChange, ConfigStore, ConfigSubscription, ConfigValue};
 
 const CONFIG_FILE_NAME: &str = "config.toml";
+/// 无法解析的配置文件备份到此文件名
+const BACKUP_FILE_NAME: &str = "config.toml.bak";
+/// 缩略图缓存默认上限（MB）
+const DEFAULT_THUMBNAIL_CACHE_LIMIT_MB: u64 = 1024;
+/// 子进程解码单个文件的默认超时（秒）
+const DEFAULT_DECODE_TIMEOUT_SECS: u64 = 30;
+
+/// 读取配置文件失败
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// 配置文件格式错误，原文件已备份到 `backup`
+    Parse {
+        path: PathBuf,
+        /// 从 1 开始的行号与列号
+        line: usize,
+        column: usize,
+        message: String,
+        backup: Option<PathBuf>,
+    },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "failed to read {}: {}", path.display(), error),
+            Self::Parse {
+                path,
+                line,
+                column,
+                message,
+                backup,
+            } => {
+                write!(f, "{}:{}:{}: {}", path.display(), line, column, message)?;
+                if let Some(backup) = backup {
+                    write!(f, " (backed up to {})", backup.display())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MeshConfig {
     album_dirs: Vec<PathBuf>,
     excluded_dirs: Vec<PathBuf>,
-    theme: RefCell<String>,
+    theme: String,
+    #[serde(default = "default_thumbnail_cache_limit_mb")]
+    thumbnail_cache_limit_mb: u64,
+    #[serde(default)]
+    thumbnail_store: ThumbnailBackend,
+    /// 需要索引的图片格式
+    #[serde(default = "default_formats")]
+    formats: Vec<MediaFormat>,
+    /// 是否在子进程中解码图片，避免损坏的文件导致 Mesh 崩溃
+    #[serde(default)]
+    decode_in_worker: bool,
+    #[serde(default = "default_decode_timeout_secs")]
+    decode_timeout_secs: u64,
+    /// gitignore 格式的忽略规则，相对于每个相册目录，例如 `**/.thumbnails`、`*.tmp`
+    #[serde(default)]
+    ignore_patterns: Vec<String>,
+    /// 是否跳过以 `.` 开头的文件与目录
+    #[serde(default = "default_skip_hidden")]
+    skip_hidden: bool,
+    /// 小于此大小（KB）的文件不会被索引
+    #[serde(default)]
+    min_file_size_kb: u64,
+    /// 宽度或高度小于此值的图片不会被索引，用于排除图标等小图
+    #[serde(default)]
+    min_width: u32,
+    #[serde(default)]
+    min_height: u32,
+    /// 扫描时是否进入符号链接指向的目录与文件
+    #[serde(default)]
+    follow_symlinks: bool,
+    /// 配置文件读取失败时的错误，此时使用默认配置且拒绝保存
+    #[serde(skip)]
+    load_error: Option<ConfigError>,
+    /// 最近一次读取或写入的文件内容，保存时用于与外部的修改合并
+    #[serde(skip)]
+    saved: Option<toml::Table>,
+    /// 保存时从文件合并进来的外部修改，由 [`ConfigStore`] 通知订阅者
+    #[serde(skip)]
+    merged_changes: Vec<ConfigChange>,
+    /// 配置文件的路径
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+fn default_thumbnail_cache_limit_mb() -> u64 {
+    DEFAULT_THUMBNAIL_CACHE_LIMIT_MB
 }
 
-impl Default for MeshConfig {
-    fn default() -> Self {
+fn default_decode_timeout_secs() -> u64 {
+    DEFAULT_DECODE_TIMEOUT_SECS
+}
+
+fn default_skip_hidden() -> bool {
+    true
+}
+
+fn default_formats() -> Vec<MediaFormat> {
+    MediaFormat::all().collect()
+}
+
+impl MeshConfig {
+    /// 保存到 `dirs` 中的默认配置
+    pub fn new(dirs: &MeshDirs) -> Self {
         let mut album_paths = Vec::new();
 
-        if let Some(user_dirs) = UserDirs::new() {
-            if let Some(picture_dir) = user_dirs.picture_dir() {
-                album_paths.push(picture_dir.to_path_buf());
-            }
+        if let Some(user_dirs) = UserDirs::new()
+            && let Some(picture_dir) = user_dirs.picture_dir()
+        {
+            album_paths.push(picture_dir.to_path_buf());
         }
 
         Self {
             album_dirs: album_paths,
             excluded_dirs: Vec::new(),
-            theme: RefCell::new("Default Light".to_owned()),
+            theme: "Default Light".to_owned(),
+            thumbnail_cache_limit_mb: DEFAULT_THUMBNAIL_CACHE_LIMIT_MB,
+            thumbnail_store: ThumbnailBackend::default(),
+            formats: default_formats(),
+            decode_in_worker: false,
+            decode_timeout_secs: DEFAULT_DECODE_TIMEOUT_SECS,
+            ignore_patterns: Vec::new(),
+            skip_hidden: true,
+            min_file_size_kb: 0,
+            min_width: 0,
+            min_height: 0,
+            follow_symlinks: false,
+            load_error: None,
+            saved: None,
+            merged_changes: Vec::new(),
+            path: dirs.config_dir().join(CONFIG_FILE_NAME),
         }
     }
-}
 
-impl MeshConfig {
-    pub fn dir_path() -> &'static Path {
-        let path = MESH_DIR.config_dir();
-        if let Err(e) = std::fs::create_dir_all(&path) {
-            log::error!("{:?}", e);
-        }
-        path
+    /// 配置文件的路径
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
-    pub fn init() -> Self {
-        let config_path = Self::dir_path().join(CONFIG_FILE_NAME);
-        if !config_path.exists() {
-            let default_config = Self::default();
-            default_config.save();
-            return default_config;
+    /// 读取配置文件，失败时使用默认配置
+    ///
+    /// 失败时不会覆盖用户的配置文件：在修复或 [`reset`](Self::reset) 之前拒绝保存，
+    /// 错误可以通过 [`load_error`](Self::load_error) 获取。
+    pub fn init(dirs: &MeshDirs) -> Self {
+        match Self::load(dirs) {
+            Ok(config) => config,
+            Err(e) => {
+                log::error!("Failed to load config: {}", e);
+                Self {
+                    load_error: Some(e),
+                    ..Self::new(dirs)
+                }
+            }
         }
+    }
 
-        let config_content = std::fs::read_to_string(&config_path)
-            .map_err(|e| log::warn!("Failed to read config: {}", e))
-            .unwrap_or_default();
-
-        toml::from_str::<Self>(&config_content)
-            .map_err(|e| log::warn!("Failed to parse config: {}", e))
-            .unwrap_or_default()
+    /// 读取配置文件，文件不存在时写入默认配置
+    ///
+    /// 无法解析时先把原文件备份，再返回带有行列号的错误。
+    pub fn load(dirs: &MeshDirs) -> Result<Self, ConfigError> {
+        let config_path = dirs.config_dir().join(CONFIG_FILE_NAME);
+        match Self::read_file(&config_path)? {
+            Some(config) => Ok(config),
+            None => {
+                let mut default_config = Self::new(dirs);
+                default_config.save();
+                Ok(default_config)
+            }
+        }
     }
 
-    pub fn save(&self) {
-        let config_path = Self::dir_path().join(CONFIG_FILE_NAME);
-        if let Ok(config_content) = toml::to_string_pretty(self) {
-            if let Err(e) = std::fs::write(&config_path, config_content) {
-                log::warn!("{:?}", e);
+    /// 读取并解析配置文件，文件不存在时返回 `None`
+    fn read_file(config_path: &Path) -> Result<Option<Self>, ConfigError> {
+        let config_content = match fs::read_to_string(config_path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => {
+                return Err(ConfigError::Io {
+                    path: config_path.to_path_buf(),
+                    error,
+                });
             }
         };
+
+        let mut config = toml::from_str::<Self>(&config_content).map_err(|e| {
+            let (line, column) = e
+                .span()
+                .map(|span| line_column(&config_content, span.start))
+                .unwrap_or((1, 1));
+            ConfigError::Parse {
+                backup: backup(config_path),
+                path: config_path.to_path_buf(),
+                line,
+                column,
+                message: e.message().to_owned(),
+            }
+        })?;
+        config.saved = toml::from_str(&config_content).ok();
+        config.path = config_path.to_path_buf();
+        Ok(Some(config))
+    }
+
+    /// 用默认配置覆盖配置文件，原文件先备份，用于从无法解析的配置中恢复
+    pub fn reset(dirs: &MeshDirs) -> io::Result<Self> {
+        let mut config = Self::new(dirs);
+        if config.path.exists() {
+            fs::copy(&config.path, config.path.with_file_name(BACKUP_FILE_NAME))?;
+        }
+        config.try_save()?;
+        Ok(config)
     }
 
-    pub fn change_theme(&self, theme: String) {
-        *self.theme.borrow_mut() = theme;
+    /// 启动时读取配置文件失败的原因，此时不会保存任何修改
+    pub fn load_error(&self) -> Option<&ConfigError> {
+        self.load_error.as_ref()
     }
 
-    pub fn current_theme(&self) -> String {
-        self.theme.borrow().clone()
+    /// 写入配置文件，失败时只记录日志，需要处理失败时使用 [`try_save`](Self::try_save)
+    pub fn save(&mut self) {
+        if let Err(e) = self.try_save() {
+            log::warn!("Failed to save config: {}", e);
+        }
+    }
+
+    /// 写入配置文件
+    ///
+    /// 文件在上次读写之后被外部修改过时，只覆盖本进程修改过的项，其余保留文件中的值，
+    /// 并把合并后的值写回内存中的配置。
+    /// 先写入临时文件再重命名，写入中途失败不会损坏原有的配置文件。
+    pub fn try_save(&mut self) -> io::Result<()> {
+        if let Some(e) = &self.load_error {
+            return Err(io::Error::other(format!(
+                "config was not loaded, refusing to overwrite it: {}",
+                e
+            )));
+        }
+
+        let config_path = self.path.clone();
+        let ours = toml::Table::try_from(&*self).map_err(io::Error::other)?;
+        let merged = match (fs::read_to_string(&config_path), &self.saved) {
+            (Ok(content), Some(base)) => {
+                let theirs = toml::from_str::<toml::Table>(&content).map_err(|e| {
+                    io::Error::other(format!(
+                        "config was modified and cannot be parsed, refusing to overwrite it: {}",
+                        e.message()
+                    ))
+                })?;
+                (theirs != *base).then(|| merge(base, ours.clone(), theirs))
+            }
+            _ => None,
+        };
+        let (table, config_content, merged) = match merged {
+            Some(table) => {
+                // 合并结果必须仍然是合法的配置
+                let config = table.clone().try_into::<Self>().map_err(io::Error::other)?;
+                let content = toml::to_string_pretty(&table).map_err(io::Error::other)?;
+                (table, content, Some(config))
+            }
+            None => (
+                ours,
+                toml::to_string_pretty(self).map_err(io::Error::other)?,
+                None,
+            ),
+        };
+
+        write_atomic(&config_path, config_content.as_bytes())?;
+        if let Some(mut config) = merged {
+            // 采用合并后的值，否则下次保存时会用内存中的旧值覆盖外部的修改
+            self.merged_changes.extend(store::diff(self, &config));
+            config.path = config_path;
+            config.merged_changes = std::mem::take(&mut self.merged_changes);
+            *self = config;
+        }
+        self.saved = Some(table);
+        Ok(())
     }
 
-    pub fn themes_dir_path() -> PathBuf {
-        Self::dir_path().join("themes")
+    pub fn theme(&self) -> &str {
+        &self.theme
     }
 
     pub fn album_dirs(&self) -> &Vec<PathBuf> {
@@ -90,13 +316,306 @@ impl MeshConfig {
         &self.excluded_dirs
     }
 
-    // pub fn add_album_dir(&mut self, path: PathBuf) {
-    //     if !self.album_dirs.iter().any(|p| p == &path) {
-    //         self.album_dirs.push(path);
-    //     }
-    // }
+    /// 缩略图缓存的大小上限（字节）
+    pub fn thumbnail_cache_limit(&self) -> u64 {
+        self.thumbnail_cache_limit_mb.saturating_mul(1024 * 1024)
+    }
+
+    pub fn thumbnail_store(&self) -> ThumbnailBackend {
+        self.thumbnail_store
+    }
+
+    pub fn set_thumbnail_store(&mut self, backend: ThumbnailBackend) {
+        self.thumbnail_store = backend;
+    }
+
+    /// 配置中启用的图片格式
+    pub fn formats(&self) -> &[MediaFormat] {
+        &self.formats
+    }
+
+    pub fn set_formats(&mut self, formats: Vec<MediaFormat>) {
+        self.formats = formats;
+    }
+
+    /// 子进程解码单个文件的超时，未启用子进程解码时为 `None`
+    pub fn decode_worker_timeout(&self) -> Option<Duration> {
+        self.decode_in_worker
+            .then(|| Duration::from_secs(self.decode_timeout_secs))
+    }
+
+    pub fn ignore_patterns(&self) -> &[String] {
+        &self.ignore_patterns
+    }
+
+    pub fn skip_hidden(&self) -> bool {
+        self.skip_hidden
+    }
+
+    /// 索引文件的最小大小（字节）
+    pub fn min_file_size(&self) -> u64 {
+        self.min_file_size_kb.saturating_mul(1024)
+    }
+
+    /// 索引图片的最小宽度与高度
+    pub fn min_dimensions(&self) -> (u32, u32) {
+        (self.min_width, self.min_height)
+    }
+
+    pub fn follow_symlinks(&self) -> bool {
+        self.follow_symlinks
+    }
+}
+
+/// 三方合并：自上次读写以来只在本进程中修改过的项使用 `ours`，其余使用文件中的 `theirs`
+fn merge(base: &toml::Table, ours: toml::Table, theirs: toml::Table) -> toml::Table {
+    let mut merged = theirs;
+    for (key, value) in ours {
+        if base.get(&key) != Some(&value) {
+            merged.insert(key, value);
+        }
+    }
+    merged
+}
+
+/// 把无法解析的配置文件复制一份，返回备份的路径
+fn backup(config_path: &Path) -> Option<PathBuf> {
+    let backup_path = config_path.with_file_name(BACKUP_FILE_NAME);
+    fs::copy(config_path, &backup_path)
+        .map_err(|e| log::warn!("Failed to back up config: {}", e))
+        .ok()
+        .map(|_| backup_path)
+}
+
+/// 把字节偏移换算为从 1 开始的行号与列号
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = content.get(..offset).unwrap_or(content);
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .map_or(0, |line| line.chars().count())
+        + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_toml(content: &str) -> (tempfile::TempDir, MeshConfig) {
+        let dir = tempfile::tempdir().unwrap();
+        let dirs = MeshDirs::new(dir.path());
+        fs::create_dir_all(dirs.config_dir()).unwrap();
+        fs::write(dirs.config_dir().join(CONFIG_FILE_NAME), content).unwrap();
+        let config = MeshConfig::load(&dirs).unwrap();
+        (dir, config)
+    }
+
+    #[test]
+    fn byte_limits_saturate() {
+        let (_dir, config) = load_toml(
+            "album_dirs = []
+             excluded_dirs = []
+             theme = \"Default Light\"
+             thumbnail_cache_limit_mb = 9223372036854775807
+             min_file_size_kb = 9223372036854775807",
+        );
+        assert_eq!(config.thumbnail_cache_limit(), u64::MAX);
+        assert_eq!(config.min_file_size(), u64::MAX);
+    }
+
+    const MINIMAL: &str = "album_dirs = []
+excluded_dirs = []
+theme = \"Default Light\"
+";
+
+    fn table(content: &str) -> toml::Table {
+        toml::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn merge_keeps_changes_from_both_sides() {
+        let base = table("a = 1\nb = 1\nc = 1\nd = 1");
+        let ours = table("a = 2\nb = 1\nc = 1\nd = 1");
+        let theirs = table("a = 1\nb = 3\nd = 1\ne = 5");
+
+        // 只在本进程中修改的 a 用 ours，文件中修改、删除或新增的 b、c、e 用 theirs
+        assert_eq!(
+            merge(&base, ours, theirs),
+            table("a = 2\nb = 3\nd = 1\ne = 5")
+        );
+
+        // 双方都修改过的项以本进程为准
+        let ours = table("a = 2\nb = 1\nc = 1\nd = 1");
+        let theirs = table("a = 3\nb = 1\nc = 1\nd = 1");
+        assert_eq!(merge(&base, ours, theirs)["a"], toml::Value::Integer(2));
+    }
+
+    #[test]
+    fn save_merges_external_edits() {
+        let (_dir, mut config) = load_toml(MINIMAL);
+        let path = config.path().to_path_buf();
+        let edited = fs::read_to_string(&path)
+            .unwrap()
+            .replace("Default Light", "Default Dark");
+        fs::write(&path, edited).unwrap();
 
-    // pub fn remove_album_dir(&mut self, path: PathBuf) {
-    //     self.album_dirs.retain(|p| p != &path);
-    // }
+        config.set(ConfigValue::MinWidth(64));
+        config.try_save().unwrap();
+
+        let saved = table(&fs::read_to_string(&path).unwrap());
+        assert_eq!(saved["theme"].as_str(), Some("Default Dark"));
+        assert_eq!(saved["min_width"].as_integer(), Some(64));
+        // 合并后的值写回内存，并记录下来等待通知
+        assert_eq!(config.theme(), "Default Dark");
+        assert_eq!(config.min_dimensions(), (64, 0));
+        assert_eq!(
+            config.merged_changes,
+            [ConfigChange::Value(ConfigValue::Theme(
+                "Default Dark".to_owned()
+            ))]
+        );
+    }
+
+    #[test]
+    fn second_save_keeps_merged_external_edits() {
+        let (_dir, mut config) = load_toml(MINIMAL);
+        let path = config.path().to_path_buf();
+        let edited = fs::read_to_string(&path)
+            .unwrap()
+            .replace("Default Light", "Default Dark");
+        fs::write(&path, edited).unwrap();
+
+        config.set(ConfigValue::MinWidth(64));
+        config.try_save().unwrap();
+        // 文件与上次写入的内容相同，第二次保存直接写入内存中的配置
+        config.set(ConfigValue::MinHeight(32));
+        config.try_save().unwrap();
+
+        let saved = table(&fs::read_to_string(&path).unwrap());
+        assert_eq!(saved["theme"].as_str(), Some("Default Dark"));
+        assert_eq!(saved["min_width"].as_integer(), Some(64));
+        assert_eq!(saved["min_height"].as_integer(), Some(32));
+    }
+
+    #[test]
+    fn store_notifies_merged_external_edits() {
+        let (_dir, config) = load_toml(MINIMAL);
+        let path = config.path().to_path_buf();
+        let store = ConfigStore::new(config);
+        let changes = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let _subscription = store.subscribe({
+            let changes = changes.clone();
+            move |change| changes.lock().unwrap().push(change.clone())
+        });
+
+        fs::write(&path, MINIMAL.replace("Default Light", "Default Dark")).unwrap();
+        store.set(ConfigValue::MinWidth(64)).unwrap();
+
+        assert_eq!(
+            *changes.lock().unwrap(),
+            [
+                ConfigChange::Value(ConfigValue::MinWidth(64)),
+                ConfigChange::Value(ConfigValue::Theme("Default Dark".to_owned())),
+            ]
+        );
+        assert_eq!(store.read().theme(), "Default Dark");
+        // 文件已经与内存一致，之后的 reload 不会重复通知
+        assert!(store.reload().is_empty());
+    }
+
+    #[test]
+    fn save_refuses_to_overwrite_broken_edits() {
+        let (_dir, mut config) = load_toml(MINIMAL);
+        let path = config.path().to_path_buf();
+        fs::write(&path, "theme = ").unwrap();
+
+        config.set(ConfigValue::MinWidth(64));
+        assert!(config.try_save().is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "theme = ");
+    }
+
+    #[test]
+    fn reload_reports_external_changes() {
+        let (_dir, config) = load_toml(MINIMAL);
+        let path = config.path().to_path_buf();
+        let store = ConfigStore::new(config);
+        assert!(store.reload().is_empty());
+
+        fs::write(&path, format!("{}min_height = 32\n", MINIMAL)).unwrap();
+        assert_eq!(
+            store.reload(),
+            [ConfigChange::Value(ConfigValue::MinHeight(32))]
+        );
+        assert_eq!(store.read().min_dimensions(), (0, 32));
+
+        // 无法解析时保留当前配置并拒绝保存
+        fs::write(&path, "min_height = ").unwrap();
+        assert!(store.reload().is_empty());
+        assert_eq!(store.read().min_dimensions(), (0, 32));
+        assert!(store.read().load_error().is_some());
+        assert!(store.set(ConfigValue::MinWidth(8)).is_err());
+    }
+
+    #[test]
+    fn parse_errors_keep_the_users_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let dirs = MeshDirs::new(dir.path());
+        let path = dirs.config_dir().join(CONFIG_FILE_NAME);
+        let broken = format!("{}min_width = \"wide\"\n", MINIMAL);
+        fs::create_dir_all(dirs.config_dir()).unwrap();
+        fs::write(&path, &broken).unwrap();
+
+        match MeshConfig::load(&dirs) {
+            Err(ConfigError::Parse {
+                line,
+                column,
+                backup,
+                ..
+            }) => {
+                assert_eq!((line, column), (4, 13));
+                let backup = backup.unwrap();
+                assert_eq!(backup, dirs.config_dir().join(BACKUP_FILE_NAME));
+                assert_eq!(fs::read_to_string(backup).unwrap(), broken);
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        // 使用默认配置，但在修复之前不会写入
+        let mut config = MeshConfig::init(&dirs);
+        assert!(config.load_error().is_some());
+        config.set(ConfigValue::MinWidth(64));
+        assert!(config.try_save().is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), broken);
+
+        // 重置时备份原文件并写入默认配置
+        let config = MeshConfig::reset(&dirs).unwrap();
+        assert!(config.load_error().is_none());
+        assert!(MeshConfig::load(&dirs).is_ok());
+    }
+
+    #[test]
+    fn missing_file_writes_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let dirs = MeshDirs::new(dir.path());
+
+        let config = MeshConfig::load(&dirs).unwrap();
+        assert!(config.path().exists());
+        assert_eq!(
+            MeshConfig::load(&dirs).unwrap().album_dirs(),
+            config.album_dirs()
+        );
+    }
+
+    #[test]
+    fn line_column_counts_characters() {
+        let content = "a = 1\n京都 = x\n";
+        assert_eq!(line_column(content, 0), (1, 1));
+        assert_eq!(line_column(content, 6), (2, 1));
+        assert_eq!(line_column(content, content.find('=').unwrap()), (1, 3));
+        assert_eq!(line_column(content, content.rfind('=').unwrap()), (2, 4));
+        // 超出范围的偏移按文件末尾处理
+        assert_eq!(line_column(content, 100), (3, 1));
+    }
 }
@@ -0,0 +1,450 @@
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use super::MeshConfig;
+
+/// 相册目录或排除目录修改后发出的事件
+///
+/// 扫描器据此索引新增的目录树，或从照片库中清理不再需要的目录树。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DirChange {
+    AlbumAdded(PathBuf),
+    AlbumRemoved(PathBuf),
+    AlbumsReordered,
+    /// 相册目录所在的卷挂载到了新的位置，照片库中的记录已随之迁移
+    AlbumMoved {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    ExcludedAdded(PathBuf),
+    ExcludedRemoved(PathBuf),
+    ExcludedReordered,
+}
+
+impl DirChange {
+    /// 需要扫描并索引的目录树
+    pub fn index_root(&self) -> Option<&Path> {
+        match self {
+            Self::AlbumAdded(path) | Self::ExcludedRemoved(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// 需要从照片库中清理的目录树
+    pub fn prune_root(&self) -> Option<&Path> {
+        match self {
+            Self::AlbumRemoved(path) | Self::ExcludedAdded(path) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// 修改相册目录或排除目录失败的原因
+#[derive(Debug)]
+pub enum DirError {
+    /// 路径不存在或无法访问
+    Io(PathBuf, io::Error),
+    NotADirectory(PathBuf),
+    /// 列表中已有相同的目录
+    Duplicate(PathBuf),
+    /// 与列表中已有的目录互相包含
+    Nested {
+        path: PathBuf,
+        existing: PathBuf,
+    },
+    /// 排除目录不在任何相册目录内
+    OutsideAlbums(PathBuf),
+    /// 列表中没有该目录
+    NotFound(PathBuf),
+    /// 调整顺序时下标越界
+    OutOfRange(usize),
+    /// 写入配置文件失败，内存中的修改已撤销
+    Save(io::Error),
+}
+
+impl fmt::Display for DirError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            Self::NotADirectory(path) => write!(f, "not a directory: {}", path.display()),
+            Self::Duplicate(path) => write!(f, "already in the list: {}", path.display()),
+            Self::Nested { path, existing } => write!(
+                f,
+                "{} overlaps with {} in the list",
+                path.display(),
+                existing.display()
+            ),
+            Self::OutsideAlbums(path) => {
+                write!(f, "not inside any album directory: {}", path.display())
+            }
+            Self::NotFound(path) => write!(f, "not in the list: {}", path.display()),
+            Self::OutOfRange(index) => write!(f, "index out of range: {}", index),
+            Self::Save(e) => write!(f, "failed to save config: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DirError {}
+
+impl MeshConfig {
+    /// 添加相册目录，路径会被规范化，不能与已有的相册目录相同或互相包含
+    pub fn add_album_dir(&mut self, path: impl AsRef<Path>) -> Result<DirChange, DirError> {
+        let path = canonical_dir(path.as_ref())?;
+        check_overlap(&path, &self.album_dirs)?;
+
+        let mut album_dirs = self.album_dirs.clone();
+        album_dirs.push(path.clone());
+        self.replace_dirs(Some(album_dirs), None)?;
+        Ok(DirChange::AlbumAdded(path))
+    }
+
+    /// 移除相册目录，目录已被删除时按配置中的原样匹配
+    pub fn remove_album_dir(&mut self, path: impl AsRef<Path>) -> Result<DirChange, DirError> {
+        let index = position(&self.album_dirs, path.as_ref())?;
+
+        let mut album_dirs = self.album_dirs.clone();
+        let removed = album_dirs.remove(index);
+        self.replace_dirs(Some(album_dirs), None)?;
+        Ok(DirChange::AlbumRemoved(removed))
+    }
+
+    /// 把位于 `from` 下（含 `from` 本身）的相册目录与排除目录移到 `to` 下
+    ///
+    /// 用于卷挂载到新位置或照片文件夹整体移动之后，移动后与已有目录重复的只保留一个。
+    pub fn relocate_album_dir(
+        &mut self,
+        from: impl AsRef<Path>,
+        to: impl AsRef<Path>,
+    ) -> Result<DirChange, DirError> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        if !self.album_dirs.iter().any(|dir| dir.starts_with(from)) {
+            return Err(DirError::NotFound(from.to_path_buf()));
+        }
+
+        let relocate = |dirs: &[PathBuf]| {
+            let mut relocated: Vec<PathBuf> = Vec::with_capacity(dirs.len());
+            for dir in dirs {
+                let dir = match dir.strip_prefix(from) {
+                    Ok(relative) if relative.as_os_str().is_empty() => to.to_path_buf(),
+                    Ok(relative) => to.join(relative),
+                    Err(_) => dir.clone(),
+                };
+                if !relocated.contains(&dir) {
+                    relocated.push(dir);
+                }
+            }
+            relocated
+        };
+        let album_dirs = relocate(&self.album_dirs);
+        let excluded_dirs = relocate(&self.excluded_dirs);
+        self.replace_dirs(Some(album_dirs), Some(excluded_dirs))?;
+        Ok(DirChange::AlbumMoved {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        })
+    }
+
+    /// 把第 `from` 个相册目录移动到第 `to` 个位置
+    pub fn move_album_dir(&mut self, from: usize, to: usize) -> Result<DirChange, DirError> {
+        let album_dirs = moved(&self.album_dirs, from, to)?;
+        self.replace_dirs(Some(album_dirs), None)?;
+        Ok(DirChange::AlbumsReordered)
+    }
+
+    /// 添加排除目录，必须位于某个相册目录内，且不能与已有的排除目录相同或互相包含
+    pub fn add_excluded_dir(&mut self, path: impl AsRef<Path>) -> Result<DirChange, DirError> {
+        let path = canonical_dir(path.as_ref())?;
+        if !self.album_dirs.iter().any(|album| path.starts_with(album)) {
+            return Err(DirError::OutsideAlbums(path));
+        }
+        check_overlap(&path, &self.excluded_dirs)?;
+
+        let mut excluded_dirs = self.excluded_dirs.clone();
+        excluded_dirs.push(path.clone());
+        self.replace_dirs(None, Some(excluded_dirs))?;
+        Ok(DirChange::ExcludedAdded(path))
+    }
+
+    pub fn remove_excluded_dir(&mut self, path: impl AsRef<Path>) -> Result<DirChange, DirError> {
+        let index = position(&self.excluded_dirs, path.as_ref())?;
+
+        let mut excluded_dirs = self.excluded_dirs.clone();
+        let removed = excluded_dirs.remove(index);
+        self.replace_dirs(None, Some(excluded_dirs))?;
+        Ok(DirChange::ExcludedRemoved(removed))
+    }
+
+    pub fn move_excluded_dir(&mut self, from: usize, to: usize) -> Result<DirChange, DirError> {
+        let excluded_dirs = moved(&self.excluded_dirs, from, to)?;
+        self.replace_dirs(None, Some(excluded_dirs))?;
+        Ok(DirChange::ExcludedReordered)
+    }
+
+    /// 替换目录列表并写入配置文件，写入失败时恢复原来的列表
+    fn replace_dirs(
+        &mut self,
+        album_dirs: Option<Vec<PathBuf>>,
+        excluded_dirs: Option<Vec<PathBuf>>,
+    ) -> Result<(), DirError> {
+        let old_album_dirs = album_dirs.map(|dirs| std::mem::replace(&mut self.album_dirs, dirs));
+        let old_excluded_dirs =
+            excluded_dirs.map(|dirs| std::mem::replace(&mut self.excluded_dirs, dirs));
+
+        self.try_save().map_err(|e| {
+            if let Some(dirs) = old_album_dirs {
+                self.album_dirs = dirs;
+            }
+            if let Some(dirs) = old_excluded_dirs {
+                self.excluded_dirs = dirs;
+            }
+            DirError::Save(e)
+        })
+    }
+}
+
+fn canonical_dir(path: &Path) -> Result<PathBuf, DirError> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| DirError::Io(path.to_path_buf(), e))?;
+    if !canonical.is_dir() {
+        return Err(DirError::NotADirectory(canonical));
+    }
+    Ok(canonical)
+}
+
+fn check_overlap(path: &Path, dirs: &[PathBuf]) -> Result<(), DirError> {
+    match dirs
+        .iter()
+        .find(|dir| path.starts_with(dir) || dir.starts_with(path))
+    {
+        Some(dir) if dir == path => Err(DirError::Duplicate(path.to_path_buf())),
+        Some(dir) => Err(DirError::Nested {
+            path: path.to_path_buf(),
+            existing: dir.clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+fn position(dirs: &[PathBuf], path: &Path) -> Result<usize, DirError> {
+    let canonical = path.canonicalize().ok();
+    dirs.iter()
+        .position(|dir| dir == path || Some(dir) == canonical.as_ref())
+        .ok_or_else(|| DirError::NotFound(path.to_path_buf()))
+}
+
+fn moved(dirs: &[PathBuf], from: usize, to: usize) -> Result<Vec<PathBuf>, DirError> {
+    if let Some(index) = [from, to].into_iter().find(|&index| index >= dirs.len()) {
+        return Err(DirError::OutOfRange(index));
+    }
+    let mut dirs = dirs.to_vec();
+    let dir = dirs.remove(from);
+    dirs.insert(to, dir);
+    Ok(dirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{ConfigError, MeshDirs};
+
+    /// 没有相册目录的配置，保存在临时目录中
+    fn empty_config(base: &Path) -> MeshConfig {
+        let mut config = MeshConfig::new(&MeshDirs::new(base.join("mesh")));
+        config.album_dirs.clear();
+        config.try_save().unwrap();
+        config
+    }
+
+    fn saved_dirs(base: &Path) -> (Vec<PathBuf>, Vec<PathBuf>) {
+        let config = MeshConfig::load(&MeshDirs::new(base.join("mesh"))).unwrap();
+        (config.album_dirs, config.excluded_dirs)
+    }
+
+    #[test]
+    fn add_album_dir_rejects_overlaps() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().canonicalize().unwrap();
+        let photos = base.join("photos");
+        fs::create_dir_all(photos.join("trips")).unwrap();
+        fs::write(base.join("file.jpg"), b"").unwrap();
+        let mut config = empty_config(&base);
+
+        // 路径被规范化
+        let change = config.add_album_dir(photos.join("trips/..")).unwrap();
+        assert_eq!(change, DirChange::AlbumAdded(photos.clone()));
+        assert_eq!(change.index_root(), Some(photos.as_path()));
+        assert_eq!(change.prune_root(), None);
+
+        assert!(matches!(
+            config.add_album_dir(&photos),
+            Err(DirError::Duplicate(path)) if path == photos
+        ));
+        assert!(matches!(
+            config.add_album_dir(photos.join("trips")),
+            Err(DirError::Nested { existing, .. }) if existing == photos
+        ));
+        assert!(matches!(
+            config.add_album_dir(&base),
+            Err(DirError::Nested { existing, .. }) if existing == photos
+        ));
+        assert!(matches!(
+            config.add_album_dir(base.join("file.jpg")),
+            Err(DirError::NotADirectory(_))
+        ));
+        assert!(matches!(
+            config.add_album_dir(base.join("missing")),
+            Err(DirError::Io(..))
+        ));
+
+        assert_eq!(saved_dirs(&base), (vec![photos], vec![]));
+    }
+
+    #[test]
+    fn excluded_dirs_must_be_inside_albums() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().canonicalize().unwrap();
+        let photos = base.join("photos");
+        let raw = photos.join("raw");
+        let other = base.join("other");
+        fs::create_dir_all(raw.join("2024")).unwrap();
+        fs::create_dir_all(&other).unwrap();
+        let mut config = empty_config(&base);
+        config.add_album_dir(&photos).unwrap();
+
+        assert!(matches!(
+            config.add_excluded_dir(&other),
+            Err(DirError::OutsideAlbums(path)) if path == other
+        ));
+        let change = config.add_excluded_dir(&raw).unwrap();
+        assert_eq!(change.prune_root(), Some(raw.as_path()));
+        assert!(matches!(
+            config.add_excluded_dir(raw.join("2024")),
+            Err(DirError::Nested { .. })
+        ));
+
+        // 目录被删除后仍能按原样移除
+        fs::remove_dir_all(&raw).unwrap();
+        let change = config.remove_excluded_dir(&raw).unwrap();
+        assert_eq!(change, DirChange::ExcludedRemoved(raw.clone()));
+        assert_eq!(change.index_root(), Some(raw.as_path()));
+        assert!(matches!(
+            config.remove_excluded_dir(&raw),
+            Err(DirError::NotFound(_))
+        ));
+        assert_eq!(saved_dirs(&base), (vec![photos], vec![]));
+    }
+
+    #[test]
+    fn move_album_dir_reorders() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().canonicalize().unwrap();
+        let albums: Vec<PathBuf> = ["a", "b", "c"].iter().map(|name| base.join(name)).collect();
+        let mut config = empty_config(&base);
+        for album in &albums {
+            fs::create_dir_all(album).unwrap();
+            config.add_album_dir(album).unwrap();
+        }
+
+        assert_eq!(
+            config.move_album_dir(2, 0).unwrap(),
+            DirChange::AlbumsReordered
+        );
+        let expected = vec![albums[2].clone(), albums[0].clone(), albums[1].clone()];
+        assert_eq!(config.album_dirs, expected);
+        assert!(matches!(
+            config.move_album_dir(0, 3),
+            Err(DirError::OutOfRange(3))
+        ));
+        assert_eq!(saved_dirs(&base).0, expected);
+    }
+
+    #[test]
+    fn relocate_album_dir_moves_nested_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = empty_config(dir.path());
+        config.album_dirs = vec![
+            PathBuf::from("/media/old/photos"),
+            PathBuf::from("/media/old/videos"),
+            PathBuf::from("/home/user/Pictures"),
+            PathBuf::from("/media/new/photos"),
+        ];
+        config.excluded_dirs = vec![
+            PathBuf::from("/media/old/photos/raw"),
+            PathBuf::from("/home/user/Pictures/tmp"),
+        ];
+
+        let change = config
+            .relocate_album_dir("/media/old", "/media/new")
+            .unwrap();
+        assert_eq!(
+            change,
+            DirChange::AlbumMoved {
+                from: PathBuf::from("/media/old"),
+                to: PathBuf::from("/media/new"),
+            }
+        );
+        // 移动后重复的目录只保留一个
+        let albums = vec![
+            PathBuf::from("/media/new/photos"),
+            PathBuf::from("/media/new/videos"),
+            PathBuf::from("/home/user/Pictures"),
+        ];
+        let excluded = vec![
+            PathBuf::from("/media/new/photos/raw"),
+            PathBuf::from("/home/user/Pictures/tmp"),
+        ];
+        assert_eq!(config.album_dirs, albums);
+        assert_eq!(config.excluded_dirs, excluded);
+        assert_eq!(saved_dirs(dir.path()), (albums, excluded));
+
+        // 相册目录本身移动
+        config
+            .relocate_album_dir("/home/user/Pictures", "/data/Pictures")
+            .unwrap();
+        assert_eq!(config.album_dirs[2], PathBuf::from("/data/Pictures"));
+        assert_eq!(config.excluded_dirs[1], PathBuf::from("/data/Pictures/tmp"));
+
+        assert!(matches!(
+            config.relocate_album_dir("/media/old", "/media/other"),
+            Err(DirError::NotFound(_))
+        ));
+        // 只匹配完整的路径段
+        assert!(matches!(
+            config.relocate_album_dir("/media/ne", "/media/other"),
+            Err(DirError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn failed_save_restores_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().canonicalize().unwrap();
+        let photos = base.join("photos");
+        fs::create_dir_all(&photos).unwrap();
+        let mut config = empty_config(&base);
+        config.load_error = Some(ConfigError::Io {
+            path: config.path.clone(),
+            error: io::Error::other("unreadable"),
+        });
+
+        assert!(matches!(
+            config.add_album_dir(&photos),
+            Err(DirError::Save(_))
+        ));
+        assert!(config.album_dirs.is_empty());
+
+        config.album_dirs = vec![photos.clone()];
+        config.excluded_dirs = vec![photos.join("raw")];
+        assert!(matches!(
+            config.relocate_album_dir(&base, "/elsewhere"),
+            Err(DirError::Save(_))
+        ));
+        assert_eq!(config.album_dirs, std::slice::from_ref(&photos));
+        assert_eq!(config.excluded_dirs, [photos.join("raw")]);
+    }
+}
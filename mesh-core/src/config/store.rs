@@ -0,0 +1,384 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, RwLock, RwLockReadGuard, Weak,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use super::{DirChange, DirError, MeshConfig};
+use crate::{MediaFormat, ThumbnailBackend};
+
+/// 可以直接赋值的配置项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigValue {
+    Theme(String),
+    ThumbnailCacheLimitMb(u64),
+    ThumbnailStore(ThumbnailBackend),
+    Formats(Vec<MediaFormat>),
+    DecodeInWorker(bool),
+    DecodeTimeoutSecs(u64),
+    IgnorePatterns(Vec<String>),
+    SkipHidden(bool),
+    MinFileSizeKb(u64),
+    MinWidth(u32),
+    MinHeight(u32),
+    FollowSymlinks(bool),
+}
+
+/// 通知订阅者的配置变化
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigChange {
+    Value(ConfigValue),
+    Dirs(DirChange),
+}
+
+impl MeshConfig {
+    /// 修改一个配置项，返回修改前的值
+    pub fn set(&mut self, value: ConfigValue) -> ConfigValue {
+        use std::mem::replace;
+
+        match value {
+            ConfigValue::Theme(theme) => ConfigValue::Theme(replace(&mut self.theme, theme)),
+            ConfigValue::ThumbnailCacheLimitMb(limit) => ConfigValue::ThumbnailCacheLimitMb(
+                replace(&mut self.thumbnail_cache_limit_mb, limit),
+            ),
+            ConfigValue::ThumbnailStore(backend) => {
+                ConfigValue::ThumbnailStore(replace(&mut self.thumbnail_store, backend))
+            }
+            ConfigValue::Formats(formats) => {
+                ConfigValue::Formats(replace(&mut self.formats, formats))
+            }
+            ConfigValue::DecodeInWorker(enabled) => {
+                ConfigValue::DecodeInWorker(replace(&mut self.decode_in_worker, enabled))
+            }
+            ConfigValue::DecodeTimeoutSecs(secs) => {
+                ConfigValue::DecodeTimeoutSecs(replace(&mut self.decode_timeout_secs, secs))
+            }
+            ConfigValue::IgnorePatterns(patterns) => {
+                ConfigValue::IgnorePatterns(replace(&mut self.ignore_patterns, patterns))
+            }
+            ConfigValue::SkipHidden(skip) => {
+                ConfigValue::SkipHidden(replace(&mut self.skip_hidden, skip))
+            }
+            ConfigValue::MinFileSizeKb(size) => {
+                ConfigValue::MinFileSizeKb(replace(&mut self.min_file_size_kb, size))
+            }
+            ConfigValue::MinWidth(width) => {
+                ConfigValue::MinWidth(replace(&mut self.min_width, width))
+            }
+            ConfigValue::MinHeight(height) => {
+                ConfigValue::MinHeight(replace(&mut self.min_height, height))
+            }
+            ConfigValue::FollowSymlinks(follow) => {
+                ConfigValue::FollowSymlinks(replace(&mut self.follow_symlinks, follow))
+            }
+        }
+    }
+}
+
+type Subscriber = Arc<dyn Fn(&ConfigChange) + Send + Sync>;
+
+struct Inner {
+    config: RwLock<MeshConfig>,
+    subscribers: Mutex<Vec<(usize, Subscriber)>>,
+    next_id: AtomicUsize,
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+/// 可在线程间共享的配置，每次修改都会写入配置文件并通知订阅者
+///
+/// 界面、扫描器与文件监视器通过同一个 `ConfigStore` 读取和修改配置。
+#[derive(Clone)]
+pub struct ConfigStore {
+    inner: Arc<Inner>,
+}
+
+impl ConfigStore {
+    pub fn new(config: MeshConfig) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                config: RwLock::new(config),
+                subscribers: Mutex::new(Vec::new()),
+                next_id: AtomicUsize::new(0),
+                watcher: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// 读取当前配置，持有期间其他线程的修改会被阻塞
+    pub fn read(&self) -> RwLockReadGuard<'_, MeshConfig> {
+        self.inner.config.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 修改一个配置项并保存，值没有变化时不保存也不通知
+    ///
+    /// 保存失败时恢复原来的值。
+    pub fn set(&self, value: ConfigValue) -> io::Result<()> {
+        let merged = {
+            let mut config = self.inner.config.write().unwrap_or_else(|e| e.into_inner());
+            let previous = config.set(value.clone());
+            if previous == value {
+                return Ok(());
+            }
+            if let Err(e) = config.try_save() {
+                config.set(previous);
+                return Err(e);
+            }
+            std::mem::take(&mut config.merged_changes)
+        };
+
+        self.notify(&ConfigChange::Value(value));
+        for change in &merged {
+            self.notify(change);
+        }
+        Ok(())
+    }
+
+    pub fn add_album_dir(&self, path: impl AsRef<Path>) -> Result<DirChange, DirError> {
+        self.update_dirs(|config| config.add_album_dir(path))
+    }
+
+    pub fn remove_album_dir(&self, path: impl AsRef<Path>) -> Result<DirChange, DirError> {
+        self.update_dirs(|config| config.remove_album_dir(path))
+    }
+
+    pub fn move_album_dir(&self, from: usize, to: usize) -> Result<DirChange, DirError> {
+        self.update_dirs(|config| config.move_album_dir(from, to))
+    }
+
+    pub fn relocate_album_dir(
+        &self,
+        from: impl AsRef<Path>,
+        to: impl AsRef<Path>,
+    ) -> Result<DirChange, DirError> {
+        self.update_dirs(|config| config.relocate_album_dir(from, to))
+    }
+
+    pub fn add_excluded_dir(&self, path: impl AsRef<Path>) -> Result<DirChange, DirError> {
+        self.update_dirs(|config| config.add_excluded_dir(path))
+    }
+
+    pub fn remove_excluded_dir(&self, path: impl AsRef<Path>) -> Result<DirChange, DirError> {
+        self.update_dirs(|config| config.remove_excluded_dir(path))
+    }
+
+    pub fn move_excluded_dir(&self, from: usize, to: usize) -> Result<DirChange, DirError> {
+        self.update_dirs(|config| config.move_excluded_dir(from, to))
+    }
+
+    fn update_dirs(
+        &self,
+        update: impl FnOnce(&mut MeshConfig) -> Result<DirChange, DirError>,
+    ) -> Result<DirChange, DirError> {
+        let (change, merged) = {
+            let mut config = self.inner.config.write().unwrap_or_else(|e| e.into_inner());
+            let change = update(&mut config)?;
+            (change, std::mem::take(&mut config.merged_changes))
+        };
+
+        self.notify(&ConfigChange::Dirs(change.clone()));
+        for change in &merged {
+            self.notify(change);
+        }
+        Ok(change)
+    }
+
+    /// 重新读取配置文件，采用文件中的配置并把与内存中不同的项通知订阅者
+    ///
+    /// 文件无法解析时保留当前配置，并在修复之前拒绝保存；文件不存在时不做任何修改。
+    pub fn reload(&self) -> Vec<ConfigChange> {
+        let changes = {
+            let mut config = self.inner.config.write().unwrap_or_else(|e| e.into_inner());
+            match MeshConfig::read_file(&config.path) {
+                Ok(Some(new)) => {
+                    let changes = diff(&config, &new);
+                    *config = new;
+                    changes
+                }
+                Ok(None) => Vec::new(),
+                Err(e) => {
+                    log::error!("Failed to reload config: {}", e);
+                    config.load_error = Some(e);
+                    Vec::new()
+                }
+            }
+        };
+
+        for change in &changes {
+            self.notify(change);
+        }
+        changes
+    }
+
+    /// 监视配置文件，被外部修改时调用 [`reload`](Self::reload)
+    ///
+    /// 监视的是配置目录，编辑器以重命名方式保存文件时也能收到通知。
+    pub fn watch(&self) -> notify::Result<()> {
+        let config_path = self.read().path.clone();
+        let inner = Arc::downgrade(&self.inner);
+        let mut watcher = notify::recommended_watcher({
+            let config_path = config_path.clone();
+            move |event: notify::Result<notify::Event>| {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(e) => {
+                        log::warn!("Config watcher error: {}", e);
+                        return;
+                    }
+                };
+                if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                    || !event.paths.contains(&config_path)
+                {
+                    return;
+                }
+                if let Some(inner) = inner.upgrade() {
+                    ConfigStore { inner }.reload();
+                }
+            }
+        })?;
+        watcher.watch(
+            config_path.parent().unwrap_or(&config_path),
+            RecursiveMode::NonRecursive,
+        )?;
+
+        *self.inner.watcher.lock().unwrap_or_else(|e| e.into_inner()) = Some(watcher);
+        Ok(())
+    }
+
+    /// 订阅配置变化，回调在发起修改的线程上调用
+    ///
+    /// 返回的 [`ConfigSubscription`] 被丢弃时取消订阅。
+    pub fn subscribe(
+        &self,
+        subscriber: impl Fn(&ConfigChange) + Send + Sync + 'static,
+    ) -> ConfigSubscription {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        self.subscribers().push((id, Arc::new(subscriber)));
+        ConfigSubscription {
+            inner: Arc::downgrade(&self.inner),
+            id,
+        }
+    }
+
+    fn subscribers(&self) -> std::sync::MutexGuard<'_, Vec<(usize, Subscriber)>> {
+        self.inner
+            .subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 在释放所有锁之后调用订阅者，订阅者可以读取或修改配置
+    fn notify(&self, change: &ConfigChange) {
+        let subscribers: Vec<Subscriber> = self
+            .subscribers()
+            .iter()
+            .map(|(_, subscriber)| subscriber.clone())
+            .collect();
+        for subscriber in subscribers {
+            subscriber(change);
+        }
+    }
+}
+
+/// 比较两份配置，返回从 `old` 变为 `new` 的所有变化
+pub(super) fn diff(old: &MeshConfig, new: &MeshConfig) -> Vec<ConfigChange> {
+    let mut changes = Vec::new();
+    diff_dirs(
+        &old.album_dirs,
+        &new.album_dirs,
+        DirChange::AlbumRemoved,
+        DirChange::AlbumAdded,
+        DirChange::AlbumsReordered,
+        &mut changes,
+    );
+    diff_dirs(
+        &old.excluded_dirs,
+        &new.excluded_dirs,
+        DirChange::ExcludedRemoved,
+        DirChange::ExcludedAdded,
+        DirChange::ExcludedReordered,
+        &mut changes,
+    );
+
+    changes.extend(
+        values(old)
+            .into_iter()
+            .zip(values(new))
+            .filter(|(old, new)| old != new)
+            .map(|(_, new)| ConfigChange::Value(new)),
+    );
+    changes
+}
+
+fn values(config: &MeshConfig) -> [ConfigValue; 12] {
+    [
+        ConfigValue::Theme(config.theme.clone()),
+        ConfigValue::ThumbnailCacheLimitMb(config.thumbnail_cache_limit_mb),
+        ConfigValue::ThumbnailStore(config.thumbnail_store),
+        ConfigValue::Formats(config.formats.clone()),
+        ConfigValue::DecodeInWorker(config.decode_in_worker),
+        ConfigValue::DecodeTimeoutSecs(config.decode_timeout_secs),
+        ConfigValue::IgnorePatterns(config.ignore_patterns.clone()),
+        ConfigValue::SkipHidden(config.skip_hidden),
+        ConfigValue::MinFileSizeKb(config.min_file_size_kb),
+        ConfigValue::MinWidth(config.min_width),
+        ConfigValue::MinHeight(config.min_height),
+        ConfigValue::FollowSymlinks(config.follow_symlinks),
+    ]
+}
+
+fn diff_dirs(
+    old: &[PathBuf],
+    new: &[PathBuf],
+    removed: fn(PathBuf) -> DirChange,
+    added: fn(PathBuf) -> DirChange,
+    reordered: DirChange,
+    changes: &mut Vec<ConfigChange>,
+) {
+    let kept_old: Vec<_> = old.iter().filter(|dir| new.contains(dir)).collect();
+    let kept_new: Vec<_> = new.iter().filter(|dir| old.contains(dir)).collect();
+
+    changes.extend(
+        old.iter()
+            .filter(|dir| !new.contains(dir))
+            .map(|dir| ConfigChange::Dirs(removed(dir.clone()))),
+    );
+    changes.extend(
+        new.iter()
+            .filter(|dir| !old.contains(dir))
+            .map(|dir| ConfigChange::Dirs(added(dir.clone()))),
+    );
+    if kept_old != kept_new {
+        changes.push(ConfigChange::Dirs(reordered));
+    }
+}
+
+/// 配置变化的订阅，被丢弃时取消订阅
+#[must_use = "dropping the subscription unsubscribes immediately"]
+pub struct ConfigSubscription {
+    inner: Weak<Inner>,
+    id: usize,
+}
+
+impl ConfigSubscription {
+    /// 保持订阅直到 [`ConfigStore`] 被释放
+    pub fn detach(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for ConfigSubscription {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.upgrade() {
+            inner
+                .subscribers
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .retain(|(id, _)| *id != self.id);
+        }
+    }
+}
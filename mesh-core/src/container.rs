@@ -0,0 +1,150 @@
+//! RAW 与视频解析共用的 ISOBMFF 遍历与读取工具
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// 读取下一个 ISOBMFF box 的类型与数据范围，读到文件末尾返回 `None`
+///
+/// 返回后读取位置位于 box 数据的开头。
+pub(crate) fn next_box<R: Read + Seek>(reader: &mut R) -> io::Result<Option<([u8; 4], u64, u64)>> {
+    let mut header = [0u8; 8];
+    if read_up_to(reader, &mut header)? < 8 {
+        return Ok(None);
+    }
+    let box_type: [u8; 4] = header[4..8].try_into().unwrap();
+    let (header_len, size) = match u32::from_be_bytes(header[0..4].try_into().unwrap()) {
+        // 延伸到文件末尾
+        0 => {
+            let position = reader.stream_position()?;
+            let end = reader.seek(SeekFrom::End(0))?;
+            reader.seek(SeekFrom::Start(position))?;
+            (8, end - position + 8)
+        }
+        1 => {
+            let mut large = [0u8; 8];
+            reader.read_exact(&mut large)?;
+            (16, u64::from_be_bytes(large))
+        }
+        size => (8, size as u64),
+    };
+    if size < header_len {
+        return Ok(None);
+    }
+
+    let body_offset = reader.stream_position()?;
+    Ok(Some((box_type, body_offset, size - header_len)))
+}
+
+/// 遍历内存中的 ISOBMFF box，返回类型与数据
+pub(crate) fn boxes(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let size = u32::from_be_bytes(data.get(0..4)?.try_into().ok()?) as usize;
+        let box_type: [u8; 4] = data.get(4..8)?.try_into().ok()?;
+        let body = data.get(8..size)?;
+        data = &data[size..];
+        Some((box_type, body))
+    })
+}
+
+/// 尽量填满 `buf`，返回实际读取的字节数
+pub(crate) fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn next_box_reads_box_headers() {
+        let mut data = 16u32.to_be_bytes().to_vec();
+        data.extend_from_slice(b"ftyp");
+        data.extend_from_slice(&[0; 8]);
+        // 64 位长度
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&20u64.to_be_bytes());
+        data.extend_from_slice(&[0; 4]);
+        // 延伸到文件末尾
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(b"free");
+        data.extend_from_slice(&[0; 6]);
+
+        let mut reader = Cursor::new(&data);
+        assert_eq!(next_box(&mut reader).unwrap(), Some((*b"ftyp", 8, 8)));
+        reader.seek(SeekFrom::Start(16)).unwrap();
+        assert_eq!(next_box(&mut reader).unwrap(), Some((*b"mdat", 32, 4)));
+        reader.seek(SeekFrom::Start(36)).unwrap();
+        assert_eq!(next_box(&mut reader).unwrap(), Some((*b"free", 44, 6)));
+        reader.seek(SeekFrom::Start(50)).unwrap();
+        assert_eq!(next_box(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn next_box_rejects_truncated_and_undersized_boxes() {
+        for len in 0..8 {
+            let data = [0, 0, 0, 16, b'm', b'o', b'o', b'v'];
+            assert_eq!(next_box(&mut Cursor::new(&data[..len])).unwrap(), None);
+        }
+        // 长度小于头部
+        let data = [0, 0, 0, 7, b'm', b'o', b'o', b'v'];
+        assert_eq!(next_box(&mut Cursor::new(&data)).unwrap(), None);
+        let mut data = vec![0, 0, 0, 1, b'm', b'o', b'o', b'v'];
+        data.extend_from_slice(&15u64.to_be_bytes());
+        assert_eq!(next_box(&mut Cursor::new(&data)).unwrap(), None);
+        // 64 位长度不完整
+        let data = [0, 0, 0, 1, b'm', b'o', b'o', b'v', 0, 0];
+        assert!(next_box(&mut Cursor::new(&data)).is_err());
+    }
+
+    #[test]
+    fn boxes_stop_at_invalid_sizes() {
+        let mut data = 10u32.to_be_bytes().to_vec();
+        data.extend_from_slice(b"CMT1ab");
+        data.extend_from_slice(&8u32.to_be_bytes());
+        data.extend_from_slice(b"CMT2");
+        let found: Vec<_> = boxes(&data).collect();
+        assert_eq!(found, vec![(*b"CMT1", &b"ab"[..]), (*b"CMT2", &b""[..])]);
+
+        // 长度超出数据、小于头部或为 0 时停止
+        for size in [100u32, 4, 0] {
+            let mut data = size.to_be_bytes().to_vec();
+            data.extend_from_slice(b"CMT1abcd");
+            assert_eq!(boxes(&data).count(), 0, "{}", size);
+        }
+        assert_eq!(boxes(&[0, 0, 0]).count(), 0);
+    }
+
+    /// 每次只返回一个字节的读取器
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some((&first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            buf[0] = first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn read_up_to_fills_buffer_from_short_reads() {
+        let mut buf = [0u8; 4];
+        assert_eq!(read_up_to(&mut Trickle(b"abcdef"), &mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"abcd");
+        assert_eq!(read_up_to(&mut Trickle(b"ab"), &mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"ab");
+    }
+}
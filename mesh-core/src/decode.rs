@@ -0,0 +1,412 @@
+use std::{
+    fs,
+    io::{BufRead, Cursor, Seek},
+    path::Path,
+};
+
+use image::{
+    DynamicImage, GenericImageView, GrayImage, ImageDecoder, ImageError, ImageFormat, ImageReader,
+    ImageResult, Limits, RgbImage,
+    error::{
+        DecodingError, ImageFormatHint, LimitError, LimitErrorKind, UnsupportedError,
+        UnsupportedErrorKind,
+    },
+    metadata::Orientation,
+};
+
+use crate::{FormatDecoder, MediaFormat, color, raw, resize};
+
+/// 允许解码的最大宽度与高度
+pub const MAX_IMAGE_DIMENSION: u32 = 1 << 16;
+/// 解码单张图片允许分配的最大内存（字节）
+pub const MAX_DECODE_ALLOC: u64 = 1 << 30;
+
+/// 为生成缩略图解码得到的图片
+#[derive(Debug)]
+pub struct DecodedImage {
+    /// 解码结果，尺寸可能已经小于原图
+    pub image: DynamicImage,
+    /// 原图宽度
+    pub width: u32,
+    /// 原图高度
+    pub height: u32,
+    /// 内嵌的 ICC 配置文件
+    ///
+    /// `image` 尚未转换颜色空间，[`resize_for_thumbnail`] 缩放后再转换到 sRGB。
+    pub icc: Option<Vec<u8>>,
+    /// 相机 RAW 的 EXIF 中记录的拍摄时间，UNIX 时间戳（秒）
+    pub captured_at: Option<i64>,
+}
+
+impl DecodedImage {
+    fn new(image: DynamicImage, width: u32, height: u32, icc: Option<Vec<u8>>) -> Self {
+        Self {
+            image,
+            width,
+            height,
+            icc,
+            captured_at: None,
+        }
+    }
+}
+
+/// 以不低于 `max_height` 的分辨率解码图片，用于生成缩略图
+///
+/// `format` 为按文件内容识别出的格式。JPEG 优先使用足够大的 EXIF 内嵌缩略图，
+/// 否则按 1/2、1/4、1/8 做 DCT 缩放解码；相机 RAW 以同样的方式解码内嵌的 JPEG 预览；
+/// 其他格式按原尺寸解码。
+pub fn decode_for_thumbnail(
+    path: &Path,
+    format: MediaFormat,
+    max_height: u32,
+) -> ImageResult<DecodedImage> {
+    let image_format = match format.info().decoder {
+        FormatDecoder::Image(image_format) => image_format,
+        FormatDecoder::RawPreview => return decode_raw_for_thumbnail(path, max_height),
+        FormatDecoder::Video | FormatDecoder::Unsupported => {
+            return Err(unsupported(path, format!("decoding {}", format)));
+        }
+    };
+
+    if image_format == ImageFormat::Jpeg {
+        let data = fs::read(path)?;
+        if let Some(decoded) = decode_jpeg_scaled(&data, max_height)? {
+            return Ok(decoded);
+        }
+    }
+
+    let (image, icc) = decode_file(path, image_format)?;
+    let (width, height) = image.dimensions();
+    Ok(DecodedImage::new(image, width, height, icc))
+}
+
+/// 按文件内容识别格式并以原尺寸解码用于查看，相机 RAW 返回按方向旋转后的内嵌预览
+///
+/// 带有 ICC 配置文件的图片会转换到 sRGB。
+pub fn open_image(path: &Path) -> ImageResult<DynamicImage> {
+    let format = MediaFormat::detect(path)?
+        .ok_or_else(|| unsupported(path, "unrecognized content".to_owned()))?;
+    match format.info().decoder {
+        FormatDecoder::Image(image_format) => {
+            let (image, icc) = decode_file(path, image_format)?;
+            Ok(color::to_srgb(image, icc.as_deref()).0)
+        }
+        FormatDecoder::RawPreview => {
+            let (preview, orientation) = raw_preview(path)?;
+            let (image, icc) = decode_memory(&preview.data, ImageFormat::Jpeg)?;
+            let (mut image, _) = color::to_srgb(image, icc.as_deref());
+            image.apply_orientation(orientation);
+            Ok(image)
+        }
+        FormatDecoder::Video | FormatDecoder::Unsupported => {
+            Err(unsupported(path, format!("decoding {}", format)))
+        }
+    }
+}
+
+/// 只读取文件头获取图片的宽高，无法读取时返回 `None`
+pub(crate) fn image_dimensions(path: &Path, format: ImageFormat) -> Option<(u32, u32)> {
+    let mut reader = ImageReader::open(path).ok()?;
+    reader.set_format(format);
+    reader.into_dimensions().ok()
+}
+
+/// 解码所用的资源限制，防止超大尺寸或解压炸弹耗尽内存
+fn limits() -> Limits {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+    limits
+}
+
+/// 在解码前检查尺寸是否超出限制，用于不经过 [`decode_reader`] 的解码器
+fn check_limits(width: u32, height: u32, bytes_per_pixel: u64) -> ImageResult<()> {
+    if width > MAX_IMAGE_DIMENSION || height > MAX_IMAGE_DIMENSION {
+        return Err(ImageError::Limits(LimitError::from_kind(
+            LimitErrorKind::DimensionError,
+        )));
+    }
+    if width as u64 * height as u64 * bytes_per_pixel > MAX_DECODE_ALLOC {
+        return Err(ImageError::Limits(LimitError::from_kind(
+            LimitErrorKind::InsufficientMemory,
+        )));
+    }
+    Ok(())
+}
+
+fn decode_file(path: &Path, format: ImageFormat) -> ImageResult<(DynamicImage, Option<Vec<u8>>)> {
+    let mut reader = ImageReader::open(path)?;
+    reader.set_format(format);
+    decode_reader(reader)
+}
+
+fn decode_memory(data: &[u8], format: ImageFormat) -> ImageResult<(DynamicImage, Option<Vec<u8>>)> {
+    decode_reader(ImageReader::with_format(Cursor::new(data), format))
+}
+
+/// 解码并读取内嵌的 ICC 配置文件，读取配置文件失败时按没有配置文件处理
+///
+/// 所有经过 `image` 的解码都从这里进入，统一应用 [`limits`]。
+fn decode_reader<R: BufRead + Seek>(
+    mut reader: ImageReader<R>,
+) -> ImageResult<(DynamicImage, Option<Vec<u8>>)> {
+    let mut limits = limits();
+    reader.limits(limits.clone());
+    let mut decoder = reader.into_decoder()?;
+    // `into_decoder` 不检查输出缓冲区的大小
+    limits.reserve(decoder.total_bytes())?;
+    let icc = decoder.icc_profile().ok().flatten();
+    Ok((DynamicImage::from_decoder(decoder)?, icc))
+}
+
+/// 计算缩略图尺寸：保持宽高比，高度不超过 `max_height`
+pub fn thumbnail_size(width: u32, height: u32, max_height: u32) -> (u32, u32) {
+    if height <= max_height {
+        (width, height)
+    } else {
+        let scale = max_height as f32 / height as f32;
+        (((width as f32 * scale).round() as u32).max(1), max_height)
+    }
+}
+
+/// 把解码结果缩放到缩略图尺寸并转换到 sRGB，同时返回内嵌 ICC 配置文件的名称
+///
+/// JPEG 经过 DCT 缩放后通常只剩 2 倍以内的缩小，但其他格式以及超过 8 倍的 JPEG
+/// 仍按原尺寸缩小，因此使用 SIMD 实现的面积平均算法（见 [`resize::downscale`]）。
+/// 颜色转换在缩放之后进行，只需处理缩略图大小的像素。
+pub fn resize_for_thumbnail(
+    decoded: DecodedImage,
+    max_height: u32,
+) -> (DynamicImage, Option<String>) {
+    let (target_w, target_h) = thumbnail_size(decoded.width, decoded.height, max_height);
+    let image = if decoded.image.dimensions() == (target_w, target_h) {
+        decoded.image
+    } else {
+        resize::downscale(decoded.image, target_w, target_h)
+    };
+    color::to_srgb(image, decoded.icc.as_deref())
+}
+
+/// 返回 `None` 表示该 JPEG 的像素格式不支持缩放解码，应回退到完整解码
+fn decode_jpeg_scaled(data: &[u8], max_height: u32) -> ImageResult<Option<DecodedImage>> {
+    let mut decoder = jpeg_decoder::Decoder::new(Cursor::new(data));
+    decoder.read_info().map_err(jpeg_error)?;
+    let Some(info) = decoder.info() else {
+        return Ok(None);
+    };
+    let (width, height) = (info.width as u32, info.height as u32);
+    // 缩放前的解码缓冲区按原尺寸分配
+    check_limits(width, height, info.pixel_format.pixel_bytes() as u64)?;
+    decoder.set_max_decoding_buffer_size(MAX_DECODE_ALLOC as usize);
+    let (target_w, target_h) = thumbnail_size(width, height, max_height);
+    // EXIF 缩略图与原图使用同一颜色空间
+    let icc = decoder.icc_profile();
+
+    if let Some(image) = decoder
+        .exif_data()
+        .and_then(|exif| exif_thumbnail(exif, width, height, target_h))
+    {
+        return Ok(Some(DecodedImage::new(image, width, height, icc)));
+    }
+
+    let (scaled_w, scaled_h) = decoder
+        .scale(target_w as u16, target_h as u16)
+        .map_err(jpeg_error)?;
+    let pixels = decoder.decode().map_err(jpeg_error)?;
+    let (scaled_w, scaled_h) = (scaled_w as u32, scaled_h as u32);
+
+    let image = match info.pixel_format {
+        jpeg_decoder::PixelFormat::RGB24 => {
+            RgbImage::from_raw(scaled_w, scaled_h, pixels).map(DynamicImage::ImageRgb8)
+        }
+        jpeg_decoder::PixelFormat::L8 => {
+            GrayImage::from_raw(scaled_w, scaled_h, pixels).map(DynamicImage::ImageLuma8)
+        }
+        // CMYK 与 16 位灰度交给 image 处理
+        _ => None,
+    };
+
+    Ok(image.map(|image| DecodedImage::new(image, width, height, icc)))
+}
+
+fn decode_raw_for_thumbnail(path: &Path, max_height: u32) -> ImageResult<DecodedImage> {
+    let (preview, orientation) = raw_preview(path)?;
+    let swaps = matches!(
+        orientation,
+        Orientation::Rotate90
+            | Orientation::Rotate270
+            | Orientation::Rotate90FlipH
+            | Orientation::Rotate270FlipH
+    );
+
+    // 旋转 90° 后缩略图的高度对应预览图的宽度
+    let preview_max_height = if swaps {
+        (max_height as u64 * preview.height as u64).div_ceil(preview.width as u64) as u32
+    } else {
+        max_height
+    };
+
+    let mut decoded = match decode_jpeg_scaled(&preview.data, preview_max_height)? {
+        Some(decoded) => decoded,
+        None => {
+            let (image, icc) = decode_memory(&preview.data, ImageFormat::Jpeg)?;
+            let (width, height) = image.dimensions();
+            DecodedImage::new(image, width, height, icc)
+        }
+    };
+
+    decoded.image.apply_orientation(orientation);
+    // 记录传感器输出的尺寸而不是预览图的尺寸
+    if let Some((width, height)) = preview.sensor_size {
+        decoded.width = width;
+        decoded.height = height;
+    }
+    if swaps {
+        std::mem::swap(&mut decoded.width, &mut decoded.height);
+    }
+    decoded.captured_at = preview.captured_at;
+    Ok(decoded)
+}
+
+fn raw_preview(path: &Path) -> ImageResult<(raw::RawPreview, Orientation)> {
+    let preview = raw::extract_preview(path)?
+        .ok_or_else(|| unsupported(path, "RAW file without JPEG preview".to_owned()))?;
+    let orientation =
+        Orientation::from_exif(preview.orientation).unwrap_or(Orientation::NoTransforms);
+    Ok((preview, orientation))
+}
+
+/// 读取 EXIF 中的 JPEG 缩略图，只有宽高比与原图一致且高度足够时才使用
+fn exif_thumbnail(exif: &[u8], width: u32, height: u32, min_height: u32) -> Option<DynamicImage> {
+    let exif = exif::Reader::new().read_raw(exif.to_vec()).ok()?;
+    let offset = exif
+        .get_field(exif::Tag::JPEGInterchangeFormat, exif::In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+    let len = exif
+        .get_field(exif::Tag::JPEGInterchangeFormatLength, exif::In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+    let data = exif.buf().get(offset..offset.checked_add(len)?)?;
+
+    let (thumbnail, _) = decode_memory(data, ImageFormat::Jpeg).ok()?;
+    let (thumb_w, thumb_h) = thumbnail.dimensions();
+    if thumb_h < min_height {
+        return None;
+    }
+
+    // 部分相机的内嵌缩略图带黑边，宽高比偏差超过 1% 时不使用
+    let ratio = width as f64 / height as f64;
+    let thumb_ratio = thumb_w as f64 / thumb_h as f64;
+    if (ratio - thumb_ratio).abs() / ratio > 0.01 {
+        return None;
+    }
+    Some(thumbnail)
+}
+
+fn unsupported(path: &Path, feature: String) -> ImageError {
+    ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+        ImageFormatHint::from(path),
+        UnsupportedErrorKind::GenericFeature(feature),
+    ))
+}
+
+fn jpeg_error(e: jpeg_decoder::Error) -> ImageError {
+    match e {
+        jpeg_decoder::Error::Io(e) => ImageError::IoError(e),
+        e => ImageError::Decoding(DecodingError::new(
+            ImageFormatHint::Exact(ImageFormat::Jpeg),
+            e,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageEncoder, Rgb, codecs::png::PngEncoder};
+    use moxcms::ColorProfile;
+
+    use super::*;
+
+    #[test]
+    fn converts_color_after_resizing() {
+        let icc = ColorProfile::new_display_p3().encode().unwrap();
+        let image = RgbImage::from_pixel(900, 600, Rgb([200, 120, 40]));
+        let mut data = Vec::new();
+        let mut encoder = PngEncoder::new(&mut data);
+        encoder.set_icc_profile(icc).unwrap();
+        encoder
+            .write_image(image.as_raw(), 900, 600, image::ExtendedColorType::Rgb8)
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("p3.png");
+        fs::write(&path, data).unwrap();
+
+        // 解码结果保留原始像素，等到缩放之后再转换
+        let decoded = decode_for_thumbnail(&path, MediaFormat::Png, 300).unwrap();
+        assert!(decoded.icc.is_some());
+        assert_eq!(
+            decoded.image.to_rgb8().get_pixel(0, 0),
+            &Rgb([200, 120, 40])
+        );
+
+        let (thumbnail, color_space) = resize_for_thumbnail(decoded, 300);
+        assert_eq!(thumbnail.dimensions(), (450, 300));
+        assert!(color_space.is_some());
+        assert_ne!(thumbnail.to_rgb8().get_pixel(0, 0), &Rgb([200, 120, 40]));
+    }
+
+    #[test]
+    fn keeps_pixels_without_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plain.png");
+        RgbImage::from_pixel(90, 60, Rgb([200, 120, 40]))
+            .save(&path)
+            .unwrap();
+
+        let decoded = decode_for_thumbnail(&path, MediaFormat::Png, 300).unwrap();
+        assert_eq!(decoded.icc, None);
+        let (thumbnail, color_space) = resize_for_thumbnail(decoded, 300);
+        assert_eq!(thumbnail.dimensions(), (90, 60));
+        assert_eq!(color_space, None);
+        assert_eq!(thumbnail.to_rgb8().get_pixel(0, 0), &Rgb([200, 120, 40]));
+    }
+
+    /// 只有文件头、声明为 `width` × `height` 的 24 位 BMP
+    fn bmp_header(width: i32, height: i32) -> Vec<u8> {
+        let mut data = b"BM".to_vec();
+        data.extend_from_slice(&54u32.to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&54u32.to_le_bytes());
+        data.extend_from_slice(&40u32.to_le_bytes());
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&24u16.to_le_bytes());
+        data.extend_from_slice(&[0; 24]);
+        data
+    }
+
+    #[test]
+    fn rejects_images_over_memory_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("huge.bmp");
+
+        // 尺寸在限制内，但解码缓冲区超出内存限制
+        fs::write(&path, bmp_header(40_000, 40_000)).unwrap();
+        let result = decode_for_thumbnail(&path, MediaFormat::Bmp, 300);
+        assert!(matches!(result, Err(ImageError::Limits(_))), "{result:?}");
+    }
+
+    #[test]
+    fn check_limits_bounds_dimensions_and_memory() {
+        assert!(check_limits(MAX_IMAGE_DIMENSION, 1, 3).is_ok());
+        assert!(check_limits(MAX_IMAGE_DIMENSION + 1, 1, 3).is_err());
+        assert!(check_limits(1, MAX_IMAGE_DIMENSION + 1, 3).is_err());
+        // 16384 × 16384 × 4 字节恰好等于内存上限
+        assert!(check_limits(16_384, 16_384, 4).is_ok());
+        assert!(check_limits(16_384, 16_385, 4).is_err());
+        assert!(check_limits(MAX_IMAGE_DIMENSION, MAX_IMAGE_DIMENSION, 1).is_err());
+    }
+}
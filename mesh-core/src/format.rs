@@ -0,0 +1,657 @@
+use std::{fs::File, io, path::Path};
+
+use image::{
+    DynamicImage, ImageEncoder, ImageResult,
+    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::container::read_up_to;
+
+/// 判断格式时读取的文件开头字节数
+const SNIFF_LEN: usize = 64;
+
+/// Mesh 能够索引的文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaFormat {
+    Jpeg,
+    Png,
+    Webp,
+    Gif,
+    Bmp,
+    Tiff,
+    Avif,
+    Cr2,
+    Cr3,
+    Nef,
+    Arw,
+    Dng,
+    Raf,
+    Mp4,
+    Mov,
+    Mkv,
+    Webm,
+    Avi,
+    Heic,
+}
+
+/// 照片库中条目的类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MediaType {
+    #[default]
+    Photo,
+    Video,
+}
+
+impl MediaType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Photo => "photo",
+            Self::Video => "video",
+        }
+    }
+}
+
+impl std::fmt::Display for MediaType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 格式的解码方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatDecoder {
+    /// 使用 image 解码
+    Image(image::ImageFormat),
+    /// 相机 RAW，使用内嵌的 JPEG 预览
+    RawPreview,
+    /// 视频，只读取容器元数据，不生成缩略图
+    Video,
+    /// 暂无解码器，只用于识别文件内容
+    Unsupported,
+}
+
+/// 缩略图的编码格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbnailEncoder {
+    /// 质量 70
+    Jpeg,
+    /// 压缩级别 7，保留透明通道
+    Png,
+}
+
+/// 格式注册表中的一项
+#[derive(Debug)]
+pub struct FormatInfo {
+    pub format: MediaFormat,
+    pub name: &'static str,
+    /// 小写扩展名，第一个为首选
+    pub extensions: &'static [&'static str],
+    pub mime_type: &'static str,
+    /// 解码方式
+    pub decoder: FormatDecoder,
+    /// 当前构建是否能解码该格式
+    pub decodable: bool,
+    pub thumbnail_encoder: ThumbnailEncoder,
+}
+
+static FORMATS: [FormatInfo; 19] = [
+    FormatInfo {
+        format: MediaFormat::Jpeg,
+        name: "JPEG",
+        extensions: &["jpg", "jpeg", "jpe", "jfif"],
+        mime_type: "image/jpeg",
+        decoder: FormatDecoder::Image(image::ImageFormat::Jpeg),
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Jpeg,
+    },
+    FormatInfo {
+        format: MediaFormat::Png,
+        name: "PNG",
+        extensions: &["png"],
+        mime_type: "image/png",
+        decoder: FormatDecoder::Image(image::ImageFormat::Png),
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Png,
+    },
+    FormatInfo {
+        format: MediaFormat::Webp,
+        name: "WebP",
+        extensions: &["webp"],
+        mime_type: "image/webp",
+        decoder: FormatDecoder::Image(image::ImageFormat::WebP),
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Png,
+    },
+    FormatInfo {
+        format: MediaFormat::Gif,
+        name: "GIF",
+        extensions: &["gif"],
+        mime_type: "image/gif",
+        // 动图只解码第一帧
+        decoder: FormatDecoder::Image(image::ImageFormat::Gif),
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Png,
+    },
+    FormatInfo {
+        format: MediaFormat::Bmp,
+        name: "BMP",
+        extensions: &["bmp", "dib"],
+        mime_type: "image/bmp",
+        decoder: FormatDecoder::Image(image::ImageFormat::Bmp),
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Jpeg,
+    },
+    FormatInfo {
+        format: MediaFormat::Tiff,
+        name: "TIFF",
+        extensions: &["tif", "tiff"],
+        mime_type: "image/tiff",
+        decoder: FormatDecoder::Image(image::ImageFormat::Tiff),
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Jpeg,
+    },
+    FormatInfo {
+        format: MediaFormat::Avif,
+        name: "AVIF",
+        extensions: &["avif"],
+        mime_type: "image/avif",
+        // 解码依赖 dav1d，需要开启 `avif` feature
+        decoder: FormatDecoder::Image(image::ImageFormat::Avif),
+        decodable: cfg!(feature = "avif"),
+        thumbnail_encoder: ThumbnailEncoder::Jpeg,
+    },
+    FormatInfo {
+        format: MediaFormat::Cr2,
+        name: "Canon CR2",
+        extensions: &["cr2"],
+        mime_type: "image/x-canon-cr2",
+        decoder: FormatDecoder::RawPreview,
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Jpeg,
+    },
+    FormatInfo {
+        format: MediaFormat::Cr3,
+        name: "Canon CR3",
+        extensions: &["cr3"],
+        mime_type: "image/x-canon-cr3",
+        decoder: FormatDecoder::RawPreview,
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Jpeg,
+    },
+    FormatInfo {
+        format: MediaFormat::Nef,
+        name: "Nikon NEF",
+        extensions: &["nef", "nrw"],
+        mime_type: "image/x-nikon-nef",
+        decoder: FormatDecoder::RawPreview,
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Jpeg,
+    },
+    FormatInfo {
+        format: MediaFormat::Arw,
+        name: "Sony ARW",
+        extensions: &["arw", "srf", "sr2"],
+        mime_type: "image/x-sony-arw",
+        decoder: FormatDecoder::RawPreview,
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Jpeg,
+    },
+    FormatInfo {
+        format: MediaFormat::Dng,
+        name: "DNG",
+        extensions: &["dng"],
+        mime_type: "image/x-adobe-dng",
+        decoder: FormatDecoder::RawPreview,
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Jpeg,
+    },
+    FormatInfo {
+        format: MediaFormat::Raf,
+        name: "Fujifilm RAF",
+        extensions: &["raf"],
+        mime_type: "image/x-fuji-raf",
+        decoder: FormatDecoder::RawPreview,
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Jpeg,
+    },
+    FormatInfo {
+        format: MediaFormat::Mp4,
+        name: "MP4",
+        extensions: &["mp4", "m4v"],
+        mime_type: "video/mp4",
+        decoder: FormatDecoder::Video,
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Jpeg,
+    },
+    FormatInfo {
+        format: MediaFormat::Mov,
+        name: "QuickTime",
+        extensions: &["mov", "qt"],
+        mime_type: "video/quicktime",
+        decoder: FormatDecoder::Video,
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Jpeg,
+    },
+    FormatInfo {
+        format: MediaFormat::Mkv,
+        name: "Matroska",
+        extensions: &["mkv"],
+        mime_type: "video/x-matroska",
+        decoder: FormatDecoder::Video,
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Jpeg,
+    },
+    FormatInfo {
+        format: MediaFormat::Webm,
+        name: "WebM",
+        extensions: &["webm"],
+        mime_type: "video/webm",
+        decoder: FormatDecoder::Video,
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Jpeg,
+    },
+    FormatInfo {
+        format: MediaFormat::Avi,
+        name: "AVI",
+        extensions: &["avi"],
+        mime_type: "video/x-msvideo",
+        decoder: FormatDecoder::Video,
+        decodable: true,
+        thumbnail_encoder: ThumbnailEncoder::Jpeg,
+    },
+    FormatInfo {
+        format: MediaFormat::Heic,
+        name: "HEIC",
+        extensions: &["heic", "heif", "hif"],
+        mime_type: "image/heic",
+        decoder: FormatDecoder::Unsupported,
+        decodable: false,
+        thumbnail_encoder: ThumbnailEncoder::Jpeg,
+    },
+];
+
+impl MediaFormat {
+    /// 注册表中的全部格式
+    pub fn all() -> impl Iterator<Item = MediaFormat> {
+        FORMATS.iter().map(|info| info.format)
+    }
+
+    pub fn info(self) -> &'static FormatInfo {
+        FORMATS
+            .iter()
+            .find(|info| info.format == self)
+            .expect("every format is registered")
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        let ext = ext.to_ascii_lowercase();
+        FORMATS
+            .iter()
+            .find(|info| info.extensions.contains(&ext.as_str()))
+            .map(|info| info.format)
+    }
+
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        FORMATS
+            .iter()
+            .find(|info| info.mime_type == mime_type)
+            .map(|info| info.format)
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|s| s.to_str())
+            .and_then(Self::from_extension)
+    }
+
+    /// 根据文件开头的魔数判断格式，无法识别时返回 `None`
+    pub fn detect(path: &Path) -> io::Result<Option<Self>> {
+        let mut header = [0u8; SNIFF_LEN];
+        let read = read_up_to(&mut File::open(path)?, &mut header)?;
+        Ok(Self::sniff(&header[..read], Self::from_path(path)))
+    }
+
+    /// 根据魔数判断格式
+    ///
+    /// 同一容器的变体（TIFF 结构的 RAW、MP4 与 MOV、MKV 与 WebM）无法仅凭开头区分，
+    /// 此时若 `hint`（通常来自扩展名）属于同一容器则采用 `hint`。
+    pub fn sniff(header: &[u8], hint: Option<Self>) -> Option<Self> {
+        use MediaFormat::*;
+
+        let at =
+            |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+        let hint_in = |formats: &[MediaFormat]| hint.filter(|hint| formats.contains(hint));
+
+        if at(0, &[0xff, 0xd8, 0xff]) {
+            Some(Jpeg)
+        } else if at(0, b"\x89PNG\r\n\x1a\n") {
+            Some(Png)
+        } else if at(0, b"GIF87a") || at(0, b"GIF89a") {
+            Some(Gif)
+        } else if at(0, b"RIFF") && at(8, b"WEBP") {
+            Some(Webp)
+        } else if at(0, b"RIFF") && at(8, b"AVI ") {
+            Some(Avi)
+        } else if at(0, b"FUJIFILMCCD-RAW") {
+            Some(Raf)
+        } else if at(0, b"II*\0") || at(0, b"MM\0*") {
+            if at(8, b"CR") {
+                Some(Cr2)
+            } else {
+                hint_in(&[Cr2, Nef, Arw, Dng]).or(Some(Tiff))
+            }
+        } else if at(0, &[0x1a, 0x45, 0xdf, 0xa3]) {
+            let doc_type = if header.windows(4).any(|w| w == b"webm") {
+                Webm
+            } else {
+                Mkv
+            };
+            hint_in(&[Mkv, Webm]).or(Some(doc_type))
+        } else if at(4, b"ftyp") {
+            sniff_ftyp(header).map(|format| match format {
+                Mp4 | Mov => hint_in(&[Mp4, Mov]).unwrap_or(format),
+                _ => format,
+            })
+        } else if at(4, b"moov") || at(4, b"mdat") || at(4, b"wide") || at(4, b"free") {
+            // 没有 ftyp 的旧式 QuickTime
+            hint_in(&[Mp4, Mov]).or(Some(Mov))
+        } else if at(0, b"BM") && at(6, &[0, 0, 0, 0]) {
+            Some(Bmp)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_decodable(self) -> bool {
+        self.info().decodable
+    }
+
+    pub fn media_type(self) -> MediaType {
+        match self.info().decoder {
+            FormatDecoder::Video => MediaType::Video,
+            _ => MediaType::Photo,
+        }
+    }
+
+    /// 是否为相机 RAW 格式
+    pub fn is_raw(self) -> bool {
+        self.info().decoder == FormatDecoder::RawPreview
+    }
+}
+
+impl std::fmt::Display for MediaFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.info().name)
+    }
+}
+
+/// 根据 `ftyp` 中的主品牌与兼容品牌判断 ISOBMFF 文件的格式
+fn sniff_ftyp(header: &[u8]) -> Option<MediaFormat> {
+    let size = u32::from_be_bytes(header.get(0..4)?.try_into().ok()?) as usize;
+    let major = header.get(8..12)?;
+    let compatible = header.get(16..size.min(header.len())).unwrap_or_default();
+    let brands: Vec<&[u8]> = std::iter::once(major)
+        .chain(compatible.chunks_exact(4))
+        .collect();
+    let has = |names: &[&[u8]]| brands.iter().any(|brand| names.contains(brand));
+
+    Some(if has(&[b"crx "]) {
+        MediaFormat::Cr3
+    } else if has(&[b"avif", b"avis"]) {
+        MediaFormat::Avif
+    } else if has(&[
+        b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"mif1", b"msf1",
+    ]) {
+        MediaFormat::Heic
+    } else if major == b"qt  " {
+        MediaFormat::Mov
+    } else {
+        MediaFormat::Mp4
+    })
+}
+
+/// 按注册表中的设置编码缩略图
+pub fn encode_thumbnail(image: &DynamicImage, encoder: ThumbnailEncoder) -> ImageResult<Vec<u8>> {
+    let mut data = Vec::new();
+    match encoder {
+        ThumbnailEncoder::Png => {
+            let encoder = PngEncoder::new_with_quality(
+                &mut data,
+                image::codecs::png::CompressionType::Level(7),
+                image::codecs::png::FilterType::NoFilter,
+            );
+            let rgba = image.to_rgba8();
+            encoder.write_image(
+                &rgba,
+                rgba.width(),
+                rgba.height(),
+                image::ExtendedColorType::Rgba8,
+            )?;
+        }
+        ThumbnailEncoder::Jpeg => {
+            // JPEG 不支持透明通道与 16 位色深
+            let rgb = DynamicImage::ImageRgb8(image.to_rgb8());
+            JpegEncoder::new_with_quality(&mut data, 70).encode_image(&rgb)?;
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 构造 `ftyp` 盒子：主品牌后接次版本号与兼容品牌
+    fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let size = 16 + 4 * compatible.len() as u32;
+        let mut data = size.to_be_bytes().to_vec();
+        data.extend_from_slice(b"ftyp");
+        data.extend_from_slice(major);
+        data.extend_from_slice(&[0; 4]);
+        for brand in compatible {
+            data.extend_from_slice(*brand);
+        }
+        data
+    }
+
+    fn riff(form: &[u8; 4]) -> Vec<u8> {
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(form);
+        data
+    }
+
+    fn tiff(little_endian: bool, extra: &[u8]) -> Vec<u8> {
+        let mut data = if little_endian {
+            b"II*\0\x08\0\0\0".to_vec()
+        } else {
+            b"MM\0*\0\0\0\x08".to_vec()
+        };
+        data.extend_from_slice(extra);
+        data
+    }
+
+    fn ebml(doc_type: &[u8]) -> Vec<u8> {
+        let mut data = vec![
+            0x1a,
+            0x45,
+            0xdf,
+            0xa3,
+            0x9f,
+            0x42,
+            0x82,
+            0x80 | doc_type.len() as u8,
+        ];
+        data.extend_from_slice(doc_type);
+        data
+    }
+
+    /// 每种注册格式的典型文件开头
+    fn signatures() -> Vec<(MediaFormat, Vec<u8>)> {
+        use MediaFormat::*;
+
+        let mut bmp = b"BM".to_vec();
+        bmp.extend_from_slice(&[0x36, 0, 0, 0, 0, 0, 0, 0, 0x36, 0, 0, 0]);
+        let mut legacy_mov = 8u32.to_be_bytes().to_vec();
+        legacy_mov.extend_from_slice(b"moov");
+
+        vec![
+            (
+                Jpeg,
+                vec![0xff, 0xd8, 0xff, 0xe0, 0, 0x10, b'J', b'F', b'I', b'F'],
+            ),
+            (Png, b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec()),
+            (Gif, b"GIF89a\x01\0\x01\0".to_vec()),
+            (Gif, b"GIF87a\x01\0\x01\0".to_vec()),
+            (Webp, riff(b"WEBP")),
+            (Avi, riff(b"AVI ")),
+            (Bmp, bmp),
+            (Tiff, tiff(true, &[])),
+            (Tiff, tiff(false, &[])),
+            (Cr2, tiff(true, b"CR\x02\0")),
+            (Raf, b"FUJIFILMCCD-RAW 0201FF383501".to_vec()),
+            (Cr3, ftyp(b"crx ", &[b"crx ", b"isom"])),
+            (Avif, ftyp(b"avif", &[b"mif1", b"miaf"])),
+            (Avif, ftyp(b"mif1", &[b"avif"])),
+            (Avif, ftyp(b"avis", &[b"msf1"])),
+            (Heic, ftyp(b"heic", &[b"mif1", b"heic"])),
+            (Heic, ftyp(b"mif1", &[b"heic"])),
+            (Heic, ftyp(b"msf1", &[b"hevc"])),
+            (Mp4, ftyp(b"isom", &[b"isom", b"iso2", b"avc1", b"mp41"])),
+            (Mp4, ftyp(b"mp42", &[])),
+            (Mov, ftyp(b"qt  ", &[b"qt  "])),
+            (Mov, legacy_mov),
+            (Mkv, ebml(b"matroska")),
+            (Webm, ebml(b"webm")),
+        ]
+    }
+
+    #[test]
+    fn sniff_recognizes_signatures() {
+        for (format, header) in signatures() {
+            assert_eq!(
+                MediaFormat::sniff(&header, None),
+                Some(format),
+                "{format:?}: {header:02x?}"
+            );
+        }
+    }
+
+    #[test]
+    fn every_sniffable_format_has_a_signature() {
+        let covered: Vec<_> = signatures().into_iter().map(|(format, _)| format).collect();
+        // TIFF 结构的 RAW 只能依靠扩展名区分，见 `sniff_uses_hint_within_container`
+        let hint_only = [MediaFormat::Nef, MediaFormat::Arw, MediaFormat::Dng];
+        for format in MediaFormat::all() {
+            assert!(
+                covered.contains(&format) || hint_only.contains(&format),
+                "{format:?} has no signature test"
+            );
+        }
+    }
+
+    #[test]
+    fn sniff_uses_hint_within_container() {
+        use MediaFormat::*;
+
+        for raw in [Cr2, Nef, Arw, Dng] {
+            assert_eq!(MediaFormat::sniff(&tiff(true, &[]), Some(raw)), Some(raw));
+        }
+        // CR2 的标记优先于扩展名
+        assert_eq!(
+            MediaFormat::sniff(&tiff(true, b"CR\x02\0"), Some(Nef)),
+            Some(Cr2)
+        );
+        assert_eq!(
+            MediaFormat::sniff(&ebml(b"matroska"), Some(Webm)),
+            Some(Webm)
+        );
+        assert_eq!(MediaFormat::sniff(&ebml(b"webm"), Some(Mkv)), Some(Mkv));
+        assert_eq!(
+            MediaFormat::sniff(&ftyp(b"isom", &[]), Some(Mov)),
+            Some(Mov)
+        );
+        assert_eq!(
+            MediaFormat::sniff(&ftyp(b"qt  ", &[]), Some(Mp4)),
+            Some(Mp4)
+        );
+        // 图片品牌不受视频扩展名影响
+        assert_eq!(
+            MediaFormat::sniff(&ftyp(b"heic", &[b"mif1"]), Some(Mp4)),
+            Some(Heic)
+        );
+
+        // 不属于同一容器的扩展名被忽略
+        assert_eq!(MediaFormat::sniff(&tiff(true, &[]), Some(Jpeg)), Some(Tiff));
+        assert_eq!(MediaFormat::sniff(&ebml(b"matroska"), Some(Mp4)), Some(Mkv));
+        assert_eq!(
+            MediaFormat::sniff(&ftyp(b"isom", &[]), Some(Mkv)),
+            Some(Mp4)
+        );
+        assert_eq!(MediaFormat::sniff(b"not an image", Some(Jpeg)), None);
+    }
+
+    #[test]
+    fn sniff_ftyp_reads_only_declared_brands() {
+        // 声明的盒子长度之外的内容不是兼容品牌
+        let mut data = ftyp(b"isom", &[b"mp41"]);
+        data.extend_from_slice(b"heic");
+        assert_eq!(sniff_ftyp(&data), Some(MediaFormat::Mp4));
+
+        // 长度小于头部时只看主品牌
+        let mut data = ftyp(b"isom", &[b"heic"]);
+        data[..4].copy_from_slice(&8u32.to_be_bytes());
+        assert_eq!(sniff_ftyp(&data), Some(MediaFormat::Mp4));
+    }
+
+    #[test]
+    fn sniff_handles_short_buffers() {
+        assert_eq!(MediaFormat::sniff(&[], None), None);
+        assert_eq!(MediaFormat::sniff(&[], Some(MediaFormat::Jpeg)), None);
+
+        for (format, header) in signatures() {
+            // 区分变体的标记被截断后退回同一容器的通用格式
+            let generic = match format {
+                MediaFormat::Cr2 => MediaFormat::Tiff,
+                MediaFormat::Webm => MediaFormat::Mkv,
+                format => format,
+            };
+            for len in 0..header.len().min(12) {
+                let sniffed = MediaFormat::sniff(&header[..len], None);
+                // 截断的头部可以无法识别，但不能被识别成其他容器的格式
+                assert!(
+                    sniffed.is_none() || sniffed == Some(format) || sniffed == Some(generic),
+                    "{format:?} truncated to {len} bytes sniffed as {sniffed:?}"
+                );
+            }
+        }
+
+        // 魔数本身未截断时仍能识别
+        assert_eq!(
+            MediaFormat::sniff(&[0xff, 0xd8, 0xff], None),
+            Some(MediaFormat::Jpeg)
+        );
+        // ftyp 的主品牌被截断
+        assert_eq!(MediaFormat::sniff(&ftyp(b"heic", &[])[..11], None), None);
+        assert_eq!(sniff_ftyp(&[0, 0, 0]), None);
+    }
+
+    #[test]
+    fn detect_reads_file_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("photo.nef");
+        std::fs::write(&path, tiff(true, &[0; 64])).unwrap();
+        assert_eq!(MediaFormat::detect(&path).unwrap(), Some(MediaFormat::Nef));
+
+        // 扩展名与内容不符时以内容为准
+        let path = dir.path().join("clip.jpg");
+        std::fs::write(&path, ftyp(b"isom", &[b"avc1"])).unwrap();
+        assert_eq!(MediaFormat::detect(&path).unwrap(), Some(MediaFormat::Mp4));
+
+        let path = dir.path().join("empty.png");
+        std::fs::write(&path, []).unwrap();
+        assert_eq!(MediaFormat::detect(&path).unwrap(), None);
+    }
+}
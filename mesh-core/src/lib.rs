@@ -1,11 +1,42 @@
 mod cache;
+mod color;
 mod config;
+mod container;
+mod decode;
+mod format;
+mod library;
+mod paths;
+mod raw;
+mod resize;
+mod scanner;
+mod thumbnailer;
+mod video;
+mod volume;
+mod worker;
 
-pub use cache::MeshCache;
-pub use config::MeshConfig;
-
-use directories::ProjectDirs;
-use std::{path::PathBuf, sync::LazyLock};
-
-static MESH_DIR: LazyLock<ProjectDirs> =
-    LazyLock::new(|| ProjectDirs::from_path(PathBuf::from("mesh")).unwrap());
+pub use cache::{
+    DirectoryStore, GcReport, MeshCache, MeshDatabase, MeshThumbnail, PhotoRecord, RelocateReport,
+    RootChange, RootRecord, ScanErrorKind, ScanErrorRecord, SourceStamp, SqliteStore, TagEdit,
+    ThumbnailBackend, ThumbnailEntry, ThumbnailStats, ThumbnailStore,
+};
+pub use config::{
+    ConfigChange, ConfigError, ConfigStore, ConfigSubscription, ConfigValue, DirChange, DirError,
+    MeshConfig,
+};
+pub use decode::{
+    DecodedImage, MAX_DECODE_ALLOC, MAX_IMAGE_DIMENSION, decode_for_thumbnail, open_image,
+    resize_for_thumbnail, thumbnail_size,
+};
+pub use format::{
+    FormatDecoder, FormatInfo, MediaFormat, MediaType, ThumbnailEncoder, encode_thumbnail,
+};
+pub use library::{DEFAULT_LIBRARY, LibraryError, LibraryRegistry};
+pub use paths::{MESH_HOME_ENV, MeshDirs, PORTABLE_DIR_NAME};
+pub use scanner::{IGNORE_FILE_NAME, ScanFilter, collect_files};
+pub use thumbnailer::{
+    CancellationToken, THUMBNAIL_MAX_HEIGHT, ThumbnailError, ThumbnailJob, ThumbnailProgress,
+    ThumbnailSummary,
+};
+pub use video::{VideoMetadata, probe_video};
+pub use volume::{VOLUME_MARKER_FILE, ensure_volume_id, find_volume, read_volume_id};
+pub use worker::run_worker_if_requested;
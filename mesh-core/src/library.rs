@@ -0,0 +1,306 @@
+use std::{fmt, fs, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{MeshDirs, paths::write_atomic};
+
+/// 直接使用数据目录的照片库，升级前的配置与缓存都属于它
+pub const DEFAULT_LIBRARY: &str = "default";
+
+const REGISTRY_FILE_NAME: &str = "libraries.toml";
+/// 照片库名称的最大长度
+const MAX_NAME_LEN: usize = 64;
+
+/// 读写照片库列表或切换照片库失败的原因
+#[derive(Debug)]
+pub enum LibraryError {
+    Io(PathBuf, io::Error),
+    /// 照片库列表文件格式错误
+    Parse(PathBuf, String),
+    /// 名称为空、过长或包含字母、数字、`-`、`_` 以外的字符
+    InvalidName(String),
+    Duplicate(String),
+    NotFound(String),
+    /// 默认照片库不能删除
+    RemoveDefault,
+}
+
+impl fmt::Display for LibraryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            Self::Parse(path, message) => write!(f, "{}: {}", path.display(), message),
+            Self::InvalidName(name) => write!(
+                f,
+                "invalid library name {:?}: use letters, digits, '-' or '_'",
+                name
+            ),
+            Self::Duplicate(name) => write!(f, "library already exists: {}", name),
+            Self::NotFound(name) => write!(f, "no such library: {}", name),
+            Self::RemoveDefault => write!(f, "the default library cannot be removed"),
+        }
+    }
+}
+
+impl std::error::Error for LibraryError {}
+
+/// `libraries.toml` 的内容
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RegistryFile {
+    #[serde(default)]
+    current: Option<String>,
+    /// 默认照片库以外的照片库，按创建顺序排列
+    #[serde(default)]
+    libraries: Vec<String>,
+}
+
+/// 照片库列表，每个照片库有各自的配置（相册目录等）、数据库与缩略图缓存
+///
+/// 默认照片库使用数据目录本身，其他照片库使用数据目录下的 `libraries/<name>`。
+#[derive(Debug)]
+pub struct LibraryRegistry {
+    dirs: MeshDirs,
+    file: RegistryFile,
+}
+
+impl LibraryRegistry {
+    /// 只有默认照片库的列表，不读取 `libraries.toml`
+    pub fn new(dirs: MeshDirs) -> Self {
+        Self {
+            dirs,
+            file: RegistryFile::default(),
+        }
+    }
+
+    /// 读取 `dirs` 中的照片库列表，文件不存在时只有默认照片库
+    pub fn load(dirs: MeshDirs) -> Result<Self, LibraryError> {
+        let path = dirs.config_dir().join(REGISTRY_FILE_NAME);
+        let file = match fs::read_to_string(&path) {
+            Ok(content) => {
+                toml::from_str(&content).map_err(|e| LibraryError::Parse(path, e.to_string()))?
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::new(dirs)),
+            Err(e) => return Err(LibraryError::Io(path, e)),
+        };
+        Ok(Self { dirs, file })
+    }
+
+    /// 数据目录，也是默认照片库的目录；主题等全局数据保存在这里
+    pub fn base_dirs(&self) -> &MeshDirs {
+        &self.dirs
+    }
+
+    /// 所有照片库的名称，默认照片库在最前
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(DEFAULT_LIBRARY).chain(self.file.libraries.iter().map(String::as_str))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names().any(|library| library == name)
+    }
+
+    /// 当前照片库的名称，记录的照片库已被删除时为默认照片库
+    pub fn current(&self) -> &str {
+        self.file
+            .current
+            .as_deref()
+            .filter(|name| self.contains(name))
+            .unwrap_or(DEFAULT_LIBRARY)
+    }
+
+    /// 照片库的配置与缓存目录
+    pub fn dirs(&self, name: &str) -> Result<MeshDirs, LibraryError> {
+        if name == DEFAULT_LIBRARY {
+            Ok(self.dirs.clone())
+        } else if self.contains(name) {
+            Ok(self.dirs.library(name))
+        } else {
+            Err(LibraryError::NotFound(name.to_owned()))
+        }
+    }
+
+    pub fn current_dirs(&self) -> MeshDirs {
+        self.dirs(self.current())
+            .expect("the current library is always registered")
+    }
+
+    /// 创建照片库，新照片库的配置为默认配置
+    pub fn create(&mut self, name: &str) -> Result<MeshDirs, LibraryError> {
+        validate_name(name)?;
+        if self.contains(name) {
+            return Err(LibraryError::Duplicate(name.to_owned()));
+        }
+
+        self.file.libraries.push(name.to_owned());
+        if let Err(e) = self.save() {
+            self.file.libraries.pop();
+            return Err(e);
+        }
+        Ok(self.dirs.library(name))
+    }
+
+    /// 删除照片库及其配置、数据库与缩略图，相册中的照片不受影响
+    ///
+    /// 删除的是当前照片库时切换到默认照片库。
+    pub fn remove(&mut self, name: &str) -> Result<(), LibraryError> {
+        if name == DEFAULT_LIBRARY {
+            return Err(LibraryError::RemoveDefault);
+        }
+        let dirs = self.dirs(name)?;
+
+        let libraries = self.file.libraries.clone();
+        self.file.libraries.retain(|library| library != name);
+        if let Err(e) = self.save() {
+            self.file.libraries = libraries;
+            return Err(e);
+        }
+
+        for dir in [dirs.config_dir(), dirs.cache_dir()] {
+            if let Err(e) = fs::remove_dir_all(dir)
+                && e.kind() != io::ErrorKind::NotFound
+            {
+                log::warn!("Failed to remove {:?}: {}", dir, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// 设置当前照片库，下次启动时打开
+    pub fn set_current(&mut self, name: &str) -> Result<MeshDirs, LibraryError> {
+        let dirs = self.dirs(name)?;
+        let previous = self.file.current.replace(name.to_owned());
+        if let Err(e) = self.save() {
+            self.file.current = previous;
+            return Err(e);
+        }
+        Ok(dirs)
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dirs.config_dir().join(REGISTRY_FILE_NAME)
+    }
+
+    /// 先写入临时文件再重命名，与配置文件的保存方式相同
+    fn save(&self) -> Result<(), LibraryError> {
+        let path = self.path();
+        let content = toml::to_string_pretty(&self.file)
+            .map_err(|e| LibraryError::Io(path.clone(), io::Error::other(e)))?;
+        write_atomic(&path, content.as_bytes()).map_err(|e| LibraryError::Io(path, e))
+    }
+}
+
+/// 名称用作目录名，只允许字母、数字、`-` 与 `_`
+fn validate_name(name: &str) -> Result<(), LibraryError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(LibraryError::InvalidName(name.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_names() {
+        let long = "a".repeat(MAX_NAME_LEN);
+        for name in ["work", "family-2024", "raw_imports", "旅行", long.as_str()] {
+            assert!(validate_name(name).is_ok(), "{name:?}");
+        }
+
+        let too_long = "a".repeat(MAX_NAME_LEN + 1);
+        for name in [
+            "",
+            ".",
+            "..",
+            "../escape",
+            "a/b",
+            "a\\b",
+            "with space",
+            "dot.name",
+            "nul\0",
+            too_long.as_str(),
+        ] {
+            let result = validate_name(name);
+            assert!(
+                matches!(&result, Err(LibraryError::InvalidName(invalid)) if invalid == name),
+                "{name:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn creates_switches_and_removes_libraries() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = MeshDirs::new(dir.path());
+        let mut registry = LibraryRegistry::load(base.clone()).unwrap();
+        assert_eq!(registry.names().collect::<Vec<_>>(), [DEFAULT_LIBRARY]);
+        assert_eq!(registry.current_dirs(), base);
+
+        let work = registry.create("work").unwrap();
+        assert_eq!(work, base.library("work"));
+        assert!(matches!(
+            registry.create("work"),
+            Err(LibraryError::Duplicate(_))
+        ));
+        assert!(matches!(
+            registry.create(DEFAULT_LIBRARY),
+            Err(LibraryError::Duplicate(_))
+        ));
+        assert!(matches!(
+            registry.create("../work"),
+            Err(LibraryError::InvalidName(_))
+        ));
+        assert!(matches!(
+            registry.set_current("missing"),
+            Err(LibraryError::NotFound(_))
+        ));
+        registry.set_current("work").unwrap();
+
+        // 重新读取后保留照片库列表与当前照片库
+        let mut registry = LibraryRegistry::load(base.clone()).unwrap();
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            [DEFAULT_LIBRARY, "work"]
+        );
+        assert_eq!(registry.current(), "work");
+        assert_eq!(registry.current_dirs(), work);
+
+        fs::create_dir_all(work.config_dir()).unwrap();
+        fs::create_dir_all(work.cache_dir()).unwrap();
+        assert!(matches!(
+            registry.remove(DEFAULT_LIBRARY),
+            Err(LibraryError::RemoveDefault)
+        ));
+        registry.remove("work").unwrap();
+        assert!(!work.config_dir().exists());
+        assert!(!work.cache_dir().exists());
+        assert_eq!(registry.current(), DEFAULT_LIBRARY);
+        assert!(matches!(
+            registry.remove("work"),
+            Err(LibraryError::NotFound(_))
+        ));
+
+        let registry = LibraryRegistry::load(base).unwrap();
+        assert_eq!(registry.names().collect::<Vec<_>>(), [DEFAULT_LIBRARY]);
+    }
+
+    #[test]
+    fn rejects_malformed_registry() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = MeshDirs::new(dir.path());
+        fs::create_dir_all(base.config_dir()).unwrap();
+        fs::write(base.config_dir().join(REGISTRY_FILE_NAME), "libraries = 1").unwrap();
+
+        assert!(matches!(
+            LibraryRegistry::load(base),
+            Err(LibraryError::Parse(..))
+        ));
+    }
+}
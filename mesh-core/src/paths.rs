@@ -0,0 +1,193 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use directories::ProjectDirs;
+
+/// 指定数据目录的环境变量，配置与缓存分别放在其下的 `config` 与 `cache` 目录
+pub const MESH_HOME_ENV: &str = "MESH_HOME";
+/// 可执行文件旁存在此目录时进入便携模式，所有数据都保存在该目录中
+pub const PORTABLE_DIR_NAME: &str = "mesh-data";
+
+const THEMES_DIR_NAME: &str = "themes";
+const LIBRARIES_DIR_NAME: &str = "libraries";
+
+/// Mesh 读写配置与缓存的目录
+///
+/// 同一进程中可以为不同的目录分别创建 [`MeshConfig`](crate::MeshConfig) 与
+/// [`MeshCache`](crate::MeshCache)，互不影响。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeshDirs {
+    config_dir: PathBuf,
+    cache_dir: PathBuf,
+}
+
+impl MeshDirs {
+    /// 以 `base` 为根的目录布局：`base/config` 与 `base/cache`
+    pub fn new(base: impl AsRef<Path>) -> Self {
+        let base = base.as_ref();
+        Self {
+            config_dir: base.join("config"),
+            cache_dir: base.join("cache"),
+        }
+    }
+
+    /// 当前用户的标准配置与缓存目录，例如 Linux 上的 `~/.config/mesh` 与 `~/.cache/mesh`
+    pub fn user() -> Self {
+        let dirs = ProjectDirs::from_path(PathBuf::from("mesh"))
+            .expect("no home directory found for the current user");
+        Self {
+            config_dir: dirs.config_dir().to_path_buf(),
+            cache_dir: dirs.cache_dir().to_path_buf(),
+        }
+    }
+
+    /// 按优先级选择目录：`MESH_HOME` 环境变量、便携模式、当前用户的标准目录
+    pub fn from_env() -> Self {
+        Self::resolve(
+            std::env::var_os(MESH_HOME_ENV),
+            std::env::current_exe().ok().as_deref(),
+            Self::user,
+        )
+    }
+
+    /// 可执行文件旁的便携数据目录，不存在时返回 `None`
+    ///
+    /// 在 U 盘等可移动设备上使用时，只需在程序旁创建 `mesh-data` 目录。
+    pub fn portable_dir() -> Option<PathBuf> {
+        portable_dir_next_to(&std::env::current_exe().ok()?)
+    }
+
+    /// [`from_env`](Self::from_env) 的选择逻辑，环境变量、可执行文件路径与用户目录由调用方提供
+    fn resolve(home: Option<OsString>, exe: Option<&Path>, user: impl FnOnce() -> Self) -> Self {
+        if let Some(home) = home.filter(|home| !home.is_empty()) {
+            return Self::new(home);
+        }
+        if let Some(portable) = exe.and_then(portable_dir_next_to) {
+            return Self::new(portable);
+        }
+        user()
+    }
+
+    pub fn config_dir(&self) -> &Path {
+        &self.config_dir
+    }
+
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    pub fn themes_dir(&self) -> PathBuf {
+        self.config_dir.join(THEMES_DIR_NAME)
+    }
+
+    /// 名为 `name` 的照片库的目录，见 [`LibraryRegistry`](crate::LibraryRegistry)
+    pub fn library(&self, name: &str) -> Self {
+        Self {
+            config_dir: self.config_dir.join(LIBRARIES_DIR_NAME).join(name),
+            cache_dir: self.cache_dir.join(LIBRARIES_DIR_NAME).join(name),
+        }
+    }
+}
+
+fn portable_dir_next_to(exe: &Path) -> Option<PathBuf> {
+    let dir = exe.parent()?.join(PORTABLE_DIR_NAME);
+    dir.is_dir().then_some(dir)
+}
+
+/// 先写入同目录下的临时文件并同步到磁盘，再重命名为 `path`
+///
+/// 写入中途失败不会损坏原有的文件，上级目录不存在时自动创建。
+pub(crate) fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    File::create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp_path, path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&tmp_path);
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user() -> MeshDirs {
+        MeshDirs::new("/user")
+    }
+
+    #[test]
+    fn resolve_prefers_mesh_home() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("mesh");
+        fs::create_dir(dir.path().join(PORTABLE_DIR_NAME)).unwrap();
+
+        assert_eq!(
+            MeshDirs::resolve(Some("/home/mesh".into()), Some(&exe), user),
+            MeshDirs::new("/home/mesh")
+        );
+        // 空的环境变量视为未设置
+        assert_eq!(
+            MeshDirs::resolve(Some("".into()), Some(&exe), user),
+            MeshDirs::new(dir.path().join(PORTABLE_DIR_NAME))
+        );
+    }
+
+    #[test]
+    fn resolve_uses_portable_dir_next_to_the_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("bin").join("mesh");
+        fs::create_dir_all(exe.parent().unwrap()).unwrap();
+        assert_eq!(MeshDirs::resolve(None, Some(&exe), user), user());
+
+        // 同名的文件不会启用便携模式
+        let portable = exe.with_file_name(PORTABLE_DIR_NAME);
+        fs::write(&portable, "").unwrap();
+        assert_eq!(MeshDirs::resolve(None, Some(&exe), user), user());
+
+        fs::remove_file(&portable).unwrap();
+        fs::create_dir(&portable).unwrap();
+        assert_eq!(
+            MeshDirs::resolve(None, Some(&exe), user),
+            MeshDirs::new(&portable)
+        );
+        assert_eq!(
+            MeshDirs::resolve(None, Some(&exe), user).config_dir(),
+            portable.join("config")
+        );
+    }
+
+    #[test]
+    fn resolve_falls_back_to_user_dirs() {
+        assert_eq!(MeshDirs::resolve(None, None, user), user());
+        assert_eq!(MeshDirs::resolve(None, Some(Path::new("/")), user), user());
+    }
+
+    #[test]
+    fn write_atomic_replaces_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("config.toml");
+
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+
+        // 目标是目录时重命名失败，临时文件被删除
+        let target = dir.path().join("target");
+        fs::create_dir_all(target.join("child")).unwrap();
+        assert!(write_atomic(&target, b"data").is_err());
+        assert!(!dir.path().join("target.tmp").exists());
+    }
+}
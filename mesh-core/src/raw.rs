@@ -0,0 +1,998 @@
+//! 从相机 RAW 文件中提取内嵌的 JPEG 预览图
+//!
+//! 不做去马赛克，只解析文件结构找到相机写入的预览：
+//! - CR2 / NEF / ARW / DNG：TIFF 结构，遍历 IFD 链与 SubIFD
+//! - RAF：文件头中记录了预览的偏移与长度
+//! - CR3：ISOBMFF 结构，预览位于 `PRVW` box
+//!
+//! 同时从 RAW 的 EXIF IFD 读取拍摄时间与传感器输出的图像尺寸。
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::container::{boxes, next_box, read_up_to};
+
+/// 最多遍历的 IFD 数量，防止损坏文件中的循环引用
+const MAX_IFDS: usize = 64;
+/// 单个 IFD 的最大条目数
+const MAX_IFD_ENTRIES: u16 = 1024;
+/// 预览图的最大长度
+const MAX_PREVIEW_LEN: u64 = 64 * 1024 * 1024;
+
+const RAF_MAGIC: &[u8; 16] = b"FUJIFILMCCD-RAW ";
+/// CR3 中存放 `PRVW` 的 uuid box
+const CR3_PREVIEW_UUID: [u8; 16] = [
+    0xea, 0xf4, 0x2b, 0x5e, 0x1c, 0x98, 0x4b, 0x88, 0xb9, 0xfb, 0xb7, 0xdc, 0x40, 0x6e, 0x4d, 0x16,
+];
+/// CR3 中存放 `CMT1`（TIFF IFD0）与 `CMT2`（EXIF IFD）的 uuid box，位于 `moov` 内
+const CR3_METADATA_UUID: [u8; 16] = [
+    0x85, 0xc0, 0xb6, 0x87, 0x82, 0x0f, 0x11, 0xe0, 0x81, 0x11, 0xf4, 0xce, 0x46, 0x2b, 0x6a, 0x48,
+];
+
+const TAG_NEW_SUBFILE_TYPE: u16 = 0x00fe;
+const TAG_COMPRESSION: u16 = 0x0103;
+const TAG_STRIP_OFFSETS: u16 = 0x0111;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_STRIP_BYTE_COUNTS: u16 = 0x0117;
+const TAG_SUB_IFDS: u16 = 0x014a;
+const TAG_JPEG_OFFSET: u16 = 0x0201;
+const TAG_JPEG_LENGTH: u16 = 0x0202;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_PIXEL_X_DIMENSION: u16 = 0xa002;
+const TAG_PIXEL_Y_DIMENSION: u16 = 0xa003;
+
+/// RAW 文件中的内嵌预览
+#[derive(Debug)]
+pub struct RawPreview {
+    /// JPEG 数据
+    pub data: Vec<u8>,
+    /// 预览图宽度
+    pub width: u32,
+    /// 预览图高度
+    pub height: u32,
+    /// EXIF 方向（1-8），预览图本身通常没有旋转
+    pub orientation: u8,
+    /// EXIF 中记录的图像宽高（未按方向旋转），即传感器输出的尺寸
+    pub sensor_size: Option<(u32, u32)>,
+    /// EXIF 中的拍摄时间，UNIX 时间戳（秒），EXIF 不记录时区，按 UTC 处理
+    pub captured_at: Option<i64>,
+}
+
+/// EXIF IFD 中的拍摄信息
+#[derive(Debug, Default, Clone, Copy)]
+struct Capture {
+    sensor_size: Option<(u32, u32)>,
+    captured_at: Option<i64>,
+}
+
+impl Capture {
+    /// 从 kamadak-exif 解析出的 EXIF 中读取
+    fn from_exif(exif: &exif::Exif) -> Self {
+        let uint = |tag| {
+            exif.get_field(tag, exif::In::PRIMARY)?
+                .value
+                .get_uint(0)
+                .filter(|&v| v > 0)
+        };
+        let captured_at = exif
+            .get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)
+            .and_then(|field| match &field.value {
+                exif::Value::Ascii(values) => values.first().and_then(|v| exif_timestamp(v)),
+                _ => None,
+            });
+        Self {
+            sensor_size: uint(exif::Tag::PixelXDimension).zip(uint(exif::Tag::PixelYDimension)),
+            captured_at,
+        }
+    }
+}
+
+/// 提取 RAW 文件中分辨率最高的 JPEG 预览，没有可用预览时返回 `None`
+pub fn extract_preview(path: &Path) -> io::Result<Option<RawPreview>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 16];
+    let read = read_up_to(&mut reader, &mut magic)?;
+    let magic = &magic[..read];
+
+    if magic.starts_with(RAF_MAGIC) {
+        raf_preview(&mut reader)
+    } else if magic.starts_with(b"II*\0") || magic.starts_with(b"MM\0*") {
+        tiff_preview(&mut reader)
+    } else if magic.get(4..8) == Some(b"ftyp") {
+        cr3_preview(&mut reader)
+    } else {
+        Ok(None)
+    }
+}
+
+/// 预览图的候选位置
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    offset: u64,
+    len: u64,
+}
+
+/// 从候选位置中挑出像素最多且能被解码的 JPEG
+fn best_preview<R: Read + Seek>(
+    reader: &mut R,
+    candidates: impl IntoIterator<Item = Candidate>,
+    orientation: u8,
+    capture: Capture,
+) -> io::Result<Option<RawPreview>> {
+    let mut best: Option<(Candidate, u32, u32)> = None;
+    for candidate in candidates {
+        if candidate.len == 0 || candidate.len > MAX_PREVIEW_LEN {
+            continue;
+        }
+        let Some((width, height)) = jpeg_dimensions(reader, candidate.offset)? else {
+            continue;
+        };
+        let area = width as u64 * height as u64;
+        if best.is_none_or(|(_, w, h)| area > w as u64 * h as u64) {
+            best = Some((candidate, width, height));
+        }
+    }
+
+    let Some((candidate, width, height)) = best else {
+        return Ok(None);
+    };
+    reader.seek(SeekFrom::Start(candidate.offset))?;
+    let mut data = vec![0u8; candidate.len as usize];
+    let read = read_up_to(reader, &mut data)?;
+    data.truncate(read);
+
+    Ok(Some(RawPreview {
+        data,
+        width,
+        height,
+        orientation,
+        sensor_size: capture.sensor_size,
+        captured_at: capture.captured_at,
+    }))
+}
+
+/// 读取 JPEG 的 SOF 段得到尺寸
+///
+/// 只接受基线与渐进式 JPEG，RAW 数据常用的无损 JPEG（SOF3）返回 `None`。
+fn jpeg_dimensions<R: Read + Seek>(reader: &mut R, offset: u64) -> io::Result<Option<(u32, u32)>> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut soi = [0u8; 2];
+    if read_up_to(reader, &mut soi)? < 2 || soi != [0xff, 0xd8] {
+        return Ok(None);
+    }
+
+    loop {
+        let mut marker = [0u8; 4];
+        if read_up_to(reader, &mut marker)? < 4 || marker[0] != 0xff {
+            return Ok(None);
+        }
+        let len = u16::from_be_bytes([marker[2], marker[3]]);
+        match marker[1] {
+            // 填充字节
+            0xff => {
+                reader.seek(SeekFrom::Current(-3))?;
+            }
+            0xc0..=0xc2 => {
+                let mut sof = [0u8; 5];
+                if read_up_to(reader, &mut sof)? < 5 {
+                    return Ok(None);
+                }
+                let height = u16::from_be_bytes([sof[1], sof[2]]) as u32;
+                let width = u16::from_be_bytes([sof[3], sof[4]]) as u32;
+                return Ok((width > 0 && height > 0).then_some((width, height)));
+            }
+            // 其他 SOF 以及扫描开始、图片结束
+            0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf | 0xda | 0xd9 => return Ok(None),
+            _ if len < 2 => return Ok(None),
+            _ => {
+                reader.seek(SeekFrom::Current(len as i64 - 2))?;
+            }
+        }
+    }
+}
+
+fn raf_preview<R: Read + Seek>(reader: &mut R) -> io::Result<Option<RawPreview>> {
+    // 偏移 84 处为大端序的预览偏移与长度
+    reader.seek(SeekFrom::Start(84))?;
+    let mut buf = [0u8; 8];
+    if read_up_to(reader, &mut buf)? < 8 {
+        return Ok(None);
+    }
+    let offset = u32::from_be_bytes(buf[0..4].try_into().unwrap()) as u64;
+    let len = u32::from_be_bytes(buf[4..8].try_into().unwrap()) as u64;
+
+    let candidate = Candidate { offset, len };
+    let Some(mut preview) = best_preview(reader, [candidate], 1, Capture::default())? else {
+        return Ok(None);
+    };
+    // RAF 的预览自带完整 EXIF
+    if let Ok(exif) = exif::Reader::new().read_from_container(&mut io::Cursor::new(&preview.data)) {
+        preview.orientation = exif_orientation(&exif);
+        let capture = Capture::from_exif(&exif);
+        preview.sensor_size = capture.sensor_size;
+        preview.captured_at = capture.captured_at;
+    }
+    Ok(Some(preview))
+}
+
+/// TIFF 结构的字节序
+#[derive(Debug, Clone, Copy)]
+enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    fn u16(self, b: [u8; 2]) -> u16 {
+        match self {
+            Self::Little => u16::from_le_bytes(b),
+            Self::Big => u16::from_be_bytes(b),
+        }
+    }
+
+    fn u32(self, b: [u8; 4]) -> u32 {
+        match self {
+            Self::Little => u32::from_le_bytes(b),
+            Self::Big => u32::from_be_bytes(b),
+        }
+    }
+}
+
+/// IFD 中与预览相关的字段
+#[derive(Debug, Default)]
+struct Ifd {
+    new_subfile_type: Option<u32>,
+    compression: Option<u32>,
+    strip_offsets: Vec<u32>,
+    strip_byte_counts: Vec<u32>,
+    jpeg_offset: Option<u32>,
+    jpeg_length: Option<u32>,
+    orientation: Option<u32>,
+    sub_ifds: Vec<u32>,
+    exif_ifd: Option<u32>,
+    date_time_original: Option<Vec<u8>>,
+    pixel_x_dimension: Option<u32>,
+    pixel_y_dimension: Option<u32>,
+    next: u32,
+}
+
+impl Ifd {
+    /// EXIF IFD 中的拍摄信息
+    fn capture(&self) -> Capture {
+        Capture {
+            sensor_size: self
+                .pixel_x_dimension
+                .zip(self.pixel_y_dimension)
+                .filter(|&(w, h)| w > 0 && h > 0),
+            captured_at: self.date_time_original.as_deref().and_then(exif_timestamp),
+        }
+    }
+
+    fn candidates(&self) -> Vec<Candidate> {
+        let mut candidates = Vec::new();
+        if let (Some(offset), Some(len)) = (self.jpeg_offset, self.jpeg_length) {
+            candidates.push(Candidate {
+                offset: offset as u64,
+                len: len as u64,
+            });
+        }
+
+        // 旧式 JPEG（6）与 DNG 的预览（7 且为缩小图）存放在条带中，且只有一个条带
+        let is_jpeg = match self.compression {
+            Some(6) => true,
+            Some(7) => self.new_subfile_type.is_some_and(|t| t & 1 == 1),
+            _ => false,
+        };
+        if is_jpeg && let ([offset], [len]) = (&self.strip_offsets[..], &self.strip_byte_counts[..])
+        {
+            candidates.push(Candidate {
+                offset: *offset as u64,
+                len: *len as u64,
+            });
+        }
+        candidates
+    }
+}
+
+fn tiff_preview<R: Read + Seek>(reader: &mut R) -> io::Result<Option<RawPreview>> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut header = [0u8; 8];
+    if read_up_to(reader, &mut header)? < 8 {
+        return Ok(None);
+    }
+    let endian = if &header[0..2] == b"II" {
+        Endian::Little
+    } else {
+        Endian::Big
+    };
+
+    let mut pending = vec![endian.u32(header[4..8].try_into().unwrap())];
+    let mut visited = HashSet::new();
+    let mut candidates = Vec::new();
+    let mut orientation = None;
+    let mut exif_ifd = None;
+
+    while let Some(offset) = pending.pop() {
+        if offset == 0 || visited.len() >= MAX_IFDS || !visited.insert(offset) {
+            continue;
+        }
+        let Some(ifd) = read_ifd(reader, endian, offset)? else {
+            continue;
+        };
+
+        // 方向取自 IFD0，EXIF IFD 只由 IFD0 引用
+        if orientation.is_none() {
+            orientation = ifd.orientation;
+        }
+        if exif_ifd.is_none() {
+            exif_ifd = ifd.exif_ifd;
+        }
+        candidates.extend(ifd.candidates());
+        pending.push(ifd.next);
+        pending.extend(ifd.sub_ifds.iter().copied());
+    }
+
+    let orientation = orientation.and_then(|o| u8::try_from(o).ok()).unwrap_or(1);
+    let capture = match exif_ifd {
+        Some(offset) if offset != 0 => read_ifd(reader, endian, offset)?
+            .map(|exif| exif.capture())
+            .unwrap_or_default(),
+        _ => Capture::default(),
+    };
+    best_preview(reader, candidates, orientation, capture)
+}
+
+fn read_ifd<R: Read + Seek>(
+    reader: &mut R,
+    endian: Endian,
+    offset: u32,
+) -> io::Result<Option<Ifd>> {
+    reader.seek(SeekFrom::Start(offset as u64))?;
+    let mut count = [0u8; 2];
+    if read_up_to(reader, &mut count)? < 2 {
+        return Ok(None);
+    }
+    let count = endian.u16(count);
+    if count > MAX_IFD_ENTRIES {
+        return Ok(None);
+    }
+
+    let mut entries = vec![0u8; count as usize * 12 + 4];
+    if read_up_to(reader, &mut entries)? < entries.len() {
+        return Ok(None);
+    }
+
+    let mut ifd = Ifd {
+        next: endian.u32(entries[entries.len() - 4..].try_into().unwrap()),
+        ..Default::default()
+    };
+    for entry in entries[..count as usize * 12].chunks_exact(12) {
+        let tag = endian.u16([entry[0], entry[1]]);
+        let field_type = endian.u16([entry[2], entry[3]]);
+        let count = endian.u32(entry[4..8].try_into().unwrap());
+        let value: [u8; 4] = entry[8..12].try_into().unwrap();
+
+        match tag {
+            TAG_NEW_SUBFILE_TYPE => ifd.new_subfile_type = first_value(endian, field_type, value),
+            TAG_COMPRESSION => ifd.compression = first_value(endian, field_type, value),
+            TAG_ORIENTATION => ifd.orientation = first_value(endian, field_type, value),
+            TAG_JPEG_OFFSET => ifd.jpeg_offset = first_value(endian, field_type, value),
+            TAG_JPEG_LENGTH => ifd.jpeg_length = first_value(endian, field_type, value),
+            TAG_EXIF_IFD => ifd.exif_ifd = first_value(endian, field_type, value),
+            TAG_PIXEL_X_DIMENSION => ifd.pixel_x_dimension = first_value(endian, field_type, value),
+            TAG_PIXEL_Y_DIMENSION => ifd.pixel_y_dimension = first_value(endian, field_type, value),
+            // ASCII，格式为 `YYYY:MM:DD HH:MM:SS`
+            TAG_DATE_TIME_ORIGINAL if field_type == 2 && count <= 64 => {
+                ifd.date_time_original = read_bytes(reader, endian, count as usize, value)?;
+            }
+            TAG_STRIP_OFFSETS | TAG_STRIP_BYTE_COUNTS | TAG_SUB_IFDS => {
+                let values = read_values(reader, endian, field_type, count, value)?;
+                match tag {
+                    TAG_STRIP_OFFSETS => ifd.strip_offsets = values,
+                    TAG_STRIP_BYTE_COUNTS => ifd.strip_byte_counts = values,
+                    _ => ifd.sub_ifds = values,
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(Some(ifd))
+}
+
+/// 读取存放在条目内的第一个 SHORT 或 LONG 值
+fn first_value(endian: Endian, field_type: u16, value: [u8; 4]) -> Option<u32> {
+    match field_type {
+        // SHORT
+        3 => Some(endian.u16([value[0], value[1]]) as u32),
+        // LONG 与 IFD
+        4 | 13 => Some(endian.u32(value)),
+        _ => None,
+    }
+}
+
+/// 读取 SHORT 或 LONG 数组，超过 4 字节时数组位于 `value` 指向的偏移
+fn read_values<R: Read + Seek>(
+    reader: &mut R,
+    endian: Endian,
+    field_type: u16,
+    count: u32,
+    value: [u8; 4],
+) -> io::Result<Vec<u32>> {
+    let size = match field_type {
+        3 => 2,
+        4 | 13 => 4,
+        _ => return Ok(Vec::new()),
+    };
+    // 预览相关的数组都很短，过长的视为损坏
+    if count == 0 || count > 4096 {
+        return Ok(Vec::new());
+    }
+
+    let Some(data) = read_bytes(reader, endian, count as usize * size, value)? else {
+        return Ok(Vec::new());
+    };
+
+    Ok(data
+        .chunks_exact(size)
+        .map(|b| match size {
+            2 => endian.u16([b[0], b[1]]) as u32,
+            _ => endian.u32(b.try_into().unwrap()),
+        })
+        .collect())
+}
+
+/// 读取条目的 `len` 字节数据，超过 4 字节时位于 `value` 指向的偏移，不完整时返回 `None`
+///
+/// 读取后恢复原来的读取位置，以便继续解析后面的条目。
+fn read_bytes<R: Read + Seek>(
+    reader: &mut R,
+    endian: Endian,
+    len: usize,
+    value: [u8; 4],
+) -> io::Result<Option<Vec<u8>>> {
+    if len <= 4 {
+        return Ok(Some(value[..len].to_vec()));
+    }
+
+    let position = reader.stream_position()?;
+    reader.seek(SeekFrom::Start(endian.u32(value) as u64))?;
+    let mut data = vec![0u8; len];
+    let read = read_up_to(reader, &mut data)?;
+    reader.seek(SeekFrom::Start(position))?;
+    Ok((read == len).then_some(data))
+}
+
+fn cr3_preview<R: Read + Seek>(reader: &mut R) -> io::Result<Option<RawPreview>> {
+    let mut candidate = None;
+    let mut orientation = 1;
+    let mut capture = Capture::default();
+
+    reader.seek(SeekFrom::Start(0))?;
+    while let Some((box_type, body_offset, body_len)) = next_box(reader)? {
+        let body_end = body_offset.saturating_add(body_len);
+        match &box_type {
+            b"moov" if body_len <= MAX_PREVIEW_LEN => {
+                let mut moov = vec![0u8; body_len as usize];
+                reader.read_exact(&mut moov)?;
+                if let Some(o) = cr3_orientation(&moov) {
+                    orientation = o;
+                }
+                if let Some(c) = cr3_capture(&moov)? {
+                    capture = c;
+                }
+            }
+            b"uuid" if body_len <= MAX_PREVIEW_LEN => {
+                let mut body = vec![0u8; body_len as usize];
+                reader.read_exact(&mut body)?;
+                if body.starts_with(&CR3_PREVIEW_UUID) {
+                    candidate = prvw_candidate(&body, body_offset);
+                }
+            }
+            _ => {}
+        }
+        reader.seek(SeekFrom::Start(body_end))?;
+    }
+
+    best_preview(reader, candidate, orientation, capture)
+}
+
+/// 在预览 uuid box 中找到 `PRVW` box 内的 JPEG
+///
+/// `PRVW` 前有 8 字节未知数据，`PRVW` 内 JPEG 之前的字段为宽、高与长度。
+fn prvw_candidate(body: &[u8], body_offset: u64) -> Option<Candidate> {
+    let start = body.windows(4).position(|w| w == b"PRVW")?.checked_sub(4)?;
+    let size = u32::from_be_bytes(body.get(start..start + 4)?.try_into().ok()?) as usize;
+    let prvw = body.get(start..start.checked_add(size)?)?;
+    let jpeg = prvw.windows(3).position(|w| w == [0xff, 0xd8, 0xff])?;
+
+    Some(Candidate {
+        offset: body_offset + (start + jpeg) as u64,
+        len: (prvw.len() - jpeg) as u64,
+    })
+}
+
+/// 读取 `moov` 内元数据 uuid box 中的 `CMT1`、`CMT2` 等 TIFF 结构
+fn cr3_metadata<'a>(moov: &'a [u8], box_type: &[u8; 4]) -> Option<&'a [u8]> {
+    let metadata = boxes(moov)
+        .find(|(t, body)| t == b"uuid" && body.starts_with(&CR3_METADATA_UUID))?
+        .1;
+    Some(boxes(&metadata[16..]).find(|(t, _)| t == box_type)?.1)
+}
+
+/// 从 `CMT1`（TIFF IFD0）读取方向
+fn cr3_orientation(moov: &[u8]) -> Option<u8> {
+    let cmt1 = cr3_metadata(moov, b"CMT1")?;
+    let exif = exif::Reader::new().read_raw(cmt1.to_vec()).ok()?;
+    Some(exif_orientation(&exif))
+}
+
+/// 从 `CMT2` 读取拍摄时间与图像尺寸
+///
+/// `CMT2` 是以 EXIF IFD 为 IFD0 的 TIFF 结构，按 IFD0 解析时标签不在 EXIF 上下文中，
+/// 因此用自己的 IFD 解析而不是 kamadak-exif。
+fn cr3_capture(moov: &[u8]) -> io::Result<Option<Capture>> {
+    let Some(cmt2) = cr3_metadata(moov, b"CMT2") else {
+        return Ok(None);
+    };
+    let endian = match cmt2.get(0..4) {
+        Some(b"II*\0") => Endian::Little,
+        Some(b"MM\0*") => Endian::Big,
+        _ => return Ok(None),
+    };
+    let Some(offset) = cmt2.get(4..8) else {
+        return Ok(None);
+    };
+    let offset = endian.u32(offset.try_into().unwrap());
+    let ifd = read_ifd(&mut io::Cursor::new(cmt2), endian, offset)?;
+    Ok(ifd.map(|ifd| ifd.capture()))
+}
+
+fn exif_orientation(exif: &exif::Exif) -> u8 {
+    exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0))
+        .and_then(|o| u8::try_from(o).ok())
+        .unwrap_or(1)
+}
+
+/// 把 EXIF 的 `YYYY:MM:DD HH:MM:SS` 转换为 UNIX 时间戳，未填写的日期返回 `None`
+fn exif_timestamp(ascii: &[u8]) -> Option<i64> {
+    let date = exif::DateTime::from_ascii(ascii).ok()?;
+    let valid = date.year > 0
+        && (1..=12).contains(&date.month)
+        && (1..=31).contains(&date.day)
+        && date.hour < 24
+        && date.minute < 60
+        && date.second < 61;
+    if !valid {
+        return None;
+    }
+    let days = days_from_civil(date.year as i64, date.month as i64, date.day as i64);
+    let seconds = date.hour as i64 * 3600 + date.minute as i64 * 60 + date.second as i64;
+    Some(days * 86_400 + seconds)
+}
+
+/// 公历日期距 1970-01-01 的天数
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{RgbImage, codecs::jpeg::JpegEncoder};
+
+    use super::*;
+
+    /// 2024-05-06 07:08:09 UTC
+    const CAPTURED_AT: i64 = 1_714_979_289;
+    const DATE_TIME: &[u8; 20] = b"2024:05:06 07:08:09\0";
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        JpegEncoder::new(&mut data)
+            .encode_image(&RgbImage::new(width, height))
+            .unwrap();
+        data
+    }
+
+    /// 按字节序写入 TIFF 结构的辅助类型
+    struct Tiff {
+        endian: Endian,
+        data: Vec<u8>,
+    }
+
+    impl Tiff {
+        fn new(endian: Endian) -> Self {
+            let magic: &[u8] = match endian {
+                Endian::Little => b"II*\0",
+                Endian::Big => b"MM\0*",
+            };
+            let mut tiff = Self {
+                endian,
+                data: magic.to_vec(),
+            };
+            tiff.u32(8);
+            tiff
+        }
+
+        fn u16(&mut self, value: u16) {
+            let bytes = match self.endian {
+                Endian::Little => value.to_le_bytes(),
+                Endian::Big => value.to_be_bytes(),
+            };
+            self.data.extend_from_slice(&bytes);
+        }
+
+        fn u32(&mut self, value: u32) {
+            let bytes = match self.endian {
+                Endian::Little => value.to_le_bytes(),
+                Endian::Big => value.to_be_bytes(),
+            };
+            self.data.extend_from_slice(&bytes);
+        }
+
+        /// 写入一个 IFD，条目为 `(标签, 类型, 数量, 值)`，SHORT 值写在条目的前两个字节
+        fn ifd(&mut self, entries: &[(u16, u16, u32, u32)], next: u32) {
+            self.u16(entries.len() as u16);
+            for &(tag, field_type, count, value) in entries {
+                self.u16(tag);
+                self.u16(field_type);
+                self.u32(count);
+                if field_type == 3 && count == 1 {
+                    self.u16(value as u16);
+                    self.u16(0);
+                } else {
+                    self.u32(value);
+                }
+            }
+            self.u32(next);
+        }
+
+        fn len(&self) -> u32 {
+            self.data.len() as u32
+        }
+    }
+
+    /// IFD 占用的字节数
+    fn ifd_len(entries: usize) -> u32 {
+        2 + entries as u32 * 12 + 4
+    }
+
+    /// IFD0 带方向、JPEG 预览与 EXIF IFD 的 TIFF RAW
+    fn tiff_raw(endian: Endian, preview: &[u8]) -> Vec<u8> {
+        let exif_ifd = 8 + ifd_len(4);
+        let date = exif_ifd + ifd_len(3);
+        let jpeg_offset = date + DATE_TIME.len() as u32;
+
+        let mut tiff = Tiff::new(endian);
+        tiff.ifd(
+            &[
+                (TAG_ORIENTATION, 3, 1, 6),
+                (TAG_JPEG_OFFSET, 4, 1, jpeg_offset),
+                (TAG_JPEG_LENGTH, 4, 1, preview.len() as u32),
+                (TAG_EXIF_IFD, 4, 1, exif_ifd),
+            ],
+            0,
+        );
+        assert_eq!(tiff.len(), exif_ifd);
+        tiff.ifd(
+            &[
+                (TAG_DATE_TIME_ORIGINAL, 2, DATE_TIME.len() as u32, date),
+                (TAG_PIXEL_X_DIMENSION, 4, 1, 6000),
+                (TAG_PIXEL_Y_DIMENSION, 3, 1, 4000),
+            ],
+            0,
+        );
+        tiff.data.extend_from_slice(DATE_TIME);
+        tiff.data.extend_from_slice(preview);
+        tiff.data
+    }
+
+    #[test]
+    fn tiff_reads_preview_orientation_and_capture() {
+        for endian in [Endian::Little, Endian::Big] {
+            let preview = jpeg(32, 16);
+            let raw = tiff_raw(endian, &preview);
+
+            let found = tiff_preview(&mut Cursor::new(&raw)).unwrap().unwrap();
+            assert_eq!(found.data, preview);
+            assert_eq!((found.width, found.height), (32, 16));
+            assert_eq!(found.orientation, 6);
+            assert_eq!(found.sensor_size, Some((6000, 4000)));
+            assert_eq!(found.captured_at, Some(CAPTURED_AT));
+        }
+    }
+
+    #[test]
+    fn tiff_prefers_largest_preview_in_sub_ifds() {
+        let small = jpeg(16, 8);
+        let large = jpeg(64, 32);
+        let sub_ifd = 8 + ifd_len(3);
+        let small_offset = sub_ifd + ifd_len(3);
+        let large_offset = small_offset + small.len() as u32;
+
+        let mut tiff = Tiff::new(Endian::Big);
+        tiff.ifd(
+            &[
+                (TAG_JPEG_OFFSET, 4, 1, small_offset),
+                (TAG_JPEG_LENGTH, 4, 1, small.len() as u32),
+                (TAG_SUB_IFDS, 13, 1, sub_ifd),
+            ],
+            0,
+        );
+        // 旧式 JPEG 压缩，预览存放在唯一的条带中
+        tiff.ifd(
+            &[
+                (TAG_COMPRESSION, 3, 1, 6),
+                (TAG_STRIP_OFFSETS, 4, 1, large_offset),
+                (TAG_STRIP_BYTE_COUNTS, 4, 1, large.len() as u32),
+            ],
+            0,
+        );
+        tiff.data.extend_from_slice(&small);
+        tiff.data.extend_from_slice(&large);
+
+        let found = tiff_preview(&mut Cursor::new(&tiff.data)).unwrap().unwrap();
+        assert_eq!(found.data, large);
+        assert_eq!((found.width, found.height), (64, 32));
+        // 没有 EXIF IFD 时保留预览图的尺寸
+        assert_eq!(found.sensor_size, None);
+        assert_eq!(found.captured_at, None);
+    }
+
+    #[test]
+    fn tiff_ifd_cycle_terminates() {
+        let mut tiff = Tiff::new(Endian::Little);
+        // IFD0 的下一个 IFD 与 SubIFD 都指向自己
+        tiff.ifd(&[(TAG_SUB_IFDS, 4, 1, 8)], 8);
+        assert!(
+            tiff_preview(&mut Cursor::new(&tiff.data))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn tiff_rejects_out_of_bounds_offsets_and_lengths() {
+        let preview = jpeg(32, 16);
+        let cases: [&[(u16, u16, u32, u32)]; 5] = [
+            // 预览偏移超出文件
+            &[
+                (TAG_JPEG_OFFSET, 4, 1, u32::MAX),
+                (TAG_JPEG_LENGTH, 4, 1, 100),
+            ],
+            // 长度超过上限
+            &[
+                (TAG_JPEG_OFFSET, 4, 1, 8),
+                (TAG_JPEG_LENGTH, 4, 1, u32::MAX),
+            ],
+            // 长度为 0
+            &[(TAG_JPEG_OFFSET, 4, 1, 8), (TAG_JPEG_LENGTH, 4, 1, 0)],
+            // SubIFD 数组的数量过大或指向文件之外
+            &[
+                (TAG_SUB_IFDS, 4, u32::MAX, 8),
+                (TAG_EXIF_IFD, 4, 1, u32::MAX),
+            ],
+            &[(TAG_SUB_IFDS, 4, 2, u32::MAX - 2)],
+        ];
+        for entries in cases {
+            let mut tiff = Tiff::new(Endian::Little);
+            tiff.ifd(entries, 0);
+            tiff.data.extend_from_slice(&preview);
+            assert!(
+                tiff_preview(&mut Cursor::new(&tiff.data))
+                    .unwrap()
+                    .is_none(),
+                "{:?}",
+                entries
+            );
+        }
+
+        // 条目数超过上限的 IFD 被忽略
+        let mut tiff = Tiff::new(Endian::Little);
+        tiff.u16(u16::MAX);
+        assert!(
+            tiff_preview(&mut Cursor::new(&tiff.data))
+                .unwrap()
+                .is_none()
+        );
+
+        // 拍摄时间的偏移超出文件
+        let mut raw = tiff_raw(Endian::Little, &preview);
+        let date_entry = (8 + ifd_len(4) + 2 + 8) as usize;
+        raw[date_entry..date_entry + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let found = tiff_preview(&mut Cursor::new(&raw)).unwrap().unwrap();
+        assert_eq!(found.captured_at, None);
+        assert_eq!(found.sensor_size, Some((6000, 4000)));
+    }
+
+    #[test]
+    fn tiff_truncated_at_every_length() {
+        let raw = tiff_raw(Endian::Little, &jpeg(32, 16));
+        for len in 0..raw.len() {
+            let result = tiff_preview(&mut Cursor::new(&raw[..len])).unwrap();
+            // 截断在预览数据中间时仍能读出 SOF，只返回已有的数据
+            if let Some(preview) = result {
+                assert!(preview.data.len() < raw.len());
+            }
+        }
+    }
+
+    /// 在 JPEG 的 SOI 之后插入包含 `tiff` 的 APP1 段
+    fn with_exif(jpeg: &[u8], tiff: &[u8]) -> Vec<u8> {
+        let len = (2 + 6 + tiff.len()) as u16;
+        let mut data = jpeg[..2].to_vec();
+        data.extend_from_slice(&[0xff, 0xe1]);
+        data.extend_from_slice(&len.to_be_bytes());
+        data.extend_from_slice(b"Exif\0\0");
+        data.extend_from_slice(tiff);
+        data.extend_from_slice(&jpeg[2..]);
+        data
+    }
+
+    fn raf(preview: &[u8]) -> Vec<u8> {
+        let mut data = RAF_MAGIC.to_vec();
+        data.resize(84, 0);
+        data.extend_from_slice(&100u32.to_be_bytes());
+        data.extend_from_slice(&(preview.len() as u32).to_be_bytes());
+        data.resize(100, 0);
+        data.extend_from_slice(preview);
+        data
+    }
+
+    #[test]
+    fn raf_reads_preview_and_embedded_exif() {
+        // 预览自带 EXIF：IFD0 中的方向与 EXIF IFD 中的拍摄信息
+        let mut tiff = Tiff::new(Endian::Big);
+        let exif_ifd = 8 + ifd_len(2);
+        let date = exif_ifd + ifd_len(3);
+        tiff.ifd(
+            &[(TAG_ORIENTATION, 3, 1, 8), (TAG_EXIF_IFD, 4, 1, exif_ifd)],
+            0,
+        );
+        tiff.ifd(
+            &[
+                (TAG_DATE_TIME_ORIGINAL, 2, DATE_TIME.len() as u32, date),
+                (TAG_PIXEL_X_DIMENSION, 4, 1, 6240),
+                (TAG_PIXEL_Y_DIMENSION, 4, 1, 4160),
+            ],
+            0,
+        );
+        tiff.data.extend_from_slice(DATE_TIME);
+        let preview = with_exif(&jpeg(48, 32), &tiff.data);
+
+        let found = raf_preview(&mut Cursor::new(raf(&preview)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.data, preview);
+        assert_eq!((found.width, found.height), (48, 32));
+        assert_eq!(found.orientation, 8);
+        assert_eq!(found.sensor_size, Some((6240, 4160)));
+        assert_eq!(found.captured_at, Some(CAPTURED_AT));
+    }
+
+    #[test]
+    fn raf_rejects_truncated_and_corrupt_headers() {
+        let preview = jpeg(48, 32);
+        let data = raf(&preview);
+        // 头部不完整或预览数据开头缺失
+        for len in 0..104 {
+            assert!(
+                raf_preview(&mut Cursor::new(&data[..len]))
+                    .unwrap()
+                    .is_none(),
+                "{}",
+                len
+            );
+        }
+
+        let mut corrupt = data.clone();
+        corrupt[84..88].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(raf_preview(&mut Cursor::new(&corrupt)).unwrap().is_none());
+
+        let mut corrupt = data;
+        corrupt[88..92].copy_from_slice(&0u32.to_be_bytes());
+        assert!(raf_preview(&mut Cursor::new(&corrupt)).unwrap().is_none());
+    }
+
+    fn iso_box(box_type: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = ((8 + body.len()) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn cr3(preview: &[u8]) -> Vec<u8> {
+        let mut cmt1 = Tiff::new(Endian::Little);
+        cmt1.ifd(&[(TAG_ORIENTATION, 3, 1, 3)], 0);
+        // CMT2 的 IFD0 即 EXIF IFD
+        let mut cmt2 = Tiff::new(Endian::Little);
+        let date = 8 + ifd_len(3);
+        cmt2.ifd(
+            &[
+                (TAG_DATE_TIME_ORIGINAL, 2, DATE_TIME.len() as u32, date),
+                (TAG_PIXEL_X_DIMENSION, 4, 1, 6720),
+                (TAG_PIXEL_Y_DIMENSION, 4, 1, 4480),
+            ],
+            0,
+        );
+        cmt2.data.extend_from_slice(DATE_TIME);
+
+        let mut metadata = CR3_METADATA_UUID.to_vec();
+        metadata.extend(iso_box(b"CMT1", &cmt1.data));
+        metadata.extend(iso_box(b"CMT2", &cmt2.data));
+        let moov = iso_box(b"moov", &iso_box(b"uuid", &metadata));
+
+        // PRVW 之前有 8 字节未知数据，JPEG 之前为宽、高与长度等字段
+        let mut prvw = vec![0u8; 8];
+        prvw.extend_from_slice(&48u16.to_be_bytes());
+        prvw.extend_from_slice(&32u16.to_be_bytes());
+        prvw.extend_from_slice(&(preview.len() as u32).to_be_bytes());
+        prvw.extend_from_slice(preview);
+        let mut preview_uuid = CR3_PREVIEW_UUID.to_vec();
+        preview_uuid.extend_from_slice(&[0; 8]);
+        preview_uuid.extend(iso_box(b"PRVW", &prvw));
+
+        let mut data = iso_box(b"ftyp", b"crx \0\0\0\x01crx isom");
+        data.extend(moov);
+        data.extend(iso_box(b"uuid", &preview_uuid));
+        data
+    }
+
+    #[test]
+    fn cr3_reads_prvw_and_metadata() {
+        let preview = jpeg(48, 32);
+        let found = cr3_preview(&mut Cursor::new(cr3(&preview)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.data, preview);
+        assert_eq!((found.width, found.height), (48, 32));
+        assert_eq!(found.orientation, 3);
+        assert_eq!(found.sensor_size, Some((6720, 4480)));
+        assert_eq!(found.captured_at, Some(CAPTURED_AT));
+    }
+
+    #[test]
+    fn cr3_truncated_at_every_length() {
+        let data = cr3(&jpeg(48, 32));
+        for len in 0..data.len() {
+            // 截断的 box 会让 read_exact 报错，但不能越界或死循环
+            let _ = cr3_preview(&mut Cursor::new(&data[..len]));
+        }
+    }
+
+    #[test]
+    fn prvw_size_is_bounds_checked() {
+        let mut body = CR3_PREVIEW_UUID.to_vec();
+        body.extend_from_slice(&[0; 8]);
+        body.extend_from_slice(&u32::MAX.to_be_bytes());
+        body.extend_from_slice(b"PRVW\xff\xd8\xff");
+        assert!(prvw_candidate(&body, 0).is_none());
+
+        // `PRVW` 位于开头，前面放不下长度字段
+        assert!(prvw_candidate(b"PRVW\xff\xd8\xff", 0).is_none());
+    }
+
+    #[test]
+    fn exif_timestamps() {
+        assert_eq!(exif_timestamp(DATE_TIME), Some(CAPTURED_AT));
+        assert_eq!(exif_timestamp(b"1970:01:01 00:00:00"), Some(0));
+        assert_eq!(exif_timestamp(b"2000:02:29 12:00:00"), Some(951_825_600));
+        assert_eq!(exif_timestamp(b"    :  :     :  :  "), None);
+        assert_eq!(exif_timestamp(b"0000:00:00 00:00:00"), None);
+        assert_eq!(exif_timestamp(b"2024:13:01 00:00:00"), None);
+        assert_eq!(exif_timestamp(b"2024:05"), None);
+    }
+}
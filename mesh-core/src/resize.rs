@@ -0,0 +1,208 @@
+//! 生成缩略图用的面积平均缩小算法
+//!
+//! 先把覆盖同一输出行的源行按权重累加，再在累加结果上做水平方向的平均。
+//! 逐行累加覆盖了每一个源像素，是主要的开销，在 x86_64 上使用 SSE2 一次处理 16 个字节。
+
+use image::{DynamicImage, GenericImageView, ImageBuffer, Pixel};
+
+/// 用面积平均算法把图片缩小到 `width`×`height`
+///
+/// 8 位的灰度、RGB 与 RGBA 图片使用 SIMD 逐行累加，其余像素格式以及需要放大时
+/// 回退到 [`DynamicImage::thumbnail_exact`]。
+pub(crate) fn downscale(image: DynamicImage, width: u32, height: u32) -> DynamicImage {
+    let (src_w, src_h) = image.dimensions();
+    if width == 0 || height == 0 || width > src_w || height > src_h {
+        return image.thumbnail_exact(width, height);
+    }
+
+    match image {
+        DynamicImage::ImageLuma8(image) => downscale_buffer(&image, width, height).into(),
+        DynamicImage::ImageLumaA8(image) => downscale_buffer(&image, width, height).into(),
+        DynamicImage::ImageRgb8(image) => downscale_buffer(&image, width, height).into(),
+        DynamicImage::ImageRgba8(image) => downscale_buffer(&image, width, height).into(),
+        image => image.thumbnail_exact(width, height),
+    }
+}
+
+fn downscale_buffer<P: Pixel<Subpixel = u8>>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    width: u32,
+    height: u32,
+) -> ImageBuffer<P, Vec<u8>> {
+    let channels = P::CHANNEL_COUNT as usize;
+    let (src_w, src_h) = image.dimensions();
+    let row_len = src_w as usize * channels;
+    let columns = spans(src_w, width);
+    let rows = spans(src_h, height);
+
+    let src = image.as_raw();
+    let mut output = vec![0u8; width as usize * height as usize * channels];
+    let mut row = vec![0f32; row_len];
+    for (out_row, span) in output
+        .chunks_exact_mut(width as usize * channels)
+        .zip(&rows)
+    {
+        row.fill(0.0);
+        for (i, &weight) in span.weights.iter().enumerate() {
+            let start = (span.start + i) * row_len;
+            accumulate_row(&mut row, &src[start..start + row_len], weight);
+        }
+
+        for (pixel, span) in out_row.chunks_exact_mut(channels).zip(&columns) {
+            for (c, out) in pixel.iter_mut().enumerate() {
+                let sum: f32 = span
+                    .weights
+                    .iter()
+                    .enumerate()
+                    .map(|(i, weight)| row[(span.start + i) * channels + c] * weight)
+                    .sum();
+                *out = sum.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+
+    ImageBuffer::from_raw(width, height, output).expect("output buffer matches dimensions")
+}
+
+/// 一个输出像素覆盖的源像素范围及各自的权重，权重之和为 1
+struct Span {
+    start: usize,
+    weights: Vec<f32>,
+}
+
+/// 把 `src` 个源像素均分给 `dst` 个输出像素，边界上的源像素按覆盖的比例分配
+fn spans(src: u32, dst: u32) -> Vec<Span> {
+    let scale = src as f64 / dst as f64;
+    (0..dst)
+        .map(|o| {
+            let begin = o as f64 * scale;
+            let end = ((o + 1) as f64 * scale).min(src as f64);
+            let start = begin.floor() as usize;
+            let stop = (end.ceil() as usize).min(src as usize);
+            let weights = (start..stop)
+                .map(|i| ((end.min(i as f64 + 1.0) - begin.max(i as f64)) / scale) as f32)
+                .collect();
+            Span { start, weights }
+        })
+        .collect()
+}
+
+/// `acc[i] += src[i] * weight`
+#[cfg(target_arch = "x86_64")]
+fn accumulate_row(acc: &mut [f32], src: &[u8], weight: f32) {
+    use std::arch::x86_64::*;
+
+    assert_eq!(acc.len(), src.len());
+    let len = src.len() / 16 * 16;
+    // SAFETY: x86_64 总是支持 SSE2；每次读取 16 个字节、写入 16 个浮点数，都在 `len` 之内
+    unsafe {
+        let weight = _mm_set1_ps(weight);
+        let zero = _mm_setzero_si128();
+        for i in (0..len).step_by(16) {
+            let bytes = _mm_loadu_si128(src.as_ptr().add(i).cast());
+            let words = [
+                _mm_unpacklo_epi8(bytes, zero),
+                _mm_unpackhi_epi8(bytes, zero),
+            ];
+            for (j, words) in words.into_iter().enumerate() {
+                let ints = [
+                    _mm_unpacklo_epi16(words, zero),
+                    _mm_unpackhi_epi16(words, zero),
+                ];
+                for (k, ints) in ints.into_iter().enumerate() {
+                    let dst = acc.as_mut_ptr().add(i + j * 8 + k * 4);
+                    let product = _mm_mul_ps(_mm_cvtepi32_ps(ints), weight);
+                    _mm_storeu_ps(dst, _mm_add_ps(_mm_loadu_ps(dst), product));
+                }
+            }
+        }
+    }
+    accumulate_row_scalar(&mut acc[len..], &src[len..], weight);
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn accumulate_row(acc: &mut [f32], src: &[u8], weight: f32) {
+    accumulate_row_scalar(acc, src, weight);
+}
+
+fn accumulate_row_scalar(acc: &mut [f32], src: &[u8], weight: f32) {
+    for (acc, &value) in acc.iter_mut().zip(src) {
+        *acc += value as f32 * weight;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, RgbaImage};
+
+    fn gradient(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| {
+            Rgb([
+                (x * 255 / width) as u8,
+                (y * 255 / height) as u8,
+                ((x + y) * 255 / (width + height)) as u8,
+            ])
+        })
+    }
+
+    #[test]
+    fn accumulate_row_matches_scalar() {
+        // 长度不是 16 的倍数，覆盖 SIMD 之后的剩余部分
+        let src: Vec<u8> = (0..=255).chain(0..37).collect();
+        let mut simd = vec![0.5; src.len()];
+        let mut scalar = simd.clone();
+        accumulate_row(&mut simd, &src, 0.25);
+        accumulate_row_scalar(&mut scalar, &src, 0.25);
+        assert_eq!(simd, scalar);
+    }
+
+    #[test]
+    fn spans_cover_every_source_pixel_once() {
+        for (src, dst) in [(10, 3), (4000, 300), (7, 7), (5, 1)] {
+            let spans = spans(src, dst);
+            let mut coverage = vec![0f64; src as usize];
+            for span in &spans {
+                let sum: f32 = span.weights.iter().sum();
+                assert!((sum - 1.0).abs() < 1e-4, "{}->{}: {}", src, dst, sum);
+                for (i, weight) in span.weights.iter().enumerate() {
+                    coverage[span.start + i] += *weight as f64 * src as f64 / dst as f64;
+                }
+            }
+            assert!(coverage.iter().all(|c| (c - 1.0).abs() < 1e-4));
+        }
+    }
+
+    #[test]
+    fn uniform_color_is_preserved() {
+        let image = RgbaImage::from_pixel(333, 211, image::Rgba([10, 200, 37, 128]));
+        let resized = downscale(image.into(), 47, 30).into_rgba8();
+        assert_eq!(resized.dimensions(), (47, 30));
+        assert!(resized.pixels().all(|p| p.0 == [10, 200, 37, 128]));
+    }
+
+    #[test]
+    fn matches_area_average_of_thumbnail_exact() {
+        let image = DynamicImage::from(gradient(1203, 797));
+        let expected = image.thumbnail_exact(150, 99).into_rgb8();
+        let resized = downscale(image, 150, 99).into_rgb8();
+        assert_eq!(resized.dimensions(), expected.dimensions());
+        for (a, b) in resized.pixels().zip(expected.pixels()) {
+            for (a, b) in a.0.iter().zip(b.0) {
+                assert!(a.abs_diff(b) <= 1, "{:?} vs {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn other_formats_fall_back() {
+        let image = DynamicImage::new_rgb16(64, 48);
+        let resized = downscale(image, 16, 12);
+        assert!(matches!(resized, DynamicImage::ImageRgb16(_)));
+        assert_eq!(resized.dimensions(), (16, 12));
+
+        // 放大时同样回退
+        let resized = downscale(gradient(8, 8).into(), 16, 16);
+        assert_eq!(resized.dimensions(), (16, 16));
+    }
+}
@@ -0,0 +1,455 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ignore::{
+    Match, WalkBuilder,
+    gitignore::{Gitignore, GitignoreBuilder},
+};
+
+use crate::{FormatDecoder, MediaFormat, MeshConfig, decode};
+
+/// 目录中的忽略规则文件，格式与 `.gitignore` 相同，作用于所在目录及其子目录
+pub const IGNORE_FILE_NAME: &str = ".meshignore";
+
+/// 扫描时决定哪些文件进入照片库的规则，由配置生成
+#[derive(Debug, Clone)]
+pub struct ScanFilter {
+    /// 相册目录与以该目录为根的忽略规则
+    albums: Vec<(PathBuf, Gitignore)>,
+    excluded_dirs: Vec<PathBuf>,
+    formats: Vec<MediaFormat>,
+    skip_hidden: bool,
+    min_file_size: u64,
+    min_width: u32,
+    min_height: u32,
+    follow_symlinks: bool,
+}
+
+impl ScanFilter {
+    /// 无效的忽略规则会被跳过并记录日志
+    pub fn new(config: &MeshConfig) -> Self {
+        let (min_width, min_height) = config.min_dimensions();
+        Self {
+            albums: config
+                .album_dirs()
+                .iter()
+                .map(|dir| (dir.clone(), build_patterns(dir, config.ignore_patterns())))
+                .collect(),
+            excluded_dirs: config.excluded_dirs().clone(),
+            formats: config.formats().to_vec(),
+            skip_hidden: config.skip_hidden(),
+            min_file_size: config.min_file_size(),
+            min_width,
+            min_height,
+            follow_symlinks: config.follow_symlinks(),
+        }
+    }
+
+    fn album(&self, path: &Path) -> Option<&(PathBuf, Gitignore)> {
+        self.albums.iter().find(|(dir, _)| path.starts_with(dir))
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        self.excluded_dirs.iter().any(|dir| path.starts_with(dir))
+    }
+
+    /// 遍历的起点不经过 walker 的过滤，需要逐级检查它与相册目录之间的每一层
+    fn is_ignored_root(&self, path: &Path) -> bool {
+        let Some((album, patterns)) = self.album(path) else {
+            return true;
+        };
+        if self.is_excluded(path) {
+            return true;
+        }
+
+        // 相册目录到起点之间每一层目录中的 `.meshignore`，越深的优先级越高
+        let ignore_files: Vec<(&Path, Gitignore)> = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(album))
+            .filter_map(|dir| {
+                let file = dir.join(IGNORE_FILE_NAME);
+                file.is_file().then(|| (dir, Gitignore::new(file).0))
+            })
+            .collect();
+
+        let is_ignored = |entry: &Path, is_dir: bool| {
+            if self.skip_hidden
+                && entry
+                    .file_name()
+                    .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+            {
+                return true;
+            }
+            let matched = ignore_files
+                .iter()
+                .filter(|(dir, _)| entry.starts_with(dir) && entry != *dir)
+                .map(|(_, ignore)| ignore.matched(entry, is_dir))
+                .find(|matched| !matched.is_none())
+                .unwrap_or(Match::None);
+            matched.is_ignore() || patterns.matched(entry, is_dir).is_ignore()
+        };
+
+        let is_dir = path.is_dir();
+        path.ancestors()
+            .take_while(|entry| entry != album && entry.starts_with(album))
+            .enumerate()
+            .any(|(depth, entry)| is_ignored(entry, depth > 0 || is_dir))
+    }
+
+    /// 遍历目录树，跳过被排除、被忽略规则匹配或隐藏的文件与目录
+    ///
+    /// 跟随符号链接时，指向上级目录的链接会被识别为循环并跳过。
+    fn walk(&self, root: &Path) -> impl Iterator<Item = PathBuf> + use<> {
+        let patterns = self
+            .album(root)
+            .map(|(_, patterns)| patterns.clone())
+            .unwrap_or_else(Gitignore::empty);
+        let excluded_dirs = self.excluded_dirs.clone();
+        let follow_symlinks = self.follow_symlinks;
+
+        WalkBuilder::new(root)
+            .standard_filters(false)
+            // 从子目录开始扫描时也读取上级目录中的 `.meshignore`
+            .parents(true)
+            .hidden(self.skip_hidden)
+            .add_custom_ignore_filename(IGNORE_FILE_NAME)
+            .follow_links(self.follow_symlinks)
+            // 同一文件经由多个链接到达时，总是保留排序在前的路径
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .filter_entry(move |entry| {
+                let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+                let is_excluded =
+                    |path: &Path| excluded_dirs.iter().any(|dir| path.starts_with(dir));
+                // 链接本身不在排除的目录中时，还要检查它指向的位置
+                let links_to_excluded = follow_symlinks
+                    && entry.path_is_symlink()
+                    && fs::canonicalize(entry.path()).is_ok_and(|target| is_excluded(&target));
+                !is_excluded(entry.path())
+                    && !links_to_excluded
+                    && !patterns.matched(entry.path(), is_dir).is_ignore()
+            })
+            .build()
+            .filter_map(|entry| {
+                entry
+                    .map_err(|e| log::warn!("Failed to scan: {}", e))
+                    .ok()
+            })
+            .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+            .map(|entry| entry.into_path())
+    }
+
+    /// 属于启用的格式，且不小于最小文件大小与最小尺寸
+    fn accepts(&self, path: &Path) -> bool {
+        let Some(format) = enabled_format(path, &self.formats) else {
+            return false;
+        };
+        if self.min_file_size > 0
+            && fs::metadata(path).is_ok_and(|metadata| metadata.len() < self.min_file_size)
+        {
+            return false;
+        }
+        // 只检查能从文件头读出尺寸的图片，读取失败的文件留给缩略图生成时记录错误
+        if (self.min_width > 0 || self.min_height > 0)
+            && let FormatDecoder::Image(image_format) = format.info().decoder
+            && let Some((width, height)) = decode::image_dimensions(path, image_format)
+        {
+            return width >= self.min_width && height >= self.min_height;
+        }
+        true
+    }
+}
+
+/// 遍历 `files` 中的文件与目录，返回位于相册目录内、未被排除或忽略且属于启用格式的图片与视频
+///
+/// 扩展名不属于启用的格式时按文件内容判断，因此没有扩展名或扩展名错误的文件也会被收录；
+/// 当前构建无法解码的格式会被跳过。
+///
+/// 跟随符号链接时，指向相册内的文件以真实路径返回，与经由哪个链接到达无关，
+/// 真实路径被排除或忽略时跳过；指向相册之外的文件保留第一次遇到的链接路径。
+/// 同一文件只返回一次。
+pub fn collect_files(filter: &ScanFilter, files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    files
+        .into_iter()
+        .filter(|p| p.is_file() || p.is_dir())
+        // 不跟随符号链接时，经由链接到达的起点同样跳过
+        .filter(|p| filter.follow_symlinks || fs::canonicalize(p).is_ok_and(|c| c == *p))
+        .filter(|p| !filter.is_ignored_root(p))
+        .flat_map(|p| filter.walk(&p))
+        .filter_map(|f| {
+            if !filter.follow_symlinks {
+                return Some(f);
+            }
+            let canonical = fs::canonicalize(&f).ok()?;
+            if !seen.insert(canonical.clone()) {
+                return None;
+            }
+            if canonical == f || filter.album(&canonical).is_none() {
+                Some(f)
+            } else {
+                (!filter.is_ignored_root(&canonical)).then_some(canonical)
+            }
+        })
+        .filter(|f| filter.accepts(f))
+        .collect()
+}
+
+/// 以相册目录为根构建配置中的忽略规则
+fn build_patterns(album_dir: &Path, patterns: &[String]) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(album_dir);
+    for pattern in patterns {
+        if let Err(e) = builder.add_line(None, pattern) {
+            log::warn!("Invalid ignore pattern {:?}: {}", pattern, e);
+        }
+    }
+    builder.build().unwrap_or_else(|e| {
+        log::warn!("Failed to build ignore patterns: {}", e);
+        Gitignore::empty()
+    })
+}
+
+/// 扩展名或文件内容属于启用且可解码的格式
+fn enabled_format(path: &Path, formats: &[MediaFormat]) -> Option<MediaFormat> {
+    let enabled = |format: &MediaFormat| format.is_decodable() && formats.contains(format);
+    if let Some(format) = MediaFormat::from_path(path).filter(enabled) {
+        return Some(format);
+    }
+    MediaFormat::detect(path).ok().flatten().filter(enabled)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::RgbImage;
+
+    use super::*;
+    use crate::MeshDirs;
+
+    struct Album {
+        _dir: tempfile::TempDir,
+        root: PathBuf,
+        config: MeshConfig,
+    }
+
+    impl Album {
+        /// 以 `settings` 作为额外的配置项创建相册目录，`excluded` 相对于相册目录
+        fn new(excluded: &[&str], settings: &str) -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let base = dir.path().canonicalize().unwrap();
+            let root = base.join("album");
+            fs::create_dir_all(&root).unwrap();
+            let excluded: Vec<PathBuf> = excluded.iter().map(|dir| root.join(dir)).collect();
+
+            let dirs = MeshDirs::new(base.join("mesh"));
+            fs::create_dir_all(dirs.config_dir()).unwrap();
+            fs::write(
+                dirs.config_dir().join("config.toml"),
+                format!(
+                    "album_dirs = [{:?}]\nexcluded_dirs = {:?}\ntheme = \"Default Light\"\n{}",
+                    root, excluded, settings
+                ),
+            )
+            .unwrap();
+            let config = MeshConfig::load(&dirs).unwrap();
+            Self {
+                _dir: dir,
+                root,
+                config,
+            }
+        }
+
+        fn png(&self, relative: &str, width: u32, height: u32) {
+            let path = self.root.join(relative);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            RgbImage::from_fn(width, height, |x, y| {
+                image::Rgb([(x * 31 + y * 17) as u8, (x * y) as u8, (x ^ y) as u8])
+            })
+            .save_with_format(path, image::ImageFormat::Png)
+            .unwrap();
+        }
+
+        fn file(&self, relative: &str, content: &str) {
+            let path = self.root.join(relative);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        /// 从相册中的 `starts` 开始扫描，返回相对于相册目录的路径
+        fn collect(&self, starts: &[&str]) -> Vec<String> {
+            let starts = starts.iter().map(|start| self.root.join(start)).collect();
+            let mut files: Vec<String> = collect_files(&ScanFilter::new(&self.config), starts)
+                .iter()
+                .map(|path| {
+                    path.strip_prefix(&self.root)
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect();
+            files.sort();
+            files
+        }
+    }
+
+    #[test]
+    fn skips_excluded_hidden_and_unknown_files() {
+        let album = Album::new(&["excluded"], "");
+        album.png("a.png", 8, 8);
+        album.png("no_extension", 8, 8);
+        album.png("excluded/b.png", 8, 8);
+        album.png(".hidden.png", 8, 8);
+        album.png(".cache/c.png", 8, 8);
+        album.file("notes.txt", "not a photo");
+        // 扩展名属于启用的格式时不检查内容，解码失败在生成缩略图时记录
+        album.file("fake.png", "not a photo either");
+
+        assert_eq!(album.collect(&[""]), ["a.png", "fake.png", "no_extension"]);
+        assert!(album.collect(&["excluded"]).is_empty());
+        assert!(album.collect(&["excluded/b.png"]).is_empty());
+        assert!(album.collect(&[".cache"]).is_empty());
+        // 相册目录之外的文件不会被收录
+        let outside = album.root.with_file_name("outside");
+        fs::create_dir_all(&outside).unwrap();
+        fs::copy(album.root.join("a.png"), outside.join("a.png")).unwrap();
+        let filter = ScanFilter::new(&album.config);
+        assert!(collect_files(&filter, vec![outside.clone(), outside.join("a.png")]).is_empty());
+    }
+
+    #[test]
+    fn shows_hidden_files_when_enabled() {
+        let album = Album::new(&[], "skip_hidden = false");
+        album.png(".hidden.png", 8, 8);
+        album.png(".cache/c.png", 8, 8);
+
+        assert_eq!(album.collect(&[""]), [".cache/c.png", ".hidden.png"]);
+    }
+
+    #[test]
+    fn applies_config_patterns_and_meshignore() {
+        let album = Album::new(&[], "ignore_patterns = [\"*.tmp.png\", \"/exports\"]");
+        album.png("a.png", 8, 8);
+        album.png("a.tmp.png", 8, 8);
+        album.png("exports/b.png", 8, 8);
+        // 以 `/` 开头的规则只匹配相册目录下的一层
+        album.png("trip/exports/c.png", 8, 8);
+        album.file("trip/.meshignore", "*.png\n!keep.png\nskipped/\n");
+        album.png("trip/drop.png", 8, 8);
+        album.png("trip/keep.png", 8, 8);
+        album.png("trip/skipped/d.png", 8, 8);
+        // 更深的 `.meshignore` 优先
+        album.file("trip/day1/.meshignore", "!*.png\n");
+        album.png("trip/day1/e.png", 8, 8);
+
+        assert_eq!(
+            album.collect(&[""]),
+            ["a.png", "trip/day1/e.png", "trip/keep.png"]
+        );
+
+        // 从子目录或单个文件开始扫描时也应用上级目录中的规则
+        assert_eq!(
+            album.collect(&["trip"]),
+            ["trip/day1/e.png", "trip/keep.png"]
+        );
+        assert!(album.collect(&["trip/drop.png"]).is_empty());
+        assert!(album.collect(&["trip/skipped"]).is_empty());
+        assert!(album.collect(&["trip/skipped/d.png"]).is_empty());
+        assert!(album.collect(&["exports"]).is_empty());
+        assert!(album.collect(&["a.tmp.png"]).is_empty());
+        assert_eq!(album.collect(&["trip/day1/e.png"]), ["trip/day1/e.png"]);
+    }
+
+    #[test]
+    fn applies_size_filters() {
+        let album = Album::new(&[], "min_file_size_kb = 1\nmin_width = 32\nmin_height = 24");
+        album.png("large.png", 64, 48);
+        album.png("narrow.png", 16, 480);
+        album.png("short.png", 640, 16);
+        album.png("tiny.png", 2, 2);
+        // 无法读取尺寸的文件留给缩略图生成时记录错误
+        album.file("broken.png", &"\u{89}PNG".repeat(512));
+
+        assert_eq!(album.collect(&[""]), ["broken.png", "large.png"]);
+    }
+
+    #[cfg(unix)]
+    fn symlink(album: &Album, target: impl AsRef<Path>, link: &str) {
+        let link = album.root.join(link);
+        fs::create_dir_all(link.parent().unwrap()).unwrap();
+        std::os::unix::fs::symlink(album.root.join(target), link).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_symlinks_unless_following() {
+        let album = Album::new(&[], "");
+        album.png("a.png", 8, 8);
+        album.png("../outside/b.png", 8, 8);
+        symlink(&album, "a.png", "link.png");
+        symlink(&album, "../outside", "linked");
+
+        assert_eq!(album.collect(&[""]), ["a.png"]);
+        assert!(album.collect(&["link.png"]).is_empty());
+        assert!(album.collect(&["linked/b.png"]).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn following_symlinks_returns_each_file_once() {
+        let album = Album::new(&[], "follow_symlinks = true");
+        album.png("a.png", 8, 8);
+        album.png("trip/c.png", 8, 8);
+        album.png("../outside/b.png", 8, 8);
+        symlink(&album, "a.png", "0-link.png");
+        symlink(&album, "trip", "0-alias");
+        symlink(&album, "../outside", "linked");
+        symlink(&album, "../outside/b.png", "linked-b.png");
+
+        // 相册内的文件以真实路径返回，相册之外的文件保留排序在前的链接路径
+        assert_eq!(
+            album.collect(&[""]),
+            ["a.png", "linked/b.png", "trip/c.png"]
+        );
+        assert_eq!(album.collect(&["0-link.png"]), ["a.png"]);
+        assert_eq!(album.collect(&["0-alias"]), ["trip/c.png"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn following_symlinks_stops_at_loops() {
+        let album = Album::new(&[], "follow_symlinks = true");
+        album.png("a.png", 8, 8);
+        album.png("trip/b.png", 8, 8);
+        symlink(&album, "", "trip/loop");
+        symlink(&album, "trip", "trip/self");
+
+        assert_eq!(album.collect(&[""]), ["a.png", "trip/b.png"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_into_excluded_or_ignored_dirs_are_skipped() {
+        let album = Album::new(&["excluded"], "follow_symlinks = true");
+        album.png("a.png", 8, 8);
+        album.png("excluded/b.png", 8, 8);
+        album.png(".hidden/c.png", 8, 8);
+        symlink(&album, "excluded", "shortcut");
+        symlink(&album, "excluded/b.png", "b.png");
+        symlink(&album, ".hidden/c.png", "c.png");
+
+        assert_eq!(album.collect(&[""]), ["a.png"]);
+        assert!(album.collect(&["shortcut"]).is_empty());
+    }
+
+    #[test]
+    fn respects_enabled_formats() {
+        let album = Album::new(&[], "formats = [\"jpeg\"]");
+        album.png("a.png", 8, 8);
+        album.png("no_extension", 8, 8);
+        album.png("renamed.jpg", 8, 8);
+
+        assert_eq!(album.collect(&[""]), ["renamed.jpg"]);
+    }
+}
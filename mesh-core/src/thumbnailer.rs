@@ -0,0 +1,759 @@
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use rayon::prelude::*;
+
+use crate::decode::{decode_for_thumbnail, resize_for_thumbnail};
+use crate::format::{MediaFormat, MediaType, encode_thumbnail};
+use crate::video::probe_video;
+use crate::worker::render_in_worker;
+use crate::{MeshCache, MeshThumbnail, PhotoRecord, ScanErrorKind, ScanErrorRecord, SourceStamp};
+
+/// 缩略图的最大高度
+pub const THUMBNAIL_MAX_HEIGHT: u32 = 300;
+
+/// 用于从其他线程取消正在运行的 [`ThumbnailJob`]
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// 每处理完一个文件发出一次的进度事件
+#[derive(Debug, Clone)]
+pub struct ThumbnailProgress {
+    pub done: usize,
+    pub total: usize,
+    /// 刚处理完的文件
+    pub current: PathBuf,
+    /// 根据已用时间估算的剩余时间
+    pub eta: Option<Duration>,
+}
+
+/// 任务结束时的统计
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThumbnailSummary {
+    pub total: usize,
+    pub generated: usize,
+    /// 缓存仍然有效，或此前已失败且未修改而跳过的文件
+    pub skipped: usize,
+    /// 本次处理失败并记录到 `scan_errors` 的文件
+    pub failed: usize,
+    pub cancelled: bool,
+}
+
+/// 单个文件生成缩略图失败
+#[derive(Debug)]
+pub struct ThumbnailError {
+    pub kind: ScanErrorKind,
+    pub message: String,
+}
+
+impl ThumbnailError {
+    pub(crate) fn new(kind: ScanErrorKind, message: impl std::fmt::Display) -> Self {
+        Self {
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    pub(crate) fn io(e: impl std::fmt::Display) -> Self {
+        Self::new(ScanErrorKind::Io, e)
+    }
+}
+
+impl From<image::ImageError> for ThumbnailError {
+    fn from(e: image::ImageError) -> Self {
+        let kind = match &e {
+            // 文件被截断时解码器读到 EOF，属于内容损坏
+            image::ImageError::IoError(io) if io.kind() == std::io::ErrorKind::UnexpectedEof => {
+                ScanErrorKind::Decode
+            }
+            image::ImageError::IoError(_) => ScanErrorKind::Io,
+            image::ImageError::Unsupported(_) => ScanErrorKind::Unsupported,
+            _ => ScanErrorKind::Decode,
+        };
+        Self::new(kind, e)
+    }
+}
+
+impl std::fmt::Display for ThumbnailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)
+    }
+}
+
+impl std::error::Error for ThumbnailError {}
+
+/// 单个文件的处理结果
+enum Outcome {
+    Generated,
+    Skipped,
+    Failed,
+}
+
+type ProgressCallback = Box<dyn Fn(&ThumbnailProgress) + Send + Sync>;
+
+/// 在有界线程池上为一批文件生成缩略图并写入 [`MeshCache`]
+pub struct ThumbnailJob {
+    files: Vec<PathBuf>,
+    threads: usize,
+    retry_failed: bool,
+    worker_timeout: Option<Duration>,
+    on_progress: Option<ProgressCallback>,
+}
+
+impl ThumbnailJob {
+    pub fn new(files: Vec<PathBuf>) -> Self {
+        let threads = std::thread::available_parallelism()
+            .map(NonZeroUsize::get)
+            .unwrap_or(1);
+
+        Self {
+            files,
+            threads,
+            retry_failed: false,
+            worker_timeout: None,
+            on_progress: None,
+        }
+    }
+
+    /// 线程池大小，默认为 CPU 核数
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// 是否重新处理此前失败且未修改的文件，默认跳过
+    pub fn retry_failed(mut self, retry_failed: bool) -> Self {
+        self.retry_failed = retry_failed;
+        self
+    }
+
+    /// 在子进程中解码，单个文件超过 `timeout` 时终止并记为失败
+    ///
+    /// 为 `None` 时在当前进程内解码（默认）。启用后程序需要在 `main` 开头调用
+    /// [`run_worker_if_requested`](crate::run_worker_if_requested)。
+    pub fn decode_in_worker(mut self, timeout: Option<Duration>) -> Self {
+        self.worker_timeout = timeout;
+        self
+    }
+
+    /// 每处理完一个文件调用一次，在调用 [`run`](Self::run) 的线程上按完成顺序调用，
+    /// 不会阻塞工作线程
+    pub fn on_progress(
+        mut self,
+        on_progress: impl Fn(&ThumbnailProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_progress = Some(Box::new(on_progress));
+        self
+    }
+
+    /// 阻塞运行直到全部完成或被取消
+    ///
+    /// 单个文件失败时记录到 `scan_errors` 表并继续处理其余文件。
+    pub fn run(
+        self,
+        cache: &MeshCache,
+        token: &CancellationToken,
+    ) -> anyhow::Result<ThumbnailSummary> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .thread_name(|i| format!("mesh-thumbnail-{}", i))
+            .build()
+            .context("创建线程池失败")?;
+
+        let total = self.files.len();
+        let started = Instant::now();
+        let generated = AtomicUsize::new(0);
+        let failed = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel::<&Path>();
+        let mut done = 0;
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                pool.install(|| {
+                    self.files
+                        .par_iter()
+                        .for_each_with(sender, |sender, src_path| {
+                            if token.is_cancelled() {
+                                return;
+                            }
+
+                            match self.process(src_path, cache) {
+                                Outcome::Generated => {
+                                    generated.fetch_add(1, Ordering::Relaxed);
+                                }
+                                Outcome::Failed => {
+                                    failed.fetch_add(1, Ordering::Relaxed);
+                                }
+                                Outcome::Skipped => {}
+                            }
+                            let _ = sender.send(src_path);
+                        })
+                })
+            });
+
+            // 所有发送端在工作线程结束时释放，进度事件按接收的顺序编号，done 总是递增
+            for current in receiver {
+                done += 1;
+                if let Some(on_progress) = &self.on_progress {
+                    on_progress(&ThumbnailProgress {
+                        done,
+                        total,
+                        current: current.to_path_buf(),
+                        eta: estimate_eta(started.elapsed(), done, total),
+                    });
+                }
+            }
+        });
+
+        let generated = generated.into_inner();
+        let failed = failed.into_inner();
+        Ok(ThumbnailSummary {
+            total,
+            generated,
+            skipped: done - generated - failed,
+            failed,
+            cancelled: token.is_cancelled() && done < total,
+        })
+    }
+
+    /// 处理单个文件，并在 `scan_errors` 表中记录或清除失败
+    fn process(&self, src_path: &Path, cache: &MeshCache) -> Outcome {
+        let database = cache.database();
+        let stamp = match SourceStamp::from_path(src_path) {
+            Ok(stamp) => stamp,
+            Err(e) => {
+                let error = ThumbnailError::io(e);
+                record_error(cache, src_path, &error, None);
+                return Outcome::Failed;
+            }
+        };
+
+        // 此前失败过且原图未修改，重试也只会再次失败
+        if !self.retry_failed
+            && database
+                .has_scan_error(src_path, stamp.size, stamp.modified)
+                .unwrap_or(false)
+        {
+            return Outcome::Skipped;
+        }
+
+        let result = detect_format(src_path).and_then(|format| match format.media_type() {
+            MediaType::Video => index_video(src_path, format, &stamp, cache),
+            MediaType::Photo => {
+                generate_thumbnail(src_path, format, &stamp, cache, self.worker_timeout)
+            }
+        });
+        match result {
+            Ok(generated) => {
+                if let Err(e) = database.clear_scan_error(src_path) {
+                    log::warn!("Failed to clear scan error: {}", e);
+                }
+                if generated {
+                    Outcome::Generated
+                } else {
+                    Outcome::Skipped
+                }
+            }
+            Err(error) => {
+                record_error(cache, src_path, &error, Some(&stamp));
+                Outcome::Failed
+            }
+        }
+    }
+}
+
+fn record_error(
+    cache: &MeshCache,
+    src_path: &Path,
+    error: &ThumbnailError,
+    stamp: Option<&SourceStamp>,
+) {
+    log::warn!("生成缩略图失败 {:?}: {}", src_path, error);
+
+    let record = ScanErrorRecord {
+        path: src_path.to_path_buf(),
+        kind: error.kind,
+        message: error.message.clone(),
+        retry_count: 0,
+        source_size: stamp.map(|s| s.size).unwrap_or_default(),
+        source_modified: stamp.map(|s| s.modified).unwrap_or_default(),
+        last_attempt: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default(),
+    };
+    if let Err(e) = cache.database().record_scan_error(&record) {
+        log::error!("Failed to record scan error: {}", e);
+    }
+}
+
+fn estimate_eta(elapsed: Duration, done: usize, total: usize) -> Option<Duration> {
+    if done == 0 {
+        return None;
+    }
+    Some(elapsed.mul_f64((total - done) as f64 / done as f64))
+}
+
+/// 按文件内容识别格式，扩展名与内容不符时记录警告
+///
+/// 内容无法识别时按扩展名处理，文件损坏的情况会在解码时报告。
+fn detect_format(src_path: &Path) -> Result<MediaFormat, ThumbnailError> {
+    let by_extension = MediaFormat::from_path(src_path);
+    let detected = MediaFormat::detect(src_path).map_err(ThumbnailError::io)?;
+
+    let format = match (detected, by_extension) {
+        (Some(detected), by_extension) => {
+            if by_extension != Some(detected) {
+                log::warn!("扩展名与内容不符 {:?}: 实际为 {}", src_path, detected);
+            }
+            detected
+        }
+        (None, Some(by_extension)) => by_extension,
+        (None, None) => {
+            return Err(ThumbnailError::new(
+                ScanErrorKind::Unsupported,
+                "无法识别的文件格式",
+            ));
+        }
+    };
+
+    if !format.is_decodable() {
+        let message = match by_extension {
+            Some(by_extension) if by_extension != format => {
+                format!(
+                    "实际内容为 {}（扩展名为 {}），无法解码",
+                    format, by_extension
+                )
+            }
+            _ => format!("当前构建无法解码 {}", format),
+        };
+        return Err(ThumbnailError::new(ScanErrorKind::Unsupported, message));
+    }
+    Ok(format)
+}
+
+/// 解码并编码好的缩略图，以及写入照片库所需的原图信息
+pub(crate) struct RenderedThumbnail {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) color_space: Option<String>,
+    /// 原图 EXIF 中的拍摄时间，UNIX 时间戳（秒）
+    pub(crate) captured_at: Option<i64>,
+    pub(crate) data: Vec<u8>,
+}
+
+/// 解码原图并编码缩略图，不读写缓存
+pub(crate) fn render_thumbnail(
+    src_path: &Path,
+    format: MediaFormat,
+) -> Result<RenderedThumbnail, ThumbnailError> {
+    // ① 以接近缩略图的分辨率解码原图，再缩放到目标尺寸（最大高度 300）
+    let decoded = decode_for_thumbnail(src_path, format, THUMBNAIL_MAX_HEIGHT)?;
+    let (width, height) = (decoded.width, decoded.height);
+    let captured_at = decoded.captured_at;
+    let (resized, color_space) = resize_for_thumbnail(decoded, THUMBNAIL_MAX_HEIGHT);
+
+    // ② 按格式注册表选择编码器，编码图片（JPEG 质量70，PNG 级别7）
+    let data = encode_thumbnail(&resized, format.info().thumbnail_encoder)?;
+
+    Ok(RenderedThumbnail {
+        width,
+        height,
+        color_space,
+        captured_at,
+        data,
+    })
+}
+
+/// 为单个文件生成缩略图，缓存仍然有效时跳过并返回 `false`
+///
+/// `worker_timeout` 不为 `None` 时在子进程中解码。
+fn generate_thumbnail(
+    src_path: &Path,
+    format: MediaFormat,
+    stamp: &SourceStamp,
+    cache: &MeshCache,
+    worker_timeout: Option<Duration>,
+) -> Result<bool, ThumbnailError> {
+    let thumbnail = cache.thumbnail();
+
+    // 原图未修改且缓存命中时跳过
+    let file_hash = MeshThumbnail::generate_file_hash(src_path);
+    if thumbnail.is_fresh(file_hash, stamp)
+        && cache
+            .database()
+            .contains_current_photo(src_path, stamp.size, stamp.modified_secs())
+            .map_err(ThumbnailError::io)?
+    {
+        return Ok(false);
+    }
+
+    let rendered = match worker_timeout {
+        Some(timeout) => render_in_worker(src_path, format, timeout)?,
+        None => render_thumbnail(src_path, format)?,
+    };
+
+    // ③ 写入缓存，原图修改后会被重新生成
+    thumbnail
+        .write_thumbnail(file_hash, stamp, &rendered.data)
+        .map_err(|e| ThumbnailError::io(format!("写入缩略图缓存失败: {}", e)))?;
+
+    // ④ 记录到照片库
+    let mut record = photo_record(src_path, format, file_hash, rendered.width, rendered.height)
+        .map_err(ThumbnailError::io)?;
+    record.color_space = rendered.color_space;
+    if let Some(captured_at) = rendered.captured_at {
+        record.created_at = captured_at;
+    }
+    cache
+        .database()
+        .upsert_photo(&record)
+        .map_err(|e| ThumbnailError::io(format!("写入照片记录失败: {}", e)))?;
+
+    log::debug!("生成缩略图: {:?}", src_path);
+    Ok(true)
+}
+
+/// 读取视频的容器元数据并写入照片库，记录仍然有效时跳过并返回 `false`
+///
+/// 视频暂不生成缩略图。
+fn index_video(
+    src_path: &Path,
+    format: MediaFormat,
+    stamp: &SourceStamp,
+    cache: &MeshCache,
+) -> Result<bool, ThumbnailError> {
+    if cache
+        .database()
+        .contains_current_photo(src_path, stamp.size, stamp.modified_secs())
+        .map_err(ThumbnailError::io)?
+    {
+        return Ok(false);
+    }
+
+    let metadata = probe_video(src_path)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => ThumbnailError::new(ScanErrorKind::Decode, e),
+            _ => ThumbnailError::io(e),
+        })?
+        .ok_or_else(|| ThumbnailError::new(ScanErrorKind::Unsupported, "无法识别的视频容器"))?;
+
+    let file_hash = MeshThumbnail::generate_file_hash(src_path);
+    let mut record = photo_record(src_path, format, file_hash, metadata.width, metadata.height)
+        .map_err(ThumbnailError::io)?;
+    record.duration_ms = Some(metadata.duration_ms);
+    record.codec = metadata.codec;
+    if let Some(created_at) = metadata.created_at {
+        record.created_at = created_at;
+    }
+
+    cache
+        .database()
+        .upsert_photo(&record)
+        .map_err(|e| ThumbnailError::io(format!("写入照片记录失败: {}", e)))?;
+
+    log::debug!("索引视频: {:?}", src_path);
+    Ok(true)
+}
+
+fn photo_record(
+    path: &Path,
+    format: MediaFormat,
+    file_hash: u128,
+    width: u32,
+    height: u32,
+) -> std::io::Result<PhotoRecord> {
+    let metadata = std::fs::metadata(path)?;
+    let unix_secs = |time: std::io::Result<SystemTime>| {
+        time.ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() as i64)
+    };
+    let modified_at = unix_secs(metadata.modified()).unwrap_or_default();
+
+    Ok(PhotoRecord {
+        path: path.to_path_buf(),
+        file_hash,
+        filename: path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        width,
+        height,
+        size: metadata.len(),
+        created_at: unix_secs(metadata.created()).unwrap_or(modified_at),
+        modified_at,
+        media_type: format.media_type(),
+        mime_type: format.info().mime_type.to_owned(),
+        color_space: None,
+        duration_ms: None,
+        codec: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use image::RgbImage;
+
+    use super::*;
+    use crate::{MeshDirs, ThumbnailBackend};
+
+    /// 在临时目录中打开照片库，返回目录、缓存与放置原图的相册目录
+    fn open_cache() -> (tempfile::TempDir, MeshCache, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MeshCache::new(
+            &MeshDirs::new(dir.path().join("mesh")),
+            ThumbnailBackend::Directory,
+        );
+        let album = dir.path().join("album");
+        std::fs::create_dir_all(&album).unwrap();
+        (dir, cache, album)
+    }
+
+    fn png(album: &Path, name: &str) -> PathBuf {
+        let path = album.join(name);
+        RgbImage::from_fn(16, 12, |x, y| image::Rgb([x as u8 * 16, y as u8 * 20, 128]))
+            .save_with_format(&path, image::ImageFormat::Png)
+            .unwrap();
+        path
+    }
+
+    fn pngs(album: &Path, count: usize) -> Vec<PathBuf> {
+        (0..count)
+            .map(|i| png(album, &format!("{:03}.png", i)))
+            .collect()
+    }
+
+    /// 扩展名为 PNG 但内容无法解码的文件
+    fn broken(album: &Path, name: &str) -> PathBuf {
+        let path = album.join(name);
+        std::fs::write(&path, "not a png").unwrap();
+        path
+    }
+
+    fn assert_totals(summary: &ThumbnailSummary, done: usize) {
+        assert_eq!(
+            summary.generated + summary.skipped + summary.failed,
+            done,
+            "{:?}",
+            summary
+        );
+    }
+
+    #[test]
+    fn progress_is_monotonic_and_ends_at_total() {
+        let (_dir, cache, album) = open_cache();
+        let mut files = pngs(&album, 12);
+        files.push(broken(&album, "broken.png"));
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let summary = ThumbnailJob::new(files.clone())
+            .threads(4)
+            .on_progress({
+                let events = events.clone();
+                move |progress| {
+                    events.lock().unwrap().push((
+                        progress.done,
+                        progress.total,
+                        progress.current.clone(),
+                    ))
+                }
+            })
+            .run(&cache, &CancellationToken::new())
+            .unwrap();
+
+        let events = events.lock().unwrap();
+        let done: Vec<usize> = events.iter().map(|(done, _, _)| *done).collect();
+        assert_eq!(done, (1..=files.len()).collect::<Vec<_>>());
+        assert!(events.iter().all(|(_, total, _)| *total == files.len()));
+        // 每个文件恰好报告一次
+        let mut current: Vec<PathBuf> = events.iter().map(|(_, _, path)| path.clone()).collect();
+        current.sort();
+        assert_eq!(current, files);
+
+        assert_eq!(summary.total, files.len());
+        assert_eq!((summary.generated, summary.failed), (12, 1));
+        assert!(!summary.cancelled);
+        assert_totals(&summary, files.len());
+    }
+
+    #[test]
+    fn second_run_skips_cached_and_failed_files() {
+        let (_dir, cache, album) = open_cache();
+        let mut files = pngs(&album, 4);
+        files.push(broken(&album, "broken.png"));
+        let token = CancellationToken::new();
+
+        ThumbnailJob::new(files.clone())
+            .run(&cache, &token)
+            .unwrap();
+        let summary = ThumbnailJob::new(files.clone())
+            .run(&cache, &token)
+            .unwrap();
+        assert_eq!(
+            (summary.generated, summary.skipped, summary.failed),
+            (0, 5, 0)
+        );
+        assert_totals(&summary, files.len());
+    }
+
+    #[test]
+    fn cancelled_before_start_processes_nothing() {
+        let (_dir, cache, album) = open_cache();
+        let files = pngs(&album, 4);
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let summary = ThumbnailJob::new(files)
+            .on_progress(|_| panic!("no file should be processed"))
+            .run(&cache, &token)
+            .unwrap();
+        assert!(summary.cancelled);
+        assert_totals(&summary, 0);
+        assert_eq!(cache.thumbnail().stats().count, 0);
+    }
+
+    #[test]
+    fn cancellation_stops_the_job() {
+        let (_dir, cache, album) = open_cache();
+        let files = pngs(&album, 64);
+        let token = CancellationToken::new();
+        let done = Arc::new(AtomicUsize::new(0));
+
+        let summary = ThumbnailJob::new(files.clone())
+            .threads(1)
+            .on_progress({
+                let token = token.clone();
+                let done = done.clone();
+                move |progress| {
+                    done.store(progress.done, Ordering::Relaxed);
+                    token.cancel();
+                }
+            })
+            .run(&cache, &token)
+            .unwrap();
+
+        assert!(summary.cancelled);
+        assert_eq!(summary.total, files.len());
+        // 已经开始处理的文件仍会完成并报告进度
+        let done = done.load(Ordering::Relaxed);
+        assert!(done < files.len(), "processed {} files", done);
+        assert_totals(&summary, done);
+        assert_eq!(cache.thumbnail().stats().count, summary.generated);
+    }
+
+    fn run(cache: &MeshCache, files: &[PathBuf], retry_failed: bool) -> ThumbnailSummary {
+        ThumbnailJob::new(files.to_vec())
+            .retry_failed(retry_failed)
+            .run(cache, &CancellationToken::new())
+            .unwrap()
+    }
+
+    fn scan_error(cache: &MeshCache, path: &Path) -> Option<ScanErrorRecord> {
+        cache
+            .database()
+            .scan_errors()
+            .unwrap()
+            .into_iter()
+            .find(|error| error.path == path)
+    }
+
+    #[test]
+    fn failure_is_recorded_and_the_batch_continues() {
+        let (_dir, cache, album) = open_cache();
+        let broken = broken(&album, "a.png");
+        let good = png(&album, "b.png");
+
+        let summary = run(&cache, &[broken.clone(), good.clone()], false);
+        assert_eq!((summary.generated, summary.failed), (1, 1));
+
+        let error = scan_error(&cache, &broken).unwrap();
+        assert_eq!(error.kind, ScanErrorKind::Decode);
+        assert_eq!(error.retry_count, 0);
+        assert_eq!(error.source_size, "not a png".len() as u64);
+        assert!(scan_error(&cache, &good).is_none());
+    }
+
+    #[test]
+    fn unchanged_failure_is_skipped_unless_retrying() {
+        let (_dir, cache, album) = open_cache();
+        let broken = broken(&album, "a.png");
+        let files = [broken.clone()];
+        run(&cache, &files, false);
+
+        let summary = run(&cache, &files, false);
+        assert_eq!((summary.skipped, summary.failed), (1, 0));
+        assert_eq!(scan_error(&cache, &broken).unwrap().retry_count, 0);
+
+        let summary = run(&cache, &files, true);
+        assert_eq!((summary.skipped, summary.failed), (0, 1));
+        assert_eq!(scan_error(&cache, &broken).unwrap().retry_count, 1);
+    }
+
+    #[test]
+    fn changed_failure_is_retried() {
+        let (_dir, cache, album) = open_cache();
+        let broken = broken(&album, "a.png");
+        let files = [broken.clone()];
+        run(&cache, &files, false);
+
+        // 大小改变
+        std::fs::write(&broken, "still not a png").unwrap();
+        let summary = run(&cache, &files, false);
+        assert_eq!(summary.failed, 1);
+        let error = scan_error(&cache, &broken).unwrap();
+        assert_eq!(error.retry_count, 1);
+        assert_eq!(error.source_size, "still not a png".len() as u64);
+
+        // 大小不变，只有修改时间改变
+        let modified = std::fs::metadata(&broken).unwrap().modified().unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&broken)
+            .unwrap()
+            .set_modified(modified + Duration::from_secs(60))
+            .unwrap();
+        let summary = run(&cache, &files, false);
+        assert_eq!(summary.failed, 1);
+        assert_eq!(scan_error(&cache, &broken).unwrap().retry_count, 2);
+    }
+
+    #[test]
+    fn success_clears_the_error() {
+        let (_dir, cache, album) = open_cache();
+        let broken = broken(&album, "a.png");
+        run(&cache, std::slice::from_ref(&broken), false);
+        assert!(scan_error(&cache, &broken).is_some());
+
+        // 修复后的文件被重新处理，失败记录随之清除
+        png(&album, "a.png");
+        let summary = run(&cache, std::slice::from_ref(&broken), false);
+        assert_eq!((summary.generated, summary.failed), (1, 0));
+        assert!(scan_error(&cache, &broken).is_none());
+    }
+}
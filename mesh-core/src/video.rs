@@ -0,0 +1,836 @@
+//! 从视频容器中读取时长、分辨率、编码与创建时间
+//!
+//! 只解析容器结构，不解码视频帧：
+//! - MP4 / MOV：ISOBMFF，读取 `moov` 中的 `mvhd`、`tkhd`、`hdlr` 与 `stsd`
+//! - MKV / WebM：EBML，读取 `Segment` 中的 `Info` 与 `Tracks`
+//! - AVI：RIFF，读取 `hdrl` 中的 `avih` 与视频流的 `strf`
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::container::{boxes, next_box, read_up_to};
+
+/// 读入内存解析的头部结构的最大长度
+const MAX_HEADER_LEN: u64 = 64 * 1024 * 1024;
+/// ISOBMFF 时间的纪元（1904-01-01）与 UNIX 纪元相差的秒数
+const MP4_EPOCH_OFFSET: i64 = 2_082_844_800;
+/// Matroska 时间的纪元（2001-01-01）与 UNIX 纪元相差的秒数
+const MATROSKA_EPOCH_OFFSET: i64 = 978_307_200;
+
+const EBML_HEADER: u32 = 0x1a45_dfa3;
+const EBML_SEGMENT: u32 = 0x1853_8067;
+const EBML_INFO: u32 = 0x1549_a966;
+const EBML_TRACKS: u32 = 0x1654_ae6b;
+const EBML_CLUSTER: u32 = 0x1f43_b675;
+const EBML_TIMECODE_SCALE: u32 = 0x2a_d7b1;
+const EBML_DURATION: u32 = 0x4489;
+const EBML_DATE_UTC: u32 = 0x4461;
+const EBML_TRACK_ENTRY: u32 = 0xae;
+const EBML_TRACK_TYPE: u32 = 0x83;
+const EBML_CODEC_ID: u32 = 0x86;
+const EBML_VIDEO: u32 = 0xe0;
+const EBML_PIXEL_WIDTH: u32 = 0xb0;
+const EBML_PIXEL_HEIGHT: u32 = 0xba;
+
+/// 视频的容器元数据
+#[derive(Debug, Clone, Default)]
+pub struct VideoMetadata {
+    /// 显示宽度，已按旋转矩阵调整
+    pub width: u32,
+    pub height: u32,
+    pub duration_ms: u64,
+    /// 视频编码，常见编码归一化为 `h264`、`hevc`、`av1` 等，其余保留容器中的标识
+    pub codec: Option<String>,
+    /// 容器记录的创建时间，UNIX 时间戳（秒）
+    pub created_at: Option<i64>,
+}
+
+/// 读取视频文件的容器元数据，无法识别的容器返回 `None`
+pub fn probe_video(path: &Path) -> io::Result<Option<VideoMetadata>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 12];
+    let read = read_up_to(&mut reader, &mut magic)?;
+    let magic = &magic[..read];
+    reader.seek(SeekFrom::Start(0))?;
+
+    let isobmff: [&[u8]; 5] = [b"ftyp", b"moov", b"mdat", b"wide", b"free"];
+    if magic
+        .get(4..8)
+        .is_some_and(|box_type| isobmff.contains(&box_type))
+    {
+        probe_mp4(&mut reader)
+    } else if magic.starts_with(&EBML_HEADER.to_be_bytes()) {
+        probe_matroska(&mut reader)
+    } else if magic.starts_with(b"RIFF") && magic.get(8..12) == Some(b"AVI ") {
+        probe_avi(&mut reader)
+    } else {
+        Ok(None)
+    }
+}
+
+fn probe_mp4<R: Read + Seek>(reader: &mut R) -> io::Result<Option<VideoMetadata>> {
+    while let Some((box_type, body_offset, body_len)) = next_box(reader)? {
+        if &box_type == b"moov" && body_len <= MAX_HEADER_LEN {
+            let mut moov = vec![0u8; body_len as usize];
+            reader.read_exact(&mut moov)?;
+            return Ok(Some(parse_moov(&moov)));
+        }
+        reader.seek(SeekFrom::Start(body_offset.saturating_add(body_len)))?;
+    }
+    Ok(None)
+}
+
+fn parse_moov(moov: &[u8]) -> VideoMetadata {
+    let mut metadata = VideoMetadata::default();
+
+    if let Some(mvhd) = child(moov, b"mvhd") {
+        let mut r = SliceReader::new(mvhd);
+        let version = r.u8().unwrap_or(0);
+        r.skip(3);
+        let (created, timescale, duration) = if version == 1 {
+            let created = r.u64();
+            r.skip(8);
+            (created, r.u32(), r.u64())
+        } else {
+            let created = r.u32().map(u64::from);
+            r.skip(4);
+            (created, r.u32(), r.u32().map(u64::from))
+        };
+
+        // 创建时间直接取自文件，超出范围的值视为缺失
+        metadata.created_at = created
+            .filter(|&t| t > 0)
+            .and_then(|t| i64::try_from(t).ok())
+            .and_then(|t| t.checked_sub(MP4_EPOCH_OFFSET));
+        if let (Some(timescale), Some(duration)) = (timescale, duration)
+            && timescale > 0
+        {
+            metadata.duration_ms = duration.saturating_mul(1000) / timescale as u64;
+        }
+    }
+
+    // 取第一条视频轨道
+    for trak in children(moov, b"trak") {
+        let Some(mdia) = child(trak, b"mdia") else {
+            continue;
+        };
+        let is_video = child(mdia, b"hdlr").and_then(|hdlr| hdlr.get(8..12)) == Some(b"vide");
+        if !is_video {
+            continue;
+        }
+
+        if let Some((width, height)) = child(trak, b"tkhd").and_then(tkhd_dimensions) {
+            metadata.width = width;
+            metadata.height = height;
+        }
+        metadata.codec = child(mdia, b"minf")
+            .and_then(|minf| child(minf, b"stbl"))
+            .and_then(|stbl| child(stbl, b"stsd"))
+            // 版本与标志、条目数之后是第一个采样描述 box
+            .and_then(|stsd| stsd.get(12..16))
+            .map(|fourcc| normalize_codec(&String::from_utf8_lossy(fourcc)));
+        break;
+    }
+
+    metadata
+}
+
+/// 读取 `tkhd` 中的宽高，旋转 90° 或 270° 时交换
+fn tkhd_dimensions(tkhd: &[u8]) -> Option<(u32, u32)> {
+    let mut r = SliceReader::new(tkhd);
+    let version = r.u8()?;
+    r.skip(3);
+    // 创建时间、修改时间、轨道 ID、保留、时长
+    r.skip(if version == 1 {
+        8 + 8 + 4 + 4 + 8
+    } else {
+        4 * 5
+    });
+    // 保留、层、备用组、音量、保留
+    r.skip(8 + 2 + 2 + 2 + 2);
+    let a = r.u32()? as i32;
+    let b = r.u32()? as i32;
+    r.skip(4 * 7);
+    // 16.16 定点数
+    let width = r.u32()? >> 16;
+    let height = r.u32()? >> 16;
+
+    if a == 0 && b != 0 {
+        Some((height, width))
+    } else {
+        Some((width, height))
+    }
+}
+
+fn probe_matroska<R: Read + Seek>(reader: &mut R) -> io::Result<Option<VideoMetadata>> {
+    // EBML 头
+    let Some((EBML_HEADER, Some(len))) = read_element_header(reader)? else {
+        return Ok(None);
+    };
+    reader.seek(SeekFrom::Current(len as i64))?;
+
+    let Some((EBML_SEGMENT, segment_len)) = read_element_header(reader)? else {
+        return Ok(None);
+    };
+    let segment_end = segment_len.map(|len| reader.stream_position().map(|p| p + len));
+    let segment_end = segment_end.transpose()?;
+
+    let mut metadata = VideoMetadata::default();
+    let mut timecode_scale = 1_000_000u64;
+    let mut duration = None;
+    let (mut has_info, mut has_tracks) = (false, false);
+
+    while !(has_info && has_tracks) {
+        if segment_end.is_some_and(|end| reader.stream_position().is_ok_and(|p| p >= end)) {
+            break;
+        }
+        let Some((id, len)) = read_element_header(reader)? else {
+            break;
+        };
+        // 未知长度的元素（通常是直播流中的 Cluster）无法跳过
+        let Some(len) = len else {
+            break;
+        };
+
+        match id {
+            EBML_INFO | EBML_TRACKS if len <= MAX_HEADER_LEN => {
+                let mut body = vec![0u8; len as usize];
+                reader.read_exact(&mut body)?;
+                if id == EBML_INFO {
+                    has_info = true;
+                    for (id, data) in ebml_elements(&body) {
+                        match id {
+                            EBML_TIMECODE_SCALE => timecode_scale = ebml_uint(data),
+                            EBML_DURATION => duration = ebml_float(data),
+                            EBML_DATE_UTC => {
+                                let ns = ebml_uint(data) as i64;
+                                metadata.created_at =
+                                    Some(ns.div_euclid(1_000_000_000) + MATROSKA_EPOCH_OFFSET);
+                            }
+                            _ => {}
+                        }
+                    }
+                } else {
+                    has_tracks = true;
+                    parse_matroska_tracks(&body, &mut metadata);
+                }
+            }
+            // 通常 Info 与 Tracks 都位于第一个 Cluster 之前
+            EBML_CLUSTER => break,
+            _ => {
+                reader.seek(SeekFrom::Current(len as i64))?;
+            }
+        }
+    }
+
+    if let Some(duration) = duration {
+        metadata.duration_ms = (duration * timecode_scale as f64 / 1_000_000.0) as u64;
+    }
+    Ok((has_info || has_tracks).then_some(metadata))
+}
+
+fn parse_matroska_tracks(tracks: &[u8], metadata: &mut VideoMetadata) {
+    for (id, entry) in ebml_elements(tracks) {
+        if id != EBML_TRACK_ENTRY {
+            continue;
+        }
+
+        let mut is_video = false;
+        let mut codec = None;
+        let (mut width, mut height) = (0, 0);
+        for (id, data) in ebml_elements(entry) {
+            match id {
+                EBML_TRACK_TYPE => is_video = ebml_uint(data) == 1,
+                EBML_CODEC_ID => {
+                    codec = Some(normalize_codec(
+                        String::from_utf8_lossy(data).trim_end_matches('\0'),
+                    ))
+                }
+                EBML_VIDEO => {
+                    for (id, data) in ebml_elements(data) {
+                        match id {
+                            EBML_PIXEL_WIDTH => width = ebml_uint(data) as u32,
+                            EBML_PIXEL_HEIGHT => height = ebml_uint(data) as u32,
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if is_video {
+            metadata.width = width;
+            metadata.height = height;
+            metadata.codec = codec;
+            return;
+        }
+    }
+}
+
+fn probe_avi<R: Read + Seek>(reader: &mut R) -> io::Result<Option<VideoMetadata>> {
+    // RIFF 头之后第一个 LIST 应为 hdrl
+    reader.seek(SeekFrom::Start(12))?;
+    let mut header = [0u8; 12];
+    if read_up_to(reader, &mut header)? < 12
+        || &header[0..4] != b"LIST"
+        || &header[8..12] != b"hdrl"
+    {
+        return Ok(None);
+    }
+    let len = u32::from_le_bytes(header[4..8].try_into().unwrap()) as u64;
+    if !(4..=MAX_HEADER_LEN).contains(&len) {
+        return Ok(None);
+    }
+    let mut hdrl = vec![0u8; len as usize - 4];
+    reader.read_exact(&mut hdrl)?;
+
+    let mut metadata = VideoMetadata::default();
+    for (id, data) in riff_chunks(&hdrl) {
+        match &id {
+            b"avih" => {
+                let mut r = SliceReader::new(data);
+                let micros_per_frame = r.u32_le().unwrap_or(0) as u64;
+                r.skip(12);
+                let total_frames = r.u32_le().unwrap_or(0) as u64;
+                r.skip(12);
+                metadata.width = r.u32_le().unwrap_or(0);
+                metadata.height = r.u32_le().unwrap_or(0);
+                metadata.duration_ms = micros_per_frame * total_frames / 1000;
+            }
+            b"LIST" if data.starts_with(b"strl") => {
+                let strl: Vec<_> = riff_chunks(&data[4..]).collect();
+                let is_video = strl
+                    .iter()
+                    .any(|(id, data)| id == b"strh" && data.starts_with(b"vids"));
+                // BITMAPINFOHEADER 中的 biCompression
+                let compression = strl
+                    .iter()
+                    .find(|(id, _)| id == b"strf")
+                    .and_then(|(_, data)| data.get(16..20));
+                if is_video && let Some(fourcc) = compression {
+                    metadata.codec = Some(normalize_codec(&String::from_utf8_lossy(fourcc)));
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(Some(metadata))
+}
+
+/// 把容器中的编码标识归一化为通用名称
+fn normalize_codec(id: &str) -> String {
+    let id = id.trim();
+    let name = match id.to_ascii_lowercase().as_str() {
+        "avc1" | "avc3" | "h264" | "x264" | "v_mpeg4/iso/avc" => "h264",
+        "hvc1" | "hev1" | "hevc" | "h265" | "v_mpegh/iso/hevc" => "hevc",
+        "av01" | "v_av1" => "av1",
+        "vp09" | "v_vp9" => "vp9",
+        "vp08" | "v_vp8" => "vp8",
+        "mp4v" | "xvid" | "divx" | "dx50" | "fmp4" | "v_mpeg4/iso/sp" | "v_mpeg4/iso/asp" => {
+            "mpeg4"
+        }
+        "mjpg" | "mjpa" | "jpeg" | "v_mjpeg" => "mjpeg",
+        "apch" | "apcn" | "apcs" | "apco" | "ap4h" | "ap4x" | "v_prores" => "prores",
+        _ => return id.to_owned(),
+    };
+    name.to_owned()
+}
+
+fn children<'a>(data: &'a [u8], box_type: &'a [u8; 4]) -> impl Iterator<Item = &'a [u8]> {
+    boxes(data)
+        .filter(move |(t, _)| t == box_type)
+        .map(|(_, body)| body)
+}
+
+fn child<'a>(data: &'a [u8], box_type: &'a [u8; 4]) -> Option<&'a [u8]> {
+    children(data, box_type).next()
+}
+
+/// 读取 EBML 元素的 ID 与长度，长度未知时为 `None`
+fn read_element_header<R: Read>(reader: &mut R) -> io::Result<Option<(u32, Option<u64>)>> {
+    let Some((id, _)) = read_vint(reader, true)? else {
+        return Ok(None);
+    };
+    let Some((len, width)) = read_vint(reader, false)? else {
+        return Ok(None);
+    };
+    // 所有数据位为 1 表示未知长度
+    let unknown = len == (1u64 << (7 * width)) - 1;
+    Ok(Some((id as u32, (!unknown).then_some(len))))
+}
+
+/// 读取 EBML 变长整数，`keep_marker` 为真时保留长度标记位（用于元素 ID）
+fn read_vint<R: Read>(reader: &mut R, keep_marker: bool) -> io::Result<Option<(u64, u32)>> {
+    let mut first = [0u8; 1];
+    if read_up_to(reader, &mut first)? < 1 || first[0] == 0 {
+        return Ok(None);
+    }
+    let width = first[0].leading_zeros() + 1;
+    // 元素 ID 最长 4 字节
+    if keep_marker && width > 4 {
+        return Ok(None);
+    }
+
+    let mut value = if keep_marker {
+        first[0] as u64
+    } else {
+        (first[0] as u64) & ((1 << (8 - width)) - 1)
+    };
+    let mut rest = [0u8; 7];
+    let rest = &mut rest[..width as usize - 1];
+    if read_up_to(reader, rest)? < rest.len() {
+        return Ok(None);
+    }
+    for byte in rest {
+        value = (value << 8) | *byte as u64;
+    }
+    Ok(Some((value, width)))
+}
+
+/// 遍历内存中的 EBML 元素，返回 ID 与数据
+fn ebml_elements(mut data: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+    std::iter::from_fn(move || {
+        let mut cursor = io::Cursor::new(data);
+        let (id, len) = read_element_header(&mut cursor).ok()??;
+        let start = cursor.position() as usize;
+        let end = start.checked_add(len? as usize)?;
+        let body = data.get(start..end)?;
+        data = &data[end..];
+        Some((id, body))
+    })
+}
+
+fn ebml_uint(data: &[u8]) -> u64 {
+    data.iter()
+        .take(8)
+        .fold(0, |value, byte| (value << 8) | *byte as u64)
+}
+
+fn ebml_float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        4 => Some(f32::from_be_bytes(data.try_into().ok()?) as f64),
+        8 => Some(f64::from_be_bytes(data.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+/// 遍历内存中的 RIFF 块，返回 ID 与数据
+fn riff_chunks(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let id: [u8; 4] = data.get(0..4)?.try_into().ok()?;
+        let len = u32::from_le_bytes(data.get(4..8)?.try_into().ok()?) as usize;
+        let body = data.get(8..8usize.checked_add(len)?)?;
+        // 块按 2 字节对齐
+        let next = (8 + len + (len & 1)).min(data.len());
+        data = &data[next..];
+        Some((id, body))
+    })
+}
+
+/// 按大端序读取内存中的字段
+struct SliceReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn skip(&mut self, len: usize) {
+        self.position = self.position.saturating_add(len);
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position.checked_add(N)?)?
+            .try_into()
+            .ok()?;
+        self.position += N;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take::<1>().map(|b| b[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_be_bytes)
+    }
+
+    fn u32_le(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take().map(u64::from_be_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn iso_box(box_type: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = ((8 + body.len()) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn mvhd_v0(created: u32, timescale: u32, duration: u32) -> Vec<u8> {
+        let mut body = vec![0, 0, 0, 0];
+        body.extend_from_slice(&created.to_be_bytes());
+        body.extend_from_slice(&created.to_be_bytes());
+        body.extend_from_slice(&timescale.to_be_bytes());
+        body.extend_from_slice(&duration.to_be_bytes());
+        body.resize(100, 0);
+        iso_box(b"mvhd", &body)
+    }
+
+    fn mvhd_v1(created: u64, timescale: u32, duration: u64) -> Vec<u8> {
+        let mut body = vec![1, 0, 0, 0];
+        body.extend_from_slice(&created.to_be_bytes());
+        body.extend_from_slice(&created.to_be_bytes());
+        body.extend_from_slice(&timescale.to_be_bytes());
+        body.extend_from_slice(&duration.to_be_bytes());
+        body.resize(112, 0);
+        iso_box(b"mvhd", &body)
+    }
+
+    /// 视频轨道，`rotated` 为真时矩阵旋转 90°
+    fn video_trak(width: u32, height: u32, rotated: bool, fourcc: &[u8; 4]) -> Vec<u8> {
+        let mut tkhd = vec![0u8; 4 + 4 * 5 + 8 + 2 + 2 + 2 + 2];
+        let (a, b): (u32, u32) = if rotated {
+            (0, 0x0001_0000)
+        } else {
+            (0x0001_0000, 0)
+        };
+        tkhd.extend_from_slice(&a.to_be_bytes());
+        tkhd.extend_from_slice(&b.to_be_bytes());
+        tkhd.extend_from_slice(&[0; 4 * 7]);
+        tkhd.extend_from_slice(&(width << 16).to_be_bytes());
+        tkhd.extend_from_slice(&(height << 16).to_be_bytes());
+
+        let mut hdlr = vec![0u8; 8];
+        hdlr.extend_from_slice(b"vide");
+        hdlr.resize(24, 0);
+
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend(iso_box(fourcc, &[0; 78]));
+        let stbl = iso_box(b"stbl", &iso_box(b"stsd", &stsd));
+        let minf = iso_box(b"minf", &stbl);
+
+        let mut mdia = iso_box(b"hdlr", &hdlr);
+        mdia.extend(minf);
+        let mut trak = iso_box(b"tkhd", &tkhd);
+        trak.extend(iso_box(b"mdia", &mdia));
+        iso_box(b"trak", &trak)
+    }
+
+    fn sound_trak() -> Vec<u8> {
+        let mut hdlr = vec![0u8; 8];
+        hdlr.extend_from_slice(b"soun");
+        hdlr.resize(24, 0);
+        iso_box(b"trak", &iso_box(b"mdia", &iso_box(b"hdlr", &hdlr)))
+    }
+
+    fn mp4(mvhd: Vec<u8>, trak: Vec<u8>) -> Vec<u8> {
+        let mut moov = mvhd;
+        moov.extend(sound_trak());
+        moov.extend(trak);
+
+        let mut data = iso_box(b"ftyp", b"isom\0\0\x02\0isomiso2mp41");
+        data.extend(iso_box(b"mdat", &[0; 32]));
+        data.extend(iso_box(b"moov", &moov));
+        data
+    }
+
+    #[test]
+    fn mp4_reads_mvhd_and_first_video_track() {
+        // 2024-01-01 00:00:00 UTC
+        let created = (1_704_067_200 + MP4_EPOCH_OFFSET) as u32;
+        let data = mp4(
+            mvhd_v0(created, 600, 600 * 90 + 300),
+            video_trak(1920, 1080, false, b"avc1"),
+        );
+
+        let metadata = probe_mp4(&mut Cursor::new(data)).unwrap().unwrap();
+        assert_eq!((metadata.width, metadata.height), (1920, 1080));
+        assert_eq!(metadata.duration_ms, 90_500);
+        assert_eq!(metadata.codec.as_deref(), Some("h264"));
+        assert_eq!(metadata.created_at, Some(1_704_067_200));
+    }
+
+    #[test]
+    fn mp4_version_1_and_rotation() {
+        let created = (1_704_067_200 + MP4_EPOCH_OFFSET) as u64;
+        let data = mp4(
+            mvhd_v1(created, 1000, 12_345),
+            video_trak(3840, 2160, true, b"hvc1"),
+        );
+
+        let metadata = probe_mp4(&mut Cursor::new(data)).unwrap().unwrap();
+        // 竖拍视频交换宽高
+        assert_eq!((metadata.width, metadata.height), (2160, 3840));
+        assert_eq!(metadata.duration_ms, 12_345);
+        assert_eq!(metadata.codec.as_deref(), Some("hevc"));
+        assert_eq!(metadata.created_at, Some(1_704_067_200));
+    }
+
+    #[test]
+    fn mp4_out_of_range_values_do_not_overflow() {
+        for created in [u64::MAX, i64::MAX as u64 + 1, 0] {
+            let data = mp4(
+                mvhd_v1(created, 1, u64::MAX),
+                video_trak(1, 1, false, b"av01"),
+            );
+            let metadata = probe_mp4(&mut Cursor::new(data)).unwrap().unwrap();
+            assert_eq!(metadata.created_at, None);
+            assert_eq!(metadata.duration_ms, u64::MAX);
+        }
+
+        // 时间刻度为 0 时不计算时长
+        let data = mp4(mvhd_v0(0, 0, 100), video_trak(1, 1, false, b"av01"));
+        let metadata = probe_mp4(&mut Cursor::new(data)).unwrap().unwrap();
+        assert_eq!(metadata.duration_ms, 0);
+    }
+
+    #[test]
+    fn mp4_truncated_at_every_length() {
+        let data = mp4(mvhd_v0(1, 600, 600), video_trak(1920, 1080, false, b"avc1"));
+        let moov_body = data.windows(4).position(|w| w == b"moov").unwrap() + 4;
+        for len in 0..data.len() {
+            let result = probe_mp4(&mut Cursor::new(&data[..len]));
+            // moov 的头部之前截断时找不到元数据，moov 的数据截断时读取失败
+            if len < moov_body {
+                assert!(result.unwrap().is_none(), "{}", len);
+            } else {
+                assert!(!matches!(result, Ok(Some(_))), "{}", len);
+            }
+        }
+    }
+
+    #[test]
+    fn mp4_without_moov() {
+        let mut data = iso_box(b"ftyp", b"isom\0\0\x02\0");
+        data.extend(iso_box(b"mdat", &[0; 16]));
+        assert!(probe_mp4(&mut Cursor::new(data)).unwrap().is_none());
+
+        // box 长度小于头部长度
+        let mut data = iso_box(b"ftyp", b"isom\0\0\x02\0");
+        data.extend_from_slice(&4u32.to_be_bytes());
+        data.extend_from_slice(b"moov");
+        assert!(probe_mp4(&mut Cursor::new(data)).unwrap().is_none());
+    }
+
+    /// EBML 元素，长度统一用 8 字节变长整数表示
+    fn ebml(id: u32, body: &[u8]) -> Vec<u8> {
+        let mut data: Vec<u8> = id
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|&b| b == 0)
+            .collect();
+        data.push(0x01);
+        data.extend_from_slice(&(body.len() as u64).to_be_bytes()[1..]);
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn ebml_uint_element(id: u32, value: u64) -> Vec<u8> {
+        ebml(id, &value.to_be_bytes())
+    }
+
+    fn matroska(segment_len: Option<u64>) -> Vec<u8> {
+        // 2024-01-01 00:00:00 UTC
+        let date_ns = (1_704_067_200 - MATROSKA_EPOCH_OFFSET) as u64 * 1_000_000_000;
+        let mut info = ebml_uint_element(EBML_TIMECODE_SCALE, 1_000_000);
+        info.extend(ebml(EBML_DURATION, &61_500.0f64.to_be_bytes()));
+        info.extend(ebml_uint_element(EBML_DATE_UTC, date_ns));
+
+        let mut audio = ebml_uint_element(EBML_TRACK_TYPE, 2);
+        audio.extend(ebml(EBML_CODEC_ID, b"A_OPUS"));
+        let mut video = ebml_uint_element(EBML_TRACK_TYPE, 1);
+        video.extend(ebml(EBML_CODEC_ID, b"V_VP9\0"));
+        let mut pixels = ebml_uint_element(EBML_PIXEL_WIDTH, 1280);
+        pixels.extend(ebml_uint_element(EBML_PIXEL_HEIGHT, 720));
+        video.extend(ebml(EBML_VIDEO, &pixels));
+        let mut tracks = ebml(EBML_TRACK_ENTRY, &audio);
+        tracks.extend(ebml(EBML_TRACK_ENTRY, &video));
+
+        let mut segment = ebml(0xec, &[0; 8]);
+        segment.extend(ebml(EBML_INFO, &info));
+        segment.extend(ebml(EBML_TRACKS, &tracks));
+        segment.extend(ebml(EBML_CLUSTER, &[0; 16]));
+
+        let mut data = ebml(EBML_HEADER, &ebml(0x4282, b"webm"));
+        data.extend_from_slice(&EBML_SEGMENT.to_be_bytes());
+        match segment_len {
+            Some(len) => {
+                data.push(0x01);
+                data.extend_from_slice(&len.to_be_bytes()[1..]);
+            }
+            // 8 字节全为 1：未知长度
+            None => data.extend_from_slice(&[0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+        }
+        data.extend(segment);
+        data
+    }
+
+    #[test]
+    fn matroska_reads_info_and_video_track() {
+        let data = matroska(None);
+        let segment_len =
+            (data.len() - ebml(EBML_HEADER, &ebml(0x4282, b"webm")).len() - 12) as u64;
+        for data in [data, matroska(Some(segment_len))] {
+            let metadata = probe_matroska(&mut Cursor::new(data)).unwrap().unwrap();
+            assert_eq!((metadata.width, metadata.height), (1280, 720));
+            assert_eq!(metadata.duration_ms, 61_500);
+            assert_eq!(metadata.codec.as_deref(), Some("vp9"));
+            assert_eq!(metadata.created_at, Some(1_704_067_200));
+        }
+    }
+
+    #[test]
+    fn matroska_truncated_at_every_length() {
+        let data = matroska(None);
+        let tracks_end = data
+            .windows(4)
+            .position(|w| w == [0x1f, 0x43, 0xb6, 0x75])
+            .unwrap();
+        for len in 0..data.len() {
+            let result = probe_matroska(&mut Cursor::new(&data[..len]));
+            // Tracks 不完整时读取失败或缺少视频轨道
+            if len < tracks_end {
+                assert!(
+                    !matches!(&result, Ok(Some(m)) if m.codec.is_some()),
+                    "{}",
+                    len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn matroska_rejects_corrupt_headers() {
+        // 不是 EBML 头
+        let data = ebml(EBML_SEGMENT, &[0; 8]);
+        assert!(probe_matroska(&mut Cursor::new(data)).unwrap().is_none());
+        // 全零字节不是合法的变长整数
+        assert!(
+            probe_matroska(&mut Cursor::new([0u8; 16]))
+                .unwrap()
+                .is_none()
+        );
+        // 元素 ID 超过 4 字节
+        let data = [0x08, 0x00, 0x00, 0x00, 0x00, 0x81, 0x00];
+        assert!(probe_matroska(&mut Cursor::new(data)).unwrap().is_none());
+    }
+
+    fn riff_chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = id.to_vec();
+        data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        data.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            data.push(0);
+        }
+        data
+    }
+
+    fn riff_list(list_type: &[u8; 4], chunks: &[u8]) -> Vec<u8> {
+        let mut body = list_type.to_vec();
+        body.extend_from_slice(chunks);
+        riff_chunk(b"LIST", &body)
+    }
+
+    fn avi() -> Vec<u8> {
+        let mut avih = Vec::new();
+        for value in [40_000u32, 0, 0, 0, 250, 0, 0, 0, 640, 480, 0, 0, 0, 0] {
+            avih.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut strh = b"vids".to_vec();
+        strh.resize(56, 0);
+        let mut strf = vec![0u8; 16];
+        strf.extend_from_slice(b"XVID");
+        strf.resize(40, 0);
+        let mut strl = riff_chunk(b"strh", &strh);
+        strl.extend(riff_chunk(b"strf", &strf));
+
+        // 奇数长度的块之后有填充字节
+        let mut hdrl = riff_chunk(b"avih", &avih);
+        hdrl.extend(riff_chunk(b"JUNK", &[0; 3]));
+        hdrl.extend(riff_list(b"strl", &strl));
+
+        let mut body = b"AVI ".to_vec();
+        body.extend(riff_list(b"hdrl", &hdrl));
+        riff_chunk(b"RIFF", &body)
+    }
+
+    #[test]
+    fn avi_reads_avih_and_video_stream() {
+        let metadata = probe_avi(&mut Cursor::new(avi())).unwrap().unwrap();
+        assert_eq!((metadata.width, metadata.height), (640, 480));
+        assert_eq!(metadata.duration_ms, 10_000);
+        assert_eq!(metadata.codec.as_deref(), Some("mpeg4"));
+        assert_eq!(metadata.created_at, None);
+    }
+
+    #[test]
+    fn avi_truncated_at_every_length() {
+        let data = avi();
+        for len in 0..data.len() {
+            let result = probe_avi(&mut Cursor::new(&data[..len]));
+            // hdrl 不完整时不返回元数据
+            assert!(!matches!(result, Ok(Some(_))), "{}", len);
+        }
+    }
+
+    #[test]
+    fn avi_rejects_corrupt_hdrl_length() {
+        let mut data = avi();
+        data[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(probe_avi(&mut Cursor::new(&data)).unwrap().is_none());
+        data[16..20].copy_from_slice(&2u32.to_le_bytes());
+        assert!(probe_avi(&mut Cursor::new(&data)).unwrap().is_none());
+    }
+
+    #[test]
+    fn probe_video_dispatches_on_magic() {
+        let dir = tempfile::tempdir().unwrap();
+        let cases: [(&str, Vec<u8>, Option<&str>); 4] = [
+            (
+                "clip.mp4",
+                mp4(mvhd_v0(1, 600, 600), video_trak(2, 2, false, b"avc1")),
+                Some("h264"),
+            ),
+            ("clip.webm", matroska(None), Some("vp9")),
+            ("clip.avi", avi(), Some("mpeg4")),
+            ("clip.bin", b"not a video container".to_vec(), None),
+        ];
+        for (name, data, codec) in cases {
+            let path = dir.path().join(name);
+            std::fs::write(&path, data).unwrap();
+            let metadata = probe_video(&path).unwrap();
+            assert_eq!(metadata.and_then(|m| m.codec).as_deref(), codec, "{}", name);
+        }
+    }
+
+    #[test]
+    fn normalizes_codec_ids() {
+        assert_eq!(normalize_codec("avc1"), "h264");
+        assert_eq!(normalize_codec("V_MPEGH/ISO/HEVC"), "hevc");
+        assert_eq!(normalize_codec("DIVX"), "mpeg4");
+        assert_eq!(normalize_codec("apcn"), "prores");
+        assert_eq!(normalize_codec(" tscc "), "tscc");
+    }
+}
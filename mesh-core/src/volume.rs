@@ -0,0 +1,210 @@
+use std::{
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// 相册目录中记录卷标识的文件，移动硬盘换了挂载点之后靠它找回原来的相册
+pub const VOLUME_MARKER_FILE: &str = ".mesh-volume";
+
+/// 读取相册目录中的卷标识，文件不存在或为空时返回 `None`
+pub fn read_volume_id(root: &Path) -> Option<String> {
+    let content = fs::read_to_string(root.join(VOLUME_MARKER_FILE)).ok()?;
+    let id = content.trim();
+    (!id.is_empty()).then(|| id.to_owned())
+}
+
+/// 读取相册目录中的卷标识，不存在时生成一个新的并写入
+pub fn ensure_volume_id(root: &Path) -> io::Result<String> {
+    match read_volume_id(root) {
+        Some(id) => Ok(id),
+        None => new_volume_id(root),
+    }
+}
+
+/// 生成新的卷标识并覆盖相册目录中原有的标识
+///
+/// 复制的相册或克隆的磁盘会带着与原目录相同的标识，需要重新生成。
+pub fn new_volume_id(root: &Path) -> io::Result<String> {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(root.as_os_str().as_encoded_bytes());
+    hasher.update(&nanos.to_le_bytes());
+    hasher.update(&std::process::id().to_le_bytes());
+    let id = hasher.finalize().to_hex()[..32].to_owned();
+
+    fs::write(root.join(VOLUME_MARKER_FILE), format!("{}\n", id))?;
+    Ok(id)
+}
+
+/// 在常见的挂载位置中查找带有 `volume_id` 标识的相册目录
+///
+/// 卷挂载到新位置后，相册目录相对卷根目录的路径不变，而卷根目录本身的位置未知，
+/// 因此依次尝试挂载点本身以及挂载点下 `old_root` 的每一段后缀。
+pub fn find_volume(volume_id: &str, old_root: &Path) -> Option<PathBuf> {
+    let parents = mount_parents(std::env::var_os("USER").as_deref());
+    find_volume_in(mount_points(&parents), volume_id, old_root)
+}
+
+/// 在 `mounts` 中查找，见 [`find_volume`]
+fn find_volume_in(mounts: Vec<PathBuf>, volume_id: &str, old_root: &Path) -> Option<PathBuf> {
+    let suffixes: Vec<PathBuf> = (0..old_root.components().count())
+        .map(|skip| old_root.components().skip(skip).collect::<PathBuf>())
+        .filter(|suffix| !suffix.has_root())
+        .collect();
+
+    mounts
+        .into_iter()
+        .flat_map(|mount| {
+            let nested: Vec<PathBuf> = suffixes.iter().map(|suffix| mount.join(suffix)).collect();
+            // 相册目录也可能就是卷的根目录
+            std::iter::once(mount).chain(nested)
+        })
+        .filter(|candidate| candidate != old_root)
+        .find(|candidate| read_volume_id(candidate).as_deref() == Some(volume_id))
+}
+
+/// 可移动设备与网络驱动器通常挂载在这些目录之下
+fn mount_parents(user: Option<&OsStr>) -> Vec<PathBuf> {
+    let mut parents = vec![PathBuf::from("/media"), PathBuf::from("/mnt")];
+    if let Some(user) = user {
+        parents.push(Path::new("/media").join(user));
+        parents.push(Path::new("/run/media").join(user));
+    }
+    parents.push(PathBuf::from("/Volumes"));
+    parents
+}
+
+/// `parents` 下的每个目录，Windows 上还包括所有存在的盘符
+fn mount_points(parents: &[PathBuf]) -> Vec<PathBuf> {
+    let mut mounts: Vec<PathBuf> = parents
+        .iter()
+        .filter_map(|parent| fs::read_dir(parent).ok())
+        .flat_map(|entries| entries.filter_map(|entry| entry.ok()))
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+
+    if cfg!(windows) {
+        mounts.extend(
+            (b'A'..=b'Z')
+                .map(|letter| PathBuf::from(format!("{}:\\", letter as char)))
+                .filter(|drive| drive.is_dir()),
+        );
+    }
+    mounts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ensure_volume_id_writes_marker_once() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(read_volume_id(dir.path()), None);
+
+        let id = ensure_volume_id(dir.path()).unwrap();
+        assert_eq!(id.len(), 32);
+        assert_eq!(read_volume_id(dir.path()).as_deref(), Some(id.as_str()));
+        assert_eq!(ensure_volume_id(dir.path()).unwrap(), id);
+
+        // 重新生成时覆盖原有的标识
+        let new_id = new_volume_id(dir.path()).unwrap();
+        assert_ne!(new_id, id);
+        assert_eq!(ensure_volume_id(dir.path()).unwrap(), new_id);
+    }
+
+    #[test]
+    fn empty_or_unreadable_markers_have_no_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join(VOLUME_MARKER_FILE);
+
+        fs::write(&marker, " \n").unwrap();
+        assert_eq!(read_volume_id(dir.path()), None);
+        let id = ensure_volume_id(dir.path()).unwrap();
+        assert_eq!(fs::read_to_string(&marker).unwrap(), format!("{}\n", id));
+
+        // 标识文件无法读取时也无法写入新的标识
+        fs::remove_file(&marker).unwrap();
+        fs::create_dir(&marker).unwrap();
+        assert_eq!(read_volume_id(dir.path()), None);
+        assert!(ensure_volume_id(dir.path()).is_err());
+    }
+
+    #[test]
+    fn mount_points_lists_directories_under_parents() {
+        let dir = tempfile::tempdir().unwrap();
+        let media = dir.path().join("media");
+        fs::create_dir_all(media.join("disk1")).unwrap();
+        fs::create_dir_all(media.join("disk2")).unwrap();
+        fs::write(media.join("file"), "").unwrap();
+
+        let mut mounts = mount_points(&[media.clone(), dir.path().join("missing")]);
+        mounts.sort();
+        assert_eq!(mounts, [media.join("disk1"), media.join("disk2")]);
+
+        assert_eq!(
+            mount_parents(Some(OsStr::new("alice"))),
+            [
+                PathBuf::from("/media"),
+                PathBuf::from("/mnt"),
+                PathBuf::from("/media/alice"),
+                PathBuf::from("/run/media/alice"),
+                PathBuf::from("/Volumes"),
+            ]
+        );
+        assert_eq!(mount_parents(None).len(), 3);
+    }
+
+    #[test]
+    fn find_volume_searches_mounts_and_suffixes() {
+        let dir = tempfile::tempdir().unwrap();
+        let media = dir.path().join("media");
+        let nested = media.join("disk2/photos/2024");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(media.join("disk1")).unwrap();
+        let nested_id = ensure_volume_id(&nested).unwrap();
+        let root_id = ensure_volume_id(&media.join("disk1")).unwrap();
+        let mounts = || mount_points(std::slice::from_ref(&media));
+
+        // 卷换了挂载点，相册目录相对卷根目录的路径不变
+        assert_eq!(
+            find_volume_in(mounts(), &nested_id, Path::new("/mnt/old/photos/2024")),
+            Some(nested.clone())
+        );
+        // 相册目录就是卷的根目录
+        assert_eq!(
+            find_volume_in(mounts(), &root_id, Path::new("/Volumes/Old")),
+            Some(media.join("disk1"))
+        );
+        // 原来的位置不算找到
+        assert_eq!(find_volume_in(mounts(), &nested_id, &nested), None);
+        assert_eq!(
+            find_volume_in(mounts(), "missing", Path::new("/mnt/old/photos/2024")),
+            None
+        );
+    }
+
+    #[test]
+    fn find_volume_skips_unreadable_markers() {
+        let dir = tempfile::tempdir().unwrap();
+        let media = dir.path().join("media");
+        fs::create_dir_all(media.join("disk1").join(VOLUME_MARKER_FILE)).unwrap();
+        fs::create_dir_all(media.join("disk2")).unwrap();
+        let id = ensure_volume_id(&media.join("disk2")).unwrap();
+
+        assert_eq!(
+            find_volume_in(
+                mount_points(std::slice::from_ref(&media)),
+                &id,
+                Path::new("/mnt/old")
+            ),
+            Some(media.join("disk2"))
+        );
+    }
+}
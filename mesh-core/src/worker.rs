@@ -0,0 +1,238 @@
+//! 在独立的子进程中解码图片
+//!
+//! 损坏或恶意构造的文件可能让解码器耗尽内存、崩溃或陷入死循环，
+//! 在子进程中解码时最多只损失这一个文件，主进程不受影响。
+
+use std::{
+    ffi::OsStr,
+    io::{self, Read, Write},
+    path::Path,
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use crate::thumbnailer::{RenderedThumbnail, ThumbnailError, render_thumbnail};
+use crate::{MediaFormat, ScanErrorKind};
+
+/// 以解码子进程方式启动时的第一个命令行参数
+const WORKER_ARG: &str = "--mesh-decode-worker";
+/// 子进程的地址空间上限，在解码内存上限之外为程序本身留出余量
+#[cfg(unix)]
+const WORKER_ADDRESS_SPACE: u64 = crate::decode::MAX_DECODE_ALLOC * 3;
+
+const STATUS_OK: u8 = 0;
+const STATUS_ERR: u8 = 1;
+
+/// 如果当前进程是解码子进程，完成解码后直接退出，否则立即返回
+///
+/// 启用子进程解码的程序需要在 `main` 的最开始、解析命令行参数之前调用。
+pub fn run_worker_if_requested() {
+    let mut args = std::env::args_os().skip(1);
+    if args.next().as_deref() != Some(OsStr::new(WORKER_ARG)) {
+        return;
+    }
+    let (Some(mime_type), Some(path)) = (args.next(), args.next()) else {
+        std::process::exit(2);
+    };
+    let Some(format) = mime_type.to_str().and_then(MediaFormat::from_mime_type) else {
+        std::process::exit(2);
+    };
+
+    restrict_resources();
+    let result = std::panic::catch_unwind(|| render_thumbnail(Path::new(&path), format))
+        .unwrap_or_else(|_| Err(ThumbnailError::new(ScanErrorKind::Decode, "解码器崩溃")));
+
+    let mut stdout = io::stdout().lock();
+    let written = stdout
+        .write_all(&encode_response(&result))
+        .and_then(|_| stdout.flush());
+    std::process::exit(if written.is_ok() { 0 } else { 1 });
+}
+
+/// 在子进程中解码原图并编码缩略图，超过 `timeout` 时终止子进程
+///
+/// 子进程崩溃或超时都记为解码失败。
+pub(crate) fn render_in_worker(
+    src_path: &Path,
+    format: MediaFormat,
+    timeout: Duration,
+) -> Result<RenderedThumbnail, ThumbnailError> {
+    let exe = std::env::current_exe().map_err(ThumbnailError::io)?;
+    let mut child = Command::new(exe)
+        .arg(WORKER_ARG)
+        .arg(format.info().mime_type)
+        .arg(src_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| ThumbnailError::io(format!("启动解码进程失败: {}", e)))?;
+
+    // 在另一个线程读取输出，子进程退出后管道关闭，读取随之结束
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = tx.send(stdout.read_to_end(&mut output).map(|_| output));
+    });
+
+    let output = match rx.recv_timeout(timeout) {
+        Ok(output) => output.map_err(ThumbnailError::io)?,
+        Err(_) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(ThumbnailError::new(
+                ScanErrorKind::Decode,
+                format!("解码超时（{} 秒）", timeout.as_secs()),
+            ));
+        }
+    };
+
+    let status = child.wait().map_err(ThumbnailError::io)?;
+    match decode_response(&output) {
+        Some(result) if status.success() => result,
+        _ => Err(ThumbnailError::new(
+            ScanErrorKind::Decode,
+            format!("解码进程异常退出（{}）", status),
+        )),
+    }
+}
+
+/// 限制子进程的地址空间，超出时分配失败并退出，而不是耗尽系统内存
+#[cfg(unix)]
+fn restrict_resources() {
+    let limit = libc::rlimit {
+        rlim_cur: WORKER_ADDRESS_SPACE as libc::rlim_t,
+        rlim_max: WORKER_ADDRESS_SPACE as libc::rlim_t,
+    };
+    // SAFETY: `limit` 在调用期间有效
+    unsafe {
+        libc::setrlimit(libc::RLIMIT_AS, &limit);
+    }
+}
+
+#[cfg(not(unix))]
+fn restrict_resources() {}
+
+/// 子进程的输出：状态字节，成功时依次为原图宽高、拍摄时间（没有时为 `i64::MIN`）、
+/// 色彩空间名称的长度与内容、缩略图数据，失败时为 `kind\nmessage`
+fn encode_response(result: &Result<RenderedThumbnail, ThumbnailError>) -> Vec<u8> {
+    let mut output = Vec::new();
+    match result {
+        Ok(rendered) => {
+            let color_space = rendered.color_space.as_deref().unwrap_or_default();
+            output.push(STATUS_OK);
+            output.extend_from_slice(&rendered.width.to_le_bytes());
+            output.extend_from_slice(&rendered.height.to_le_bytes());
+            let captured_at = rendered.captured_at.unwrap_or(i64::MIN);
+            output.extend_from_slice(&captured_at.to_le_bytes());
+            output.extend_from_slice(&(color_space.len() as u32).to_le_bytes());
+            output.extend_from_slice(color_space.as_bytes());
+            output.extend_from_slice(&rendered.data);
+        }
+        Err(error) => {
+            output.push(STATUS_ERR);
+            output.extend_from_slice(format!("{}\n{}", error.kind, error.message).as_bytes());
+        }
+    }
+    output
+}
+
+fn decode_response(output: &[u8]) -> Option<Result<RenderedThumbnail, ThumbnailError>> {
+    let (&status, body) = output.split_first()?;
+    match status {
+        STATUS_OK => {
+            let u32_at = |offset: usize| {
+                Some(u32::from_le_bytes(
+                    body.get(offset..offset + 4)?.try_into().ok()?,
+                ))
+            };
+            let (width, height) = (u32_at(0)?, u32_at(4)?);
+            let captured_at = i64::from_le_bytes(body.get(8..16)?.try_into().ok()?);
+            let len = u32_at(16)? as usize;
+            let color_space = std::str::from_utf8(body.get(20..20usize.checked_add(len)?)?).ok()?;
+            Some(Ok(RenderedThumbnail {
+                width,
+                height,
+                color_space: (!color_space.is_empty()).then(|| color_space.to_owned()),
+                captured_at: (captured_at != i64::MIN).then_some(captured_at),
+                data: body[20 + len..].to_vec(),
+            }))
+        }
+        STATUS_ERR => {
+            let message = String::from_utf8_lossy(body);
+            let (kind, message) = message.split_once('\n')?;
+            Some(Err(ThumbnailError::new(
+                ScanErrorKind::parse(kind),
+                message,
+            )))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(rendered: RenderedThumbnail) -> RenderedThumbnail {
+        match decode_response(&encode_response(&Ok(rendered))) {
+            Some(Ok(rendered)) => rendered,
+            _ => panic!("response did not round-trip"),
+        }
+    }
+
+    #[test]
+    fn response_round_trips() {
+        let rendered = round_trip(RenderedThumbnail {
+            width: 6000,
+            height: 4000,
+            color_space: Some("Display P3".to_owned()),
+            captured_at: Some(1_714_979_289),
+            data: vec![0xff, 0xd8, 0xff],
+        });
+        assert_eq!((rendered.width, rendered.height), (6000, 4000));
+        assert_eq!(rendered.color_space.as_deref(), Some("Display P3"));
+        assert_eq!(rendered.captured_at, Some(1_714_979_289));
+        assert_eq!(rendered.data, [0xff, 0xd8, 0xff]);
+
+        let rendered = round_trip(RenderedThumbnail {
+            width: 1,
+            height: 1,
+            color_space: None,
+            captured_at: None,
+            data: Vec::new(),
+        });
+        assert_eq!(rendered.color_space, None);
+        assert_eq!(rendered.captured_at, None);
+
+        let error = ThumbnailError::new(ScanErrorKind::Decode, "truncated");
+        match decode_response(&encode_response(&Err(error))) {
+            Some(Err(error)) => {
+                assert_eq!(error.kind, ScanErrorKind::Decode);
+                assert_eq!(error.message, "truncated");
+            }
+            _ => panic!("error did not round-trip"),
+        }
+    }
+
+    #[test]
+    fn truncated_response_is_rejected() {
+        let output = encode_response(&Ok(RenderedThumbnail {
+            width: 1,
+            height: 1,
+            color_space: Some("sRGB".to_owned()),
+            captured_at: None,
+            data: Vec::new(),
+        }));
+        for len in 0..output.len() {
+            assert!(
+                !matches!(decode_response(&output[..len]), Some(Ok(_))),
+                "{}",
+                len
+            );
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! 非 UTF-8 文件名在扫描、照片库与全文搜索中原样保留
+
+#![cfg(target_os = "linux")]
+
+use std::{
+    ffi::OsStr,
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use image::{RgbImage, codecs::jpeg::JpegEncoder};
+use mesh_core::{
+    CancellationToken, MeshCache, MeshConfig, MeshDirs, ScanErrorKind, ScanErrorRecord,
+    ScanFilter, ThumbnailBackend, ThumbnailJob, collect_files,
+};
+
+/// 测试结束时删除的临时目录
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("mesh-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path.canonicalize().unwrap())
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn write_jpeg(path: &Path) {
+    let image = RgbImage::from_fn(64, 48, |x, y| image::Rgb([x as u8 * 4, y as u8 * 5, 128]));
+    let file = fs::File::create(path).unwrap();
+    JpegEncoder::new(file).encode_image(&image).unwrap();
+}
+
+/// 以 `name` 的原始字节为文件名
+fn join_bytes(dir: &Path, name: &[u8]) -> PathBuf {
+    dir.join(OsStr::from_bytes(name))
+}
+
+/// 只有最后一个字节不同、替换为 U+FFFD 后完全相同的两个文件名
+const LATIN1_NAME: &[u8] = b"caf\xe9 trip.jpg";
+const OTHER_NAME: &[u8] = b"caf\xe8 trip.jpg";
+
+/// 在新的数据目录中添加相册并扫描，返回相册中的文件与照片库
+fn scan_album(home: &TempDir, names: &[&[u8]]) -> (Vec<PathBuf>, MeshConfig, MeshCache) {
+    let album = home.0.join("album");
+    fs::create_dir_all(&album).unwrap();
+    let files: Vec<PathBuf> = names
+        .iter()
+        .map(|name| {
+            let path = join_bytes(&album, name);
+            write_jpeg(&path);
+            path
+        })
+        .collect();
+
+    let dirs = MeshDirs::new(home.0.join("data"));
+    let mut config = MeshConfig::new(&dirs);
+    config.add_album_dir(&album).unwrap();
+    let cache = MeshCache::new(&dirs, ThumbnailBackend::Directory);
+
+    let collected = collect_files(&ScanFilter::new(&config), vec![album]);
+    let summary = ThumbnailJob::new(collected)
+        .threads(1)
+        .run(&cache, &CancellationToken::new())
+        .unwrap();
+    assert_eq!(summary.failed, 0);
+    (files, config, cache)
+}
+
+#[test]
+fn scanner_keeps_raw_file_names() {
+    let home = TempDir::new("scan-non-utf8");
+    let album = home.0.join("album");
+    fs::create_dir_all(&album).unwrap();
+    let path = join_bytes(&album, LATIN1_NAME);
+    write_jpeg(&path);
+
+    let dirs = MeshDirs::new(home.0.join("data"));
+    let mut config = MeshConfig::new(&dirs);
+    config.add_album_dir(&album).unwrap();
+
+    let files = collect_files(&ScanFilter::new(&config), vec![album]);
+    assert_eq!(files, vec![path]);
+}
+
+#[test]
+fn database_round_trips_non_utf8_paths() {
+    let home = TempDir::new("db-non-utf8");
+    let (files, _config, cache) = scan_album(&home, &[LATIN1_NAME, OTHER_NAME]);
+
+    // 两个文件名的显示形式相同，但仍是两条独立的记录
+    assert_eq!(cache.database().photo_count().unwrap(), 2);
+    for path in &files {
+        assert!(cache.database().contains_photo(path).unwrap());
+    }
+    let lossy = PathBuf::from(files[0].to_string_lossy().into_owned());
+    assert!(!cache.database().contains_photo(&lossy).unwrap());
+
+    // 原图仍然存在，不会被当作已删除的照片清理
+    let report = cache.gc().unwrap();
+    assert_eq!(report.removed, 0);
+    assert_eq!(cache.database().photo_count().unwrap(), 2);
+}
+
+#[test]
+fn full_text_search_returns_exact_paths() {
+    let home = TempDir::new("fts-non-utf8");
+    let (files, _config, cache) = scan_album(&home, &[LATIN1_NAME, b"beach.jpg"]);
+
+    let results = cache.database().search_filenames("trip").unwrap();
+    assert_eq!(results.len(), 1);
+    let (path, display) = &results[0];
+    assert_eq!(path.as_os_str().as_bytes(), files[0].as_os_str().as_bytes());
+    assert_eq!(display, "caf\u{FFFD} trip.jpg");
+}
+
+#[test]
+fn relocation_keeps_non_utf8_paths() {
+    let home = TempDir::new("relocate-non-utf8");
+    let (files, _config, cache) = scan_album(&home, &[LATIN1_NAME]);
+
+    let old_root = home.0.join("album");
+    let new_root = join_bytes(&home.0, b"moved-\xff");
+    fs::rename(&old_root, &new_root).unwrap();
+    let report = cache.relocate(&old_root, &new_root).unwrap();
+    assert_eq!(report.photos, 1);
+    assert_eq!(report.thumbnails, 1);
+
+    let moved = new_root.join(files[0].file_name().unwrap());
+    assert!(cache.database().contains_photo(&moved).unwrap());
+    assert_eq!(cache.gc().unwrap().removed, 0);
+}
+
+#[test]
+fn scan_errors_round_trip_non_utf8_paths() {
+    let home = TempDir::new("errors-non-utf8");
+    let (_files, _config, cache) = scan_album(&home, &[]);
+
+    let path = join_bytes(&home.0.join("album"), b"broken-\x80.jpg");
+    let record = ScanErrorRecord {
+        path: path.clone(),
+        kind: ScanErrorKind::Decode,
+        message: "truncated".to_owned(),
+        retry_count: 0,
+        source_size: 10,
+        source_modified: 20,
+        last_attempt: 30,
+    };
+    cache.database().record_scan_error(&record).unwrap();
+
+    assert!(cache.database().has_scan_error(&path, 10, 20).unwrap());
+    let errors = cache.database().scan_errors().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, path);
+
+    cache.database().clear_scan_error(&path).unwrap();
+    assert!(cache.database().scan_errors().unwrap().is_empty());
+}
@@ -0,0 +1,67 @@
+use futures::{StreamExt as _, channel::mpsc};
+use gpui::{App, SharedString};
+use gpui_component::{ActiveTheme as _, Theme, ThemeRegistry};
+use mesh_core::{ConfigChange, ConfigValue, DirChange};
+
+use crate::{MeshState, thumbnails};
+
+/// 订阅配置变化，转发到主线程后更新主题并索引或清理受影响的目录
+///
+/// 订阅的是当前照片库的配置，切换照片库后需要重新调用。
+pub fn init(cx: &mut App) {
+    let (tx, mut rx) = mpsc::unbounded::<ConfigChange>();
+    MeshState::global(cx)
+        .config
+        .subscribe(move |change| {
+            let _ = tx.unbounded_send(change.clone());
+        })
+        .detach();
+
+    cx.spawn(async move |cx| {
+        while let Some(change) = rx.next().await {
+            if cx.update(|cx| apply(change, cx)).is_err() {
+                break;
+            }
+        }
+    })
+    .detach();
+}
+
+fn apply(change: ConfigChange, cx: &mut App) {
+    match change {
+        ConfigChange::Value(ConfigValue::Theme(name)) => apply_theme(name.into(), cx),
+        ConfigChange::Dirs(change) => apply_dir_change(change, cx),
+        _ => {}
+    }
+}
+
+/// 主题由其他地方修改时（例如手动编辑配置文件）切换到新主题
+pub(crate) fn apply_theme(name: SharedString, cx: &mut App) {
+    if *cx.theme().theme_name() == name {
+        return;
+    }
+    if let Some(theme) = ThemeRegistry::global(cx).themes().get(&name).cloned() {
+        Theme::global_mut(cx).apply_config(&theme);
+        cx.refresh_windows();
+    }
+}
+
+fn apply_dir_change(change: DirChange, cx: &mut App) {
+    if let Some(root) = change.prune_root() {
+        let cache = MeshState::global(cx).cache.clone();
+        let root = root.to_path_buf();
+        cx.background_executor()
+            .spawn(async move {
+                if let Err(e) = cache.prune_dir(&root) {
+                    log::error!("Failed to prune {:?}: {:?}", root, e);
+                }
+            })
+            .detach();
+    }
+
+    if change.index_root().is_some()
+        && let Some(window) = cx.active_window()
+    {
+        let _ = window.update(cx, |_, window, cx| thumbnails::spawn(window, cx));
+    }
+}
@@ -1,13 +1,18 @@
+use std::sync::Arc;
+
 use gpui::{
     AnyView, App, AppContext, Bounds, Context, Entity, Global, IntoElement, KeyBinding,
     ParentElement, Pixels, Render, SharedString, Size, Styled, Window, WindowBounds, WindowKind,
     WindowOptions, actions, div, px, size,
 };
-use gpui_component::{Root, TitleBar, v_flex};
-use mesh_core::{MeshCache, MeshConfig};
+use gpui_component::{Root, TitleBar, WindowExt as _, notification::Notification, v_flex};
+use mesh_core::{CancellationToken, ConfigStore, LibraryRegistry, MeshCache, MeshConfig, MeshDirs};
 
 mod app_menus;
+mod config;
+mod libraries;
 mod themes;
+mod thumbnails;
 mod title_bar;
 
 pub use crate::title_bar::MeshTitleBar;
@@ -15,19 +20,42 @@ pub use crate::title_bar::MeshTitleBar;
 actions!(mesh, [About, Open, Quit, CloseWindow, ToggleSearch,]);
 
 pub struct MeshState {
-    pub config: MeshConfig,
-    pub cache: MeshCache,
+    pub libraries: LibraryRegistry,
+    /// 当前照片库的配置
+    pub config: ConfigStore,
+    pub cache: Arc<MeshCache>,
+    /// 正在后台运行的缩略图任务
+    pub thumbnail_job: Option<CancellationToken>,
 }
 
 impl MeshState {
     fn init(cx: &mut App) {
+        let dirs = MeshDirs::from_env();
+        let libraries = LibraryRegistry::load(dirs.clone()).unwrap_or_else(|e| {
+            log::error!("Failed to load libraries: {}", e);
+            LibraryRegistry::new(dirs)
+        });
+        let (config, cache) = Self::open_library(&libraries.current_dirs());
         let state = Self {
-            config: MeshConfig::init(),
-            cache: MeshCache::new(),
+            libraries,
+            config,
+            cache,
+            thumbnail_job: None,
         };
         cx.set_global::<MeshState>(state);
     }
 
+    /// 打开照片库的配置与缓存
+    fn open_library(dirs: &MeshDirs) -> (ConfigStore, Arc<MeshCache>) {
+        let config = ConfigStore::new(MeshConfig::init(dirs));
+        // 与 `ThemeRegistry::watch_dir` 一样，手动编辑配置文件后立即生效
+        if let Err(e) = config.watch() {
+            log::error!("Failed to watch config file: {}", e);
+        }
+        let cache = Arc::new(MeshCache::new(dirs, config.read().thumbnail_store()));
+        (config, cache)
+    }
+
     pub fn global(cx: &App) -> &Self {
         cx.global::<Self>()
     }
@@ -78,9 +106,10 @@ pub fn create_new_window<F, E>(
             .expect("failed to open window");
 
         window
-            .update(cx, |_, window, _| {
+            .update(cx, |_, window, cx| {
                 window.activate_window();
                 window.set_window_title(&title);
+                scan_library(window, cx);
             })
             .expect("failed to update window");
 
@@ -89,6 +118,19 @@ pub fn create_new_window<F, E>(
     .detach();
 }
 
+/// 为当前照片库生成缩略图，配置文件无法解析时只提示错误，不按默认配置扫描
+pub(crate) fn scan_library(window: &mut Window, cx: &mut App) {
+    let load_error = MeshState::global(cx)
+        .config
+        .read()
+        .load_error()
+        .map(|e| format!("Failed to load config: {}", e));
+    match load_error {
+        Some(message) => window.push_notification(Notification::error(message), cx),
+        None => thumbnails::spawn(window, cx),
+    }
+}
+
 struct StoryRoot {
     title_bar: Entity<MeshTitleBar>,
     view: AnyView,
@@ -133,6 +175,8 @@ pub fn init(cx: &mut App) {
     gpui_component::init(cx);
     MeshState::init(cx);
     themes::init(cx);
+    config::init(cx);
+    libraries::init(cx);
     // stories::init(cx);
 
     // let http_client = std::sync::Arc::new(
@@ -149,6 +193,9 @@ pub fn init(cx: &mut App) {
     ]);
 
     cx.on_action(|_: &Quit, cx: &mut App| {
+        if let Some(token) = &MeshState::global(cx).thumbnail_job {
+            token.cancel();
+        }
         cx.quit();
     });
 
@@ -0,0 +1,52 @@
+use gpui::{Action, App, SharedString};
+use gpui_component::{WindowExt as _, notification::Notification};
+
+use crate::{MeshState, config, scan_library};
+
+pub fn init(cx: &mut App) {
+    cx.on_action(|switch: &SwitchLibrary, cx| switch_library(&switch.0, cx));
+}
+
+/// 切换到另一个照片库：停止正在运行的缩略图任务，打开新照片库的配置与缓存并扫描
+fn switch_library(name: &str, cx: &mut App) {
+    let state = MeshState::global_mut(cx);
+    if state.libraries.current() == name {
+        return;
+    }
+    let dirs = match state.libraries.set_current(name) {
+        Ok(dirs) => dirs,
+        Err(e) => {
+            log::error!("Failed to switch library: {}", e);
+            if let Some(window) = cx.active_window() {
+                let message = format!("Failed to switch library: {}", e);
+                let _ = window.update(cx, |_, window, cx| {
+                    window.push_notification(Notification::error(message), cx)
+                });
+            }
+            return;
+        }
+    };
+
+    if let Some(token) = state.thumbnail_job.take() {
+        token.cancel();
+    }
+    (state.config, state.cache) = MeshState::open_library(&dirs);
+
+    // 旧配置的订阅随旧的 `ConfigStore` 一起释放
+    config::init(cx);
+    let theme = MeshState::global(cx).config.read().theme().to_owned();
+    config::apply_theme(theme.into(), cx);
+    cx.refresh_windows();
+
+    if let Some(window) = cx.active_window() {
+        let message = format!("Switched to library {}", name);
+        let _ = window.update(cx, |_, window, cx| {
+            window.push_notification(Notification::info(message), cx);
+            scan_library(window, cx);
+        });
+    }
+}
+
+#[derive(Action, Clone, PartialEq)]
+#[action(namespace = libraries, no_json)]
+pub(crate) struct SwitchLibrary(pub(crate) SharedString);
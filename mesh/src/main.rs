@@ -101,6 +101,8 @@ impl Render for Mesh {
 }
 
 fn main() {
+    mesh_core::run_worker_if_requested();
+
     env_logger::init_from_env(env_logger::Env::new().filter("MESH_LOG"));
 
     let app = Application::new().with_assets(Assets);
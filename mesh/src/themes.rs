@@ -1,35 +1,32 @@
-use std::path::PathBuf;
-
 use gpui::{Action, App, SharedString};
 use gpui_component::{ActiveTheme, Theme, ThemeMode, ThemeRegistry};
-use mesh_core::MeshConfig;
+use mesh_core::ConfigValue;
 
 use crate::MeshState;
 
 pub fn init(cx: &mut App) {
-    let config = &cx.global::<MeshState>().config;
-    let theme_name = SharedString::from(config.current_theme());
-
-    if let Err(err) = ThemeRegistry::watch_dir(
-        PathBuf::from(MeshConfig::themes_dir_path()),
-        cx,
-        move |cx| {
-            if let Some(theme) = ThemeRegistry::global(cx).themes().get(&theme_name).cloned() {
-                Theme::global_mut(cx).apply_config(&theme);
-            }
-        },
-    ) {
+    let state = cx.global::<MeshState>();
+    let theme_name = SharedString::from(state.config.read().theme().to_owned());
+    // 主题为所有照片库共用
+    let themes_dir = state.libraries.base_dirs().themes_dir();
+
+    if let Err(err) = ThemeRegistry::watch_dir(themes_dir, cx, move |cx| {
+        if let Some(theme) = ThemeRegistry::global(cx).themes().get(&theme_name).cloned() {
+            Theme::global_mut(cx).apply_config(&theme);
+        }
+    }) {
         log::error!("Failed to watch themes directory: {}", err);
     }
 
     cx.refresh_windows();
 
     cx.observe_global::<Theme>(|cx| {
-        let theme = cx.theme().theme_name().clone();
+        let theme = cx.theme().theme_name().to_string();
         let config = &cx.global::<MeshState>().config;
 
-        config.change_theme(theme.into());
-        config.save();
+        if let Err(e) = config.set(ConfigValue::Theme(theme)) {
+            log::warn!("Failed to save theme: {}", e);
+        }
     })
     .detach();
 
@@ -0,0 +1,91 @@
+use gpui::{App, Window};
+use gpui_component::{WindowExt as _, notification::Notification};
+use mesh_core::{CancellationToken, RootChange, ScanFilter, ThumbnailJob, collect_files};
+
+use crate::MeshState;
+
+/// 在后台为所有相册目录生成缩略图，开始与结束时弹出通知
+///
+/// 扫描前先核对相册目录，未挂载的卷中的照片保留为离线，换了挂载点的卷同步更新配置。
+pub fn spawn(window: &mut Window, cx: &mut App) {
+    let state = MeshState::global(cx);
+    let config = state.config.clone();
+    let worker_timeout = config.read().decode_worker_timeout();
+    let cache = state.cache.clone();
+
+    let token = CancellationToken::new();
+    if let Some(previous) = MeshState::global_mut(cx)
+        .thumbnail_job
+        .replace(token.clone())
+    {
+        previous.cancel();
+    }
+
+    window.push_notification(Notification::info("Generating thumbnails..."), cx);
+
+    window
+        .spawn(cx, async move |cx| {
+            let (offline, result) = cx
+                .background_executor()
+                .spawn(async move {
+                    let album_dirs = config.read().album_dirs().clone();
+                    let changes = cache.reconcile_roots(&album_dirs).unwrap_or_else(|e| {
+                        log::error!("{:?}", e);
+                        Vec::new()
+                    });
+                    for change in &changes {
+                        if let RootChange::Moved { from, to } = change
+                            && let Err(e) = config.relocate_album_dir(from, to)
+                        {
+                            log::error!("Failed to relocate album {:?}: {}", from, e);
+                        }
+                    }
+                    let offline = changes
+                        .iter()
+                        .filter(|change| matches!(change, RootChange::Offline(_)))
+                        .count();
+
+                    let config = config.read();
+                    let filter = ScanFilter::new(&config);
+                    let album_dirs = config.album_dirs().clone();
+                    drop(config);
+                    let files = collect_files(&filter, album_dirs);
+                    let result = ThumbnailJob::new(files)
+                        .decode_in_worker(worker_timeout)
+                        .run(&cache, &token);
+                    (offline, result)
+                })
+                .await;
+
+            let _ = cx.update(|window, cx| {
+                if offline > 0 {
+                    window.push_notification(
+                        Notification::warning(format!(
+                            "{} album folders are offline, their photos are kept",
+                            offline
+                        )),
+                        cx,
+                    );
+                }
+                let notification = match result {
+                    Ok(summary) if summary.cancelled => {
+                        Notification::warning("Thumbnail generation cancelled")
+                    }
+                    Ok(summary) if summary.failed > 0 => Notification::warning(format!(
+                        "Generated {} thumbnails, {} files failed",
+                        summary.generated, summary.failed
+                    )),
+                    Ok(summary) => Notification::success(format!(
+                        "Generated {} thumbnails ({} up to date)",
+                        summary.generated, summary.skipped
+                    )),
+                    Err(e) => {
+                        log::error!("{:?}", e);
+                        Notification::error(format!("Failed to generate thumbnails: {}", e))
+                    }
+                };
+                window.push_notification(notification, cx);
+            });
+        })
+        .detach();
+}
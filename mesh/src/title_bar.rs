@@ -7,10 +7,10 @@ use gpui::{
 use gpui_component::{
     IconName, Sizable as _, Theme, TitleBar,
     button::{Button, ButtonVariants as _},
-    menu::AppMenuBar,
+    menu::{AppMenuBar, DropdownMenu as _},
 };
 
-use crate::{app_menus, themes::switch_theme_mode};
+use crate::{MeshState, app_menus, libraries::SwitchLibrary, themes::switch_theme_mode};
 
 pub struct MeshTitleBar {
     app_menu_bar: Entity<AppMenuBar>,
@@ -62,6 +62,7 @@ impl Render for MeshTitleBar {
                     .gap_2()
                     .on_mouse_down(MouseButton::Left, |_, _, cx| cx.stop_propagation())
                     .child((self.child.clone())(window, cx))
+                    .children(library_switcher(cx))
                     // .child(self.font_size_selector.clone())
                     .child(
                         Button::new("switch-theme-mode")
@@ -87,3 +88,33 @@ impl Render for MeshTitleBar {
             )
     }
 }
+
+/// 切换照片库的下拉菜单，只有一个照片库时不显示
+fn library_switcher(cx: &App) -> Option<impl IntoElement> {
+    let libraries = &MeshState::global(cx).libraries;
+    let names: Vec<SharedString> = libraries
+        .names()
+        .map(|name| SharedString::from(name.to_owned()))
+        .collect();
+    if names.len() < 2 {
+        return None;
+    }
+    let current = SharedString::from(libraries.current().to_owned());
+
+    Some(
+        Button::new("switch-library")
+            .label(current.clone())
+            .icon(IconName::ChevronDown)
+            .small()
+            .ghost()
+            .dropdown_menu(move |menu, _, _| {
+                names.iter().fold(menu, |menu, name| {
+                    menu.menu_with_check(
+                        name.clone(),
+                        *name == current,
+                        Box::new(SwitchLibrary(name.clone())),
+                    )
+                })
+            }),
+    )
+}